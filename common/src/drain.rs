@@ -0,0 +1,166 @@
+//! Draining ahead of a rolling restart: `POST /admin/drain` flips the node into a
+//! draining state that `GET /ready` reports as `503`, so a gateway polling readiness
+//! stops sending it new requests before it shuts down. Requests that still arrive
+//! are served as usual. `DELETE /admin/drain` puts the node back into rotation.
+//!
+//! Both admin calls need `Authorization: Bearer <NODE_ADMIN_TOKEN>`; without a
+//! configured token they are refused, so nobody can take a node out of rotation.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{
+    Router,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    routing::get,
+};
+use subtle::ConstantTimeEq;
+use tracing::{info, warn};
+
+struct DrainState {
+    draining: AtomicBool,
+    admin_token: Option<String>,
+}
+
+impl DrainState {
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+        let Some(expected) = &self.admin_token else {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "admin routes are disabled; set NODE_ADMIN_TOKEN",
+            ));
+        };
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if bool::from(presented.as_bytes().ct_eq(expected.as_bytes())) {
+            Ok(())
+        } else {
+            warn!("Rejected admin request with a missing or wrong token");
+            Err((StatusCode::UNAUTHORIZED, "invalid admin token"))
+        }
+    }
+}
+
+async fn ready_handler(State(state): State<Arc<DrainState>>) -> (StatusCode, &'static str) {
+    if state.draining.load(Ordering::Relaxed) {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "ready")
+    }
+}
+
+async fn drain_handler(
+    State(state): State<Arc<DrainState>>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    state.authorize(&headers)?;
+    if !state.draining.swap(true, Ordering::Relaxed) {
+        info!("Draining: /ready now reports 503");
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn undrain_handler(
+    State(state): State<Arc<DrainState>>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    state.authorize(&headers)?;
+    if state.draining.swap(false, Ordering::Relaxed) {
+        info!("Drain cancelled: /ready reports 200 again");
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /ready` and `POST`/`DELETE /admin/drain`, sharing one draining flag. The
+/// admin routes need `admin_token` as a bearer token and are refused when it is `None`.
+pub fn routes(admin_token: Option<String>) -> Router {
+    let state = DrainState {
+        draining: AtomicBool::new(false),
+        admin_token: admin_token.filter(|token| !token.is_empty()),
+    };
+    Router::new()
+        .route("/ready", get(ready_handler))
+        .route(
+            "/admin/drain",
+            axum::routing::post(drain_handler).delete(undrain_handler),
+        )
+        .with_state(Arc::new(state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use tower::ServiceExt;
+
+    fn ready() -> Request<Body> {
+        Request::get("/ready").body(Body::empty()).unwrap()
+    }
+
+    fn admin(method: Method, token: Option<&str>) -> Request<Body> {
+        let mut req = Request::builder().method(method).uri("/admin/drain");
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_drain_flips_readiness_and_undrain_restores_it() {
+        let app = routes(Some("secret".into()));
+
+        let resp = app.clone().oneshot(ready()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = app
+            .clone()
+            .oneshot(admin(Method::POST, Some("secret")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = app.clone().oneshot(ready()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "draining");
+
+        let resp = app
+            .clone()
+            .oneshot(admin(Method::DELETE, Some("secret")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let resp = app.oneshot(ready()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_drain_requires_admin_token() {
+        let app = routes(Some("secret".into()));
+        for token in [None, Some("wrong")] {
+            let resp = app
+                .clone()
+                .oneshot(admin(Method::POST, token))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        // Without a configured token the admin routes are off entirely
+        let resp = routes(None)
+            .oneshot(admin(Method::POST, Some("")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = app.oneshot(ready()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
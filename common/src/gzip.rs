@@ -0,0 +1,170 @@
+//! Gzip request bodies: the gateway compresses chat bodies for backends listed in
+//! `GATEWAY_GZIP_BACKENDS`, and with the `axum` feature the nodes inflate bodies sent
+//! with `Content-Encoding: gzip` before the JSON extractor sees them; requests without
+//! the header pass through untouched.
+//!
+//! Inflating is capped, so a small compressed body can't expand without bound.
+
+use std::io::{Read, Write};
+
+#[cfg(feature = "axum")]
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+/// Largest body accepted before or after inflating; matches axum's default body limit.
+pub const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Compress `data` as a single gzip member.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    // Writing to a `Vec` can't fail
+    encoder.write_all(data).expect("write to Vec");
+    encoder.finish().expect("write to Vec")
+}
+
+/// Decompress a single gzip member of at most `limit` bytes, verifying its CRC and
+/// length trailer.
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    GzDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| e.to_string())?;
+    if out.len() > limit {
+        return Err(format!("inflated body exceeds {limit} bytes"));
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "axum")]
+fn is_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("gzip"))
+}
+
+/// Middleware inflating gzip request bodies; `400` when the body isn't valid gzip.
+#[cfg(feature = "axum")]
+pub async fn decompress_request(req: Request, next: Next) -> Response {
+    if !is_gzip(req.headers()) {
+        return next.run(req).await;
+    }
+    let (mut parts, body) = req.into_parts();
+    let Ok(compressed) = axum::body::to_bytes(body, MAX_BODY_BYTES).await else {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "request body too large").into_response();
+    };
+    match decompress(&compressed, MAX_BODY_BYTES) {
+        Ok(body) => {
+            parts.headers.remove(header::CONTENT_ENCODING);
+            parts.headers.remove(header::CONTENT_LENGTH);
+            next.run(Request::from_parts(parts, Body::from(body))).await
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, format!("invalid gzip body: {e}")).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "axum")]
+    use tower::ServiceExt;
+
+    /// `gzip -9` output for a chat request; the body uses a dynamic Huffman block.
+    const GZIPPED_CHAT_REQUEST: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xdd, 0x8b, 0x41, 0x0e, 0xc2,
+        0x30, 0x0c, 0x04, 0xbf, 0x62, 0xf9, 0x5c, 0xf5, 0x01, 0x7d, 0x07, 0x17, 0x84, 0x38, 0x84,
+        0xd6, 0x94, 0x42, 0x12, 0x83, 0xd7, 0x01, 0x4a, 0xd5, 0xbf, 0x13, 0x2e, 0x48, 0x7c, 0x81,
+        0xd3, 0x4a, 0x33, 0x3b, 0x0b, 0x27, 0x1d, 0x24, 0x72, 0xc7, 0x89, 0x1b, 0x4e, 0x02, 0x84,
+        0x51, 0xc0, 0xdd, 0x6e, 0x61, 0xd3, 0x28, 0x95, 0x63, 0x86, 0xcb, 0x47, 0xf6, 0x9a, 0x5d,
+        0xb2, 0x57, 0xb4, 0xd5, 0x42, 0xc1, 0x84, 0x02, 0xb9, 0x18, 0xea, 0x02, 0x13, 0x3c, 0x64,
+        0x6f, 0x79, 0x6d, 0xbe, 0x61, 0x81, 0xd8, 0x4f, 0xb6, 0x39, 0x09, 0xdd, 0xca, 0xd4, 0x5f,
+        0xe8, 0x60, 0xfa, 0xc8, 0x74, 0xd4, 0x27, 0x9d, 0x4b, 0xba, 0x82, 0xf4, 0x2e, 0x46, 0x5e,
+        0x75, 0x0c, 0xaf, 0x99, 0x06, 0x1d, 0x5b, 0xfa, 0xfb, 0x33, 0xaf, 0xfb, 0xf5, 0x0d, 0x8a,
+        0xa6, 0xc5, 0x32, 0x7e, 0x01, 0x00, 0x00,
+    ];
+
+    #[cfg(feature = "axum")]
+    fn echo_app() -> axum::Router {
+        axum::Router::new()
+            .route("/", axum::routing::post(|body: String| async move { body }))
+            .layer(axum::middleware::from_fn(decompress_request))
+    }
+
+    #[cfg(feature = "axum")]
+    fn request(body: &'static [u8], gzipped: bool) -> Request {
+        let mut req = Request::post("/");
+        if gzipped {
+            req = req.header(header::CONTENT_ENCODING, "gzip");
+        }
+        req.body(Body::from(body)).unwrap()
+    }
+
+    #[cfg(feature = "axum")]
+    async fn body_text(resp: Response) -> String {
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&body).into_owned()
+    }
+
+    #[test]
+    fn test_decompress_gzip_from_reference_encoder() {
+        let body = decompress(GZIPPED_CHAT_REQUEST, MAX_BODY_BYTES).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["messages"][1]["content"].as_str().unwrap().len(), 270);
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_gzipped_request_inflated_and_plain_passes_through() {
+        let resp = echo_app()
+            .oneshot(request(GZIPPED_CHAT_REQUEST, true))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = body_text(resp).await;
+        assert!(body.contains("quick brown fox"), "{body}");
+
+        let plain = br#"{"model":"m"}"#;
+        let resp = echo_app().oneshot(request(plain, false)).await.unwrap();
+        assert_eq!(body_text(resp).await, r#"{"model":"m"}"#);
+    }
+
+    #[test]
+    fn test_repetitive_prompt_compresses_and_round_trips() {
+        let prompt = r#"{"role":"user","content":"Summarize the meeting notes."},"#.repeat(50);
+        let gz = compress(prompt.as_bytes());
+        assert_eq!(gz[..3], [0x1f, 0x8b, 8]);
+        assert!(gz.len() < prompt.len() / 5, "{} bytes", gz.len());
+        assert_eq!(decompress(&gz, MAX_BODY_BYTES).unwrap(), prompt.as_bytes());
+    }
+
+    #[test]
+    fn test_corrupt_truncated_or_oversized_input_rejected() {
+        let mut corrupt = GZIPPED_CHAT_REQUEST.to_vec();
+        let crc_pos = corrupt.len() - 8;
+        corrupt[crc_pos] ^= 0xff;
+        assert!(decompress(&corrupt, MAX_BODY_BYTES).is_err());
+        assert!(decompress(&GZIPPED_CHAT_REQUEST[..40], MAX_BODY_BYTES).is_err());
+        assert!(decompress(GZIPPED_CHAT_REQUEST, 64).is_err());
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_invalid_gzip_body_rejected() {
+        let resp = echo_app()
+            .oneshot(request(br#"{"model":"m"}"#, true))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
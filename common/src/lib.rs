@@ -0,0 +1,9 @@
+//! Code shared by the gateway and the nodes.
+
+#[cfg(feature = "axum")]
+pub mod drain;
+#[cfg(feature = "gzip")]
+pub mod gzip;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod version;
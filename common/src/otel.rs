@@ -0,0 +1,191 @@
+//! OpenTelemetry tracing. With `OTEL_EXPORTER_OTLP_ENDPOINT` set, `tracing` spans are
+//! bridged by `tracing-opentelemetry` and shipped by a batch span processor to
+//! `$OTEL_EXPORTER_OTLP_ENDPOINT/v1/traces` over OTLP/HTTP.
+//!
+//! Traces cross process boundaries with W3C `traceparent` headers, read and written by
+//! the `TraceContextPropagator`: [`set_parent`] joins a span to the caller's trace,
+//! [`current_traceparent`] names the current span for the next hop, and with the
+//! `axum` feature `trace_request` opens a span per request that continues the caller's
+//! trace. A chat request thus shows up as one trace across the gateway and the node
+//! serving it.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "axum")]
+use axum::{extract::Request, middleware::Next, response::Response};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+#[cfg(feature = "axum")]
+use tracing::Instrument;
+use tracing::{Subscriber, warn};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Build the exporting layer when `OTEL_EXPORTER_OTLP_ENDPOINT` is set, reporting
+/// spans as coming from `service_name`.
+pub fn layer_from_env<S>(service_name: &'static str) -> Option<OpenTelemetryLayer<S, Tracer>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|e| !e.trim().is_empty())?;
+    // The builder reads the endpoint (and any other `OTEL_EXPORTER_OTLP_*`) itself
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .inspect_err(|e| warn!("OTLP exporter disabled: {e}"))
+        .ok()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build();
+    let tracer = provider.tracer(service_name);
+    opentelemetry::global::set_tracer_provider(provider);
+    Some(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Make `span` a child of the span named by a `traceparent` header value. Ignored when
+/// the value is missing or invalid, or traces aren't being exported.
+pub fn set_parent(span: &tracing::Span, traceparent: Option<&str>) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+    let carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    let parent = TraceContextPropagator::new().extract(&carrier);
+    // Fails only when no OpenTelemetry layer is installed
+    let _ = span.set_parent(parent);
+}
+
+/// `traceparent` naming the current span, for requests made inside it. `None` when
+/// no span is active or traces aren't being exported.
+pub fn current_traceparent() -> Option<String> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&tracing::Span::current().context(), &mut carrier);
+    carrier.remove("traceparent")
+}
+
+/// Middleware running each request inside a server `request` span, parented on the
+/// caller's `traceparent` when there is one.
+#[cfg(feature = "axum")]
+pub async fn trace_request(req: Request, next: Next) -> Response {
+    let span = tracing::info_span!(
+        "request",
+        otel.kind = "server",
+        method = %req.method(),
+        path = req.uri().path(),
+    );
+    let traceparent = req
+        .headers()
+        .get("traceparent")
+        .and_then(|v| v.to_str().ok());
+    set_parent(&span, traceparent);
+    next.run(req).instrument(span).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceId};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SpanData};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const INCOMING: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    /// A subscriber exporting synchronously to memory, and the exporter to read back.
+    /// Dropping the provider shuts the pipeline down, so the exporter keeps it alive.
+    fn in_memory() -> (
+        impl Subscriber + Send + Sync,
+        (InMemorySpanExporter, SdkTracerProvider),
+    ) {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("test"));
+        (
+            tracing_subscriber::registry().with(layer),
+            (exporter, provider),
+        )
+    }
+
+    fn finished((exporter, _): &(InMemorySpanExporter, SdkTracerProvider), name: &str) -> SpanData {
+        exporter
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("{name} span exported"))
+    }
+
+    #[test]
+    fn test_traceparent_continued_and_propagated() {
+        let (subscriber, exporter) = in_memory();
+
+        let outgoing = tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("handle_chat");
+            set_parent(&request, Some(INCOMING));
+            request.in_scope(current_traceparent)
+        })
+        .expect("traceparent for the current span");
+
+        let span = finished(&exporter, "handle_chat");
+        let context = &span.span_context;
+        assert_eq!(
+            context.trace_id(),
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert_eq!(
+            span.parent_span_id,
+            SpanId::from_hex("b7ad6b7169203331").unwrap()
+        );
+        assert_eq!(
+            outgoing,
+            format!("00-{}-{}-01", context.trace_id(), context.span_id())
+        );
+    }
+
+    #[test]
+    fn test_invalid_traceparent_starts_new_trace() {
+        let (subscriber, exporter) = in_memory();
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("handle_chat");
+            let zero_trace = "00-00000000000000000000000000000000-b7ad6b7169203331-01";
+            set_parent(&request, Some(zero_trace));
+            request.in_scope(|| {});
+        });
+        let span = finished(&exporter, "handle_chat");
+        assert_eq!(span.parent_span_id, SpanId::INVALID);
+        assert_ne!(span.span_context.trace_id(), TraceId::INVALID);
+        assert!(current_traceparent().is_none());
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_request_span_continues_caller_trace() {
+        let (subscriber, exporter) = in_memory();
+        let _default = tracing::subscriber::set_default(subscriber);
+        let app = axum::Router::new()
+            .route("/", axum::routing::get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(trace_request));
+        let req = axum::http::Request::get("/")
+            .header("traceparent", INCOMING)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        tower::ServiceExt::oneshot(app, req).await.unwrap();
+
+        let span = finished(&exporter, "request");
+        assert_eq!(span.span_kind, opentelemetry::trace::SpanKind::Server);
+        assert_eq!(
+            span.span_context.trace_id(),
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert_eq!(
+            span.parent_span_id,
+            SpanId::from_hex("b7ad6b7169203331").unwrap()
+        );
+    }
+}
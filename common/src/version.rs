@@ -0,0 +1,53 @@
+//! Build metadata served at `GET /version`, for matching running binaries to deploys.
+//!
+//! Each service's build script calls [`emit_build_env`], and [`version_info!`] reads the
+//! result back along with the crate's own name and version.
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct VersionInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    /// Unix seconds at build time.
+    pub build_timestamp: &'static str,
+}
+
+/// The [`VersionInfo`] of the crate this is expanded in.
+#[macro_export]
+macro_rules! version_info {
+    () => {
+        $crate::version::VersionInfo {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("GIT_SHA"),
+            build_timestamp: env!("BUILD_TIMESTAMP"),
+        }
+    };
+}
+
+/// From a build script: set `GIT_SHA` and `BUILD_TIMESTAMP` for the crate being built,
+/// rebuilding when the checked-out commit changes.
+pub fn emit_build_env() {
+    let sha = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .unwrap_or_else(|| "unknown".into());
+    let built_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=GIT_SHA={sha}");
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={built_at}");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+}
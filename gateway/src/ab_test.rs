@@ -0,0 +1,121 @@
+//! A/B splits of a client-facing chat model between two backend models
+//! (`GATEWAY_AB_SPLITS`).
+//!
+//! `chat=qwen3-8b:90,qwen3-8b-v2:10` sends 90% of requests for `chat` to `qwen3-8b` and
+//! the rest to `qwen3-8b-v2`. Requests naming a `user` are assigned by a hash of it, so
+//! each user keeps seeing the same variant; anonymous requests are assigned at random.
+//! The chosen model is reported in the `X-Model-Variant` response header.
+
+use std::str::FromStr;
+
+use anyhow::{Context, bail};
+
+pub const VARIANT_HEADER: &str = "x-model-variant";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbSplit {
+    pub a: String,
+    pub b: String,
+    /// Share of traffic, 0–100, sent to `a`.
+    pub percent_a: u8,
+}
+
+impl FromStr for AbSplit {
+    type Err = anyhow::Error;
+
+    /// `model-a:percent,model-b:percent`, with the percentages adding up to 100.
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let variants: Vec<(String, u8)> = spec
+            .split(',')
+            .map(|variant| {
+                let (model, percent) = variant
+                    .split_once(':')
+                    .with_context(|| format!("expected model:percent, got {variant:?}"))?;
+                let percent = percent
+                    .trim()
+                    .parse::<u8>()
+                    .with_context(|| format!("invalid percentage in {variant:?}"))?;
+                Ok((model.trim().to_string(), percent))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let [(a, percent_a), (b, percent_b)] = <[_; 2]>::try_from(variants)
+            .map_err(|_| anyhow::anyhow!("expected exactly two variants in {spec:?}"))?;
+        if a.is_empty() || b.is_empty() {
+            bail!("empty model name in {spec:?}");
+        }
+        if u16::from(percent_a) + u16::from(percent_b) != 100 {
+            bail!("percentages in {spec:?} must add up to 100");
+        }
+        Ok(Self { a, b, percent_a })
+    }
+}
+
+impl AbSplit {
+    /// The backend model for a request, sticky per `user` when one is given.
+    pub fn assign(&self, model: &str, user: Option<&str>) -> &str {
+        let bucket = match user {
+            // Hashing the model too keeps separate experiments independent
+            Some(user) => (fnv1a(&[model.as_bytes(), b"\0", user.as_bytes()]) % 100) as u8,
+            None => fastrand::u8(0..100),
+        };
+        if bucket < self.percent_a {
+            &self.a
+        } else {
+            &self.b
+        }
+    }
+}
+
+/// FNV-1a: stable across builds and platforms, unlike `DefaultHasher`.
+fn fnv1a(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_split() {
+        let split: AbSplit = "qwen3-8b:90, qwen3-8b-v2:10".parse().unwrap();
+        assert_eq!(
+            split,
+            AbSplit {
+                a: "qwen3-8b".into(),
+                b: "qwen3-8b-v2".into(),
+                percent_a: 90,
+            }
+        );
+        assert!("a:50,b:40".parse::<AbSplit>().is_err());
+        assert!("a:100".parse::<AbSplit>().is_err());
+        assert!("a:50,b".parse::<AbSplit>().is_err());
+    }
+
+    #[test]
+    fn test_users_sticky_and_random_split_holds() {
+        let split: AbSplit = "a:70,b:30".parse().unwrap();
+        let first = split.assign("chat", Some("user-42"));
+        for _ in 0..100 {
+            assert_eq!(split.assign("chat", Some("user-42")), first);
+        }
+
+        let trials = 20_000;
+        let to_a = (0..trials)
+            .filter(|_| split.assign("chat", None) == "a")
+            .count();
+        let share = to_a as f64 / trials as f64;
+        assert!((share - 0.7).abs() < 0.02, "{share}");
+
+        // Hashed assignment spreads distinct users by the same ratio
+        let users_to_a = (0..trials)
+            .filter(|i| split.assign("chat", Some(&format!("user-{i}"))) == "a")
+            .count();
+        let share = users_to_a as f64 / trials as f64;
+        assert!((share - 0.7).abs() < 0.02, "{share}");
+    }
+}
@@ -0,0 +1,154 @@
+//! Bounded admission queue in front of chat proxying (`GATEWAY_MAX_IN_FLIGHT`).
+//!
+//! At most `max_in_flight` chat requests are proxied at once across all clients and
+//! backends; a streamed reply keeps its place until the stream ends. Later requests
+//! wait their turn in arrival order, up to `queue_size` of them and for at most
+//! `queue_timeout`, so a burst reaches llm-node at the rate it drains instead of all
+//! at once. A request that finds the queue full, or is still waiting at its deadline,
+//! gets `503` with `Retry-After`.
+
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{debug, warn};
+use warp::http::StatusCode;
+
+use crate::{config, proxy};
+
+static ADMISSION: LazyLock<AdmissionQueue> =
+    LazyLock::new(|| AdmissionQueue::new(config().admission));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueuePolicy {
+    pub max_in_flight: usize,
+    /// Requests allowed to wait for a slot (`GATEWAY_QUEUE_SIZE`).
+    pub queue_size: usize,
+    /// Longest a request waits for a slot (`GATEWAY_QUEUE_TIMEOUT_MS`).
+    pub queue_timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejected {
+    QueueFull,
+    TimedOut,
+}
+
+/// Wait for a chat slot. The permit, if any, must be held until the reply is done.
+pub async fn admit() -> Result<Option<SemaphorePermit<'static>>, Rejected> {
+    let result = ADMISSION.admit().await;
+    match result {
+        Err(Rejected::QueueFull) => warn!("Rejected chat request: admission queue is full"),
+        Err(Rejected::TimedOut) => warn!("Rejected chat request: timed out in admission queue"),
+        Ok(_) => {}
+    }
+    result
+}
+
+/// `503` with `Retry-After` set to the queue timeout, a rough time for it to drain.
+pub fn rejected_reply(rejected: Rejected) -> warp::reply::Response {
+    let error = match rejected {
+        Rejected::QueueFull => "gateway queue is full",
+        Rejected::TimedOut => "timed out waiting in the gateway queue",
+    };
+    let reply = proxy::error_reply(error.into(), StatusCode::SERVICE_UNAVAILABLE);
+    let retry_after = ADMISSION
+        .policy
+        .map_or(Duration::ZERO, |policy| policy.queue_timeout);
+    proxy::with_retry_after(reply, retry_after)
+}
+
+pub struct AdmissionQueue {
+    policy: Option<QueuePolicy>,
+    slots: Semaphore,
+    waiting: AtomicUsize,
+}
+
+/// Counts a request as waiting until dropped, including when the client goes away.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl AdmissionQueue {
+    /// `None` admits everything at once.
+    pub fn new(policy: Option<QueuePolicy>) -> Self {
+        Self {
+            policy,
+            slots: Semaphore::new(policy.map_or(0, |policy| policy.max_in_flight)),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// A slot, queueing for one if all are taken; `Ok(None)` when there is no limit.
+    pub async fn admit(&self) -> Result<Option<SemaphorePermit<'_>>, Rejected> {
+        let Some(policy) = self.policy else {
+            return Ok(None);
+        };
+        // Fails while others are queued, as freed slots go straight to them
+        if let Ok(permit) = self.slots.try_acquire() {
+            return Ok(Some(permit));
+        }
+        self.waiting
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |waiting| {
+                (waiting < policy.queue_size).then_some(waiting + 1)
+            })
+            .map_err(|_| Rejected::QueueFull)?;
+        let _waiting = Waiting(&self.waiting);
+        debug!("All chat slots busy; queueing request");
+        match tokio::time::timeout(policy.queue_timeout, self.slots.acquire()).await {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            _ => Err(Rejected::TimedOut),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(max_in_flight: usize, queue_size: usize, timeout_ms: u64) -> AdmissionQueue {
+        AdmissionQueue::new(Some(QueuePolicy {
+            max_in_flight,
+            queue_size,
+            queue_timeout: Duration::from_millis(timeout_ms),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_admitted_when_slot_frees() {
+        let queue = queue(1, 1, 1000);
+        let first = queue.admit().await.unwrap();
+        let (second, ()) = tokio::join!(queue.admit(), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(first);
+        });
+        assert!(second.unwrap().is_some());
+        assert_eq!(queue.waiting.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_and_deadline_reject() {
+        let queue = queue(1, 1, 50);
+        let _held = queue.admit().await.unwrap();
+        let (waiter, overflow) = tokio::join!(queue.admit(), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            queue.admit().await
+        });
+        assert_eq!(overflow.unwrap_err(), Rejected::QueueFull);
+        assert_eq!(waiter.unwrap_err(), Rejected::TimedOut);
+        assert_eq!(queue.waiting.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_policy_admits_everything() {
+        let queue = AdmissionQueue::new(None);
+        for _ in 0..3 {
+            assert!(queue.admit().await.unwrap().is_none());
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Static API key authentication.
+//!
+//! Auth is enabled when `GATEWAY_API_KEYS` or `GATEWAY_API_KEYS_FILE` provides at
+//! least one key; clients then send `Authorization: Bearer <key>`. With no keys
+//! configured every request is allowed.
+//!
+//! Keys are written `name:key` so logs can attribute traffic to a caller without
+//! printing the secret; a bare key is named after its position (`key-1`, `key-2`, …).
+//! The file holds one entry per line, with blank lines and `#` comments ignored.
+//!
+//! With [`crate::jwt`] enabled, a bearer value that isn't a configured key is
+//! checked as a JWT instead.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, bail};
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tracing::debug;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::config::{env_list, env_opt};
+use crate::context::{RequestContext, request_context};
+use crate::jwt;
+use crate::proxy::{self, ProxyReply};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    /// Label used in logs in place of the key.
+    pub name: String,
+    pub key: String,
+}
+
+impl ApiKey {
+    /// Parse `name:key`, or a bare key named `key-<position>` (1-based).
+    fn parse(entry: &str, position: usize) -> anyhow::Result<Self> {
+        let (name, key) = match entry.split_once(':') {
+            Some((name, key)) => (name.trim().to_string(), key.trim()),
+            None => (format!("key-{position}"), entry.trim()),
+        };
+        if name.is_empty() || key.is_empty() {
+            bail!("API key entry {position} needs both a name and a key (`name:key`)");
+        }
+        Ok(Self {
+            name,
+            key: key.to_string(),
+        })
+    }
+}
+
+/// Keys from `GATEWAY_API_KEYS` followed by those in `GATEWAY_API_KEYS_FILE`.
+pub fn keys_from_env() -> anyhow::Result<Vec<ApiKey>> {
+    let mut entries = env_list("GATEWAY_API_KEYS")?;
+    if let Some(path) = env_opt::<String>("GATEWAY_API_KEYS_FILE")? {
+        entries.extend(read_key_file(Path::new(&path))?);
+    }
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| ApiKey::parse(entry, index + 1))
+        .collect()
+}
+
+fn read_key_file(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading API keys from {}", path.display()))?;
+    Ok(key_file_entries(&text))
+}
+
+/// Non-empty, non-comment lines of a key file.
+fn key_file_entries(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Compare against every configured key in constant time so timing does not reveal
+/// how much of a guess matched or which key it was close to.
+pub fn find_key<'a>(candidate: &str, keys: &'a [ApiKey]) -> Option<&'a ApiKey> {
+    keys.iter().fold(None, |found, key| {
+        let matched = bool::from(candidate.as_bytes().ct_eq(key.key.as_bytes()));
+        if matched { Some(key) } else { found }
+    })
+}
+
+/// Accept `ctx` if auth is disabled or it carries a configured key or, with
+/// `jwt`, a valid token; fills in [`RequestContext::key_name`] or
+/// [`RequestContext::claims`] for whichever matched.
+async fn authenticate(
+    ctx: &mut RequestContext,
+    keys: &[ApiKey],
+    jwt: Option<&jwt::Validator>,
+) -> bool {
+    if keys.is_empty() && jwt.is_none() {
+        return true;
+    }
+    let Some(token) = ctx.api_key.as_deref() else {
+        return false;
+    };
+    if let Some(key) = find_key(token, keys) {
+        ctx.key_name = Some(key.name.clone());
+        return true;
+    }
+    let Some(jwt) = jwt else {
+        return false;
+    };
+    match jwt.validate(token).await {
+        Ok(claims) => {
+            ctx.claims = Some(Arc::new(claims));
+            true
+        }
+        Err(reason) => {
+            debug!("Rejected bearer token: {reason}");
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Extract the [`RequestContext`] with the caller's identity filled in, rejecting
+/// requests without a valid key or token.
+pub fn authorized(
+    keys: &'static [ApiKey],
+) -> impl Filter<Extract = (RequestContext,), Error = Rejection> + Clone {
+    request_context().and_then(move |mut ctx: RequestContext| async move {
+        if authenticate(&mut ctx, keys, jwt::validator()).await {
+            Ok(ctx)
+        } else {
+            Err(warp::reject::custom(Unauthorized))
+        }
+    })
+}
+
+/// The `401` body in the shape OpenAI clients parse, so SDKs surface it as an
+/// authentication error.
+fn unauthorized_reply() -> ProxyReply {
+    proxy::typed_error_reply(
+        "missing or invalid API key",
+        "invalid_request_error",
+        "invalid_api_key",
+        StatusCode::UNAUTHORIZED,
+    )
+}
+
+/// Turn an [`Unauthorized`] rejection into a `401` JSON error; other rejections pass on.
+pub async fn recover_unauthorized(err: Rejection) -> Result<impl Reply, Rejection> {
+    if err.find::<Unauthorized>().is_none() {
+        return Err(err);
+    }
+    Ok(unauthorized_reply())
+}
+
+/// `GET /v1/auth/validate`: lets clients check a key without spending a completion.
+pub fn validate_route(
+    keys: &'static [ApiKey],
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    warp::path!("v1" / "auth" / "validate")
+        .and(warp::get())
+        .and(request_context())
+        .then(move |mut ctx: RequestContext| async move {
+            let jwt = jwt::validator();
+            let (status, body) = if keys.is_empty() && jwt.is_none() {
+                (StatusCode::OK, json!({ "auth": "disabled" }))
+            } else if !authenticate(&mut ctx, keys, jwt).await {
+                (StatusCode::UNAUTHORIZED, json!({ "valid": false }))
+            } else if let Some(claims) = &ctx.claims {
+                (
+                    StatusCode::OK,
+                    json!({ "valid": true, "user": claims.user }),
+                )
+            } else {
+                (
+                    StatusCode::OK,
+                    json!({ "valid": true, "name": ctx.key_name }),
+                )
+            };
+            warp::reply::with_status(warp::reply::json(&body), status).into_response()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(list: &[&str]) -> &'static [ApiKey] {
+        list.iter()
+            .enumerate()
+            .map(|(index, entry)| ApiKey::parse(entry, index + 1).unwrap())
+            .collect::<Vec<_>>()
+            .leak()
+    }
+
+    async fn validate(keys: &'static [ApiKey], auth: Option<&str>) -> (StatusCode, String) {
+        let mut req = warp::test::request()
+            .method("GET")
+            .path("/v1/auth/validate");
+        if let Some(auth) = auth {
+            req = req.header("authorization", auth);
+        }
+        let resp = req.reply(&validate_route(keys)).await;
+        let body = String::from_utf8_lossy(resp.body()).into_owned();
+        (resp.status(), body)
+    }
+
+    #[tokio::test]
+    async fn test_validate_accepts_known_key() {
+        let (status, body) = validate(keys(&["sk-a", "ci:sk-b"]), Some("Bearer sk-b")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"name":"ci","valid":true}"#);
+    }
+
+    #[tokio::test]
+    async fn test_validate_rejects_unknown_or_missing_key() {
+        let configured = keys(&["sk-a"]);
+        let (status, _) = validate(configured, Some("Bearer sk-ab")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = validate(configured, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_auth_disabled() {
+        let (status, body) = validate(keys(&[]), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"auth":"disabled"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_authorized_filter_rejects_bad_key_with_401() {
+        let filter = authorized(keys(&["sk-a"]))
+            .map(|_| "ok")
+            .recover(recover_unauthorized);
+        let resp = warp::test::request()
+            .header("authorization", "Bearer wrong")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["error"]["code"], "invalid_api_key");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+    }
+
+    #[tokio::test]
+    async fn test_authorized_filter_names_the_key() {
+        let filter = authorized(keys(&["alice:sk-a", "bob:sk-b"]))
+            .map(|ctx: RequestContext| ctx.key_name.unwrap_or_default());
+        let resp = warp::test::request()
+            .header("authorization", "Bearer sk-b")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.body(), "bob");
+    }
+
+    #[test]
+    fn test_key_entries_parsed_from_env_list_and_file() {
+        let file = "# team keys\n\nalice: sk-a\n  sk-bare  \n";
+        let entries = key_file_entries(file);
+        assert_eq!(entries, ["alice: sk-a", "sk-bare"]);
+        let parsed: Vec<ApiKey> = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| ApiKey::parse(entry, index + 1).unwrap())
+            .collect();
+        assert_eq!(parsed[0].name, "alice");
+        assert_eq!(parsed[0].key, "sk-a");
+        assert_eq!(parsed[1].name, "key-2");
+        assert_eq!(parsed[1].key, "sk-bare");
+        assert!(ApiKey::parse("name:", 1).is_err());
+        assert!(ApiKey::parse(":sk-x", 1).is_err());
+    }
+}
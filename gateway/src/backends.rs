@@ -0,0 +1,621 @@
+//! Pools of llm-node replicas that chat requests are spread across: the
+//! `GATEWAY_LLM_BACKENDS` pool, and one per model route listing several URLs.
+//!
+//! Each backend keeps an exponential moving average of its upstream latency, updated
+//! after every successful call with smoothing factor `GATEWAY_LATENCY_EMA_ALPHA`. With
+//! `GATEWAY_BACKEND_SELECTION=latency` the fastest backend is preferred; backends with
+//! no measurement yet are tried first so every replica gets a reading.
+//! `least_outstanding` picks the backend with the fewest requests in flight, counted
+//! from selection until the reply (or stream) is finished.
+//!
+//! Every `GATEWAY_READINESS_INTERVAL_MS` each backend's `/ready` is probed (see
+//! [`crate::health`]): backends that can't be reached are marked down, and those
+//! answering `503` (e.g. after `POST /admin/drain` on the node) draining. Both are
+//! skipped until a later probe finds them ready. If no backend is left, selection
+//! carries on over all of them rather than failing requests outright.
+//!
+//! With a [`BreakerPolicy`] (`GATEWAY_BREAKER_THRESHOLD`), a backend that fails that
+//! many chat requests in a row has its circuit opened for the cooldown and is skipped
+//! like a down one. Once the cooldown passes the circuit is half-open: exactly one
+//! trial request is let through while the rest keep failing fast, and its success
+//! closes the circuit while failure opens it again. When every backend's circuit is
+//! open the caller fails fast instead of sending the request (see
+//! [`Selected::circuit_open`]).
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::json;
+use warp::{Filter, Rejection, Reply};
+
+use crate::config::BackendSelection;
+use crate::context::RequestContext;
+
+/// When to stop sending requests to a failing backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerPolicy {
+    /// Consecutive failed requests that open the circuit.
+    pub threshold: u32,
+    /// How long the circuit stays open before a trial request.
+    pub cooldown: Duration,
+}
+
+#[derive(Debug)]
+pub struct Backend {
+    pub url: String,
+    latency_ema_ms: Mutex<Option<f64>>,
+    draining: AtomicBool,
+    /// Assumed until a probe fails to reach the backend.
+    up: AtomicBool,
+    outstanding: AtomicUsize,
+    /// Failed requests since the last success.
+    failures: AtomicU32,
+    /// Set while the circuit is open, and kept after the cooldown until a trial
+    /// request succeeds.
+    open_until: Mutex<Option<Instant>>,
+    /// Whether the half-open circuit's one trial request has been let through.
+    trial_in_flight: AtomicBool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Circuit {
+    Closed,
+    Open,
+    /// Past the cooldown, waiting on a trial request.
+    HalfOpen,
+}
+
+/// What the breaker lets a selected request do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Admission {
+    Send,
+    /// Send as the half-open circuit's trial.
+    Trial,
+    FailFast,
+}
+
+impl Backend {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            latency_ema_ms: Mutex::new(None),
+            draining: AtomicBool::new(false),
+            up: AtomicBool::new(true),
+            outstanding: AtomicUsize::new(0),
+            failures: AtomicU32::new(0),
+            open_until: Mutex::new(None),
+            trial_in_flight: AtomicBool::new(false),
+        }
+    }
+
+    pub fn latency_ema_ms(&self) -> Option<f64> {
+        *self.latency_ema_ms.lock().unwrap()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.up.load(Ordering::Relaxed)
+    }
+
+    fn circuit(&self) -> Circuit {
+        match *self.open_until.lock().unwrap() {
+            None => Circuit::Closed,
+            Some(until) if Instant::now() < until => Circuit::Open,
+            Some(_) => Circuit::HalfOpen,
+        }
+    }
+
+    /// Within the cooldown after too many consecutive failures, or half-open with its
+    /// trial request already under way.
+    pub fn circuit_open(&self) -> bool {
+        match self.circuit() {
+            Circuit::Closed => false,
+            Circuit::Open => true,
+            Circuit::HalfOpen => self.trial_in_flight.load(Ordering::Acquire),
+        }
+    }
+
+    /// Let a request through unless the circuit is open; a half-open circuit admits
+    /// only the first caller, as its trial.
+    fn admit(&self) -> Admission {
+        match self.circuit() {
+            Circuit::Closed => Admission::Send,
+            Circuit::Open => Admission::FailFast,
+            Circuit::HalfOpen => match self.trial_in_flight.compare_exchange(
+                false,
+                true,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => Admission::Trial,
+                Err(_) => Admission::FailFast,
+            },
+        }
+    }
+
+    /// Up, not draining and its circuit closed: fit for new requests.
+    fn is_available(&self) -> bool {
+        self.is_up() && !self.is_draining() && !self.circuit_open()
+    }
+
+    /// Requests sent to this backend that haven't finished yet.
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    /// The node's readiness endpoint, on the same host as its API URL.
+    fn ready_url(&self) -> Option<reqwest::Url> {
+        reqwest::Url::parse(&self.url).ok()?.join("/ready").ok()
+    }
+}
+
+#[derive(Serialize)]
+struct BackendStatus<'a> {
+    url: &'a str,
+    latency_ema_ms: Option<f64>,
+    up: bool,
+    draining: bool,
+    circuit_open: bool,
+    outstanding: usize,
+}
+
+/// The backend chosen for one request, counted as outstanding until dropped.
+#[derive(Debug)]
+pub struct Selected<'a> {
+    pool: &'a BackendPool,
+    backend: &'a Backend,
+    admission: Admission,
+    /// Set while this request holds the backend's trial, until its outcome is known.
+    trial: AtomicBool,
+}
+
+impl<'a> Selected<'a> {
+    pub fn url(&self) -> &'a str {
+        &self.backend.url
+    }
+
+    /// Fold this request's upstream latency into the backend's moving average.
+    pub fn record(&self, latency: Duration) {
+        self.pool.record(&self.backend.url, latency);
+    }
+
+    /// Count the request towards the backend's circuit breaker.
+    pub fn record_outcome(&self, success: bool) {
+        self.pool.record_outcome(self.backend, success);
+        self.end_trial();
+    }
+
+    /// Whether the request must not be sent: the backend's circuit is open, or it is
+    /// half-open and another request is its trial. Selection only returns such a
+    /// backend when no other is available, so the request should fail fast.
+    pub fn circuit_open(&self) -> bool {
+        self.admission == Admission::FailFast
+    }
+
+    /// Let the next request be the trial if this one was and never reported back.
+    fn end_trial(&self) {
+        if self.trial.swap(false, Ordering::AcqRel) {
+            self.backend.trial_in_flight.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl Drop for Selected<'_> {
+    fn drop(&mut self) {
+        self.end_trial();
+        self.backend.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct BackendPool {
+    backends: Vec<Backend>,
+    selection: BackendSelection,
+    alpha: f64,
+    breaker: Option<BreakerPolicy>,
+    next: AtomicUsize,
+}
+
+impl BackendPool {
+    /// `urls` must not be empty.
+    pub fn new(urls: Vec<String>, selection: BackendSelection, alpha: f64) -> Self {
+        assert!(!urls.is_empty(), "backend pool needs at least one backend");
+        Self {
+            backends: urls.into_iter().map(Backend::new).collect(),
+            selection,
+            alpha,
+            breaker: None,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Open backends' circuits by `breaker`; `None` never opens them.
+    pub fn set_breaker(&mut self, breaker: Option<BreakerPolicy>) {
+        self.breaker = breaker;
+    }
+
+    /// Use `selection` and `alpha` instead of the strategy the pool was built with.
+    pub fn configure(&mut self, selection: BackendSelection, alpha: f64) {
+        self.selection = selection;
+        self.alpha = alpha;
+    }
+
+    pub fn select(&self) -> Selected<'_> {
+        // Down or draining backends only take traffic when nothing else is left
+        let none_available = !self.backends.iter().any(Backend::is_available);
+        let eligible = |b: &&Backend| none_available || b.is_available();
+        let backend = match self.selection {
+            BackendSelection::RoundRobin => self.round_robin(eligible),
+            BackendSelection::Latency => self
+                .backends
+                .iter()
+                .filter(eligible)
+                .min_by(|a, b| {
+                    let a = a.latency_ema_ms().unwrap_or(0.0);
+                    let b = b.latency_ema_ms().unwrap_or(0.0);
+                    a.total_cmp(&b)
+                })
+                .unwrap_or_else(|| self.round_robin(eligible)),
+            // Scanning from a rotating start spreads ties instead of favouring the first
+            BackendSelection::LeastOutstanding => self
+                .rotation()
+                .filter(eligible)
+                .min_by_key(|b| b.outstanding())
+                .unwrap_or_else(|| self.round_robin(eligible)),
+        };
+        backend.outstanding.fetch_add(1, Ordering::Relaxed);
+        let admission = match self.breaker {
+            Some(_) => backend.admit(),
+            None => Admission::Send,
+        };
+        Selected {
+            pool: self,
+            backend,
+            admission,
+            trial: AtomicBool::new(admission == Admission::Trial),
+        }
+    }
+
+    /// Every backend once, starting from the next in rotation.
+    fn rotation(&self) -> impl Iterator<Item = &Backend> {
+        let len = self.backends.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..len).map(move |offset| &self.backends[(start + offset) % len])
+    }
+
+    /// The next backend in rotation that is `eligible`, or the next one regardless.
+    fn round_robin(&self, eligible: impl Fn(&&Backend) -> bool) -> &Backend {
+        let mut rotation = self.rotation().peekable();
+        let next = *rotation.peek().expect("pool is never empty");
+        rotation.find(eligible).unwrap_or(next)
+    }
+
+    /// Probe every backend's `/ready` once, giving up after `timeout`. Unreachable
+    /// backends are marked down and `503` answers draining; any other answer means
+    /// up and ready, so nodes without a readiness endpoint still count as up.
+    pub async fn check_health(&self, client: &reqwest::Client, timeout: Duration) {
+        for backend in &self.backends {
+            let Some(url) = backend.ready_url() else {
+                continue;
+            };
+            let resp = client.get(url).timeout(timeout).send().await;
+            let up = resp.is_ok();
+            let draining =
+                resp.is_ok_and(|r| r.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE);
+            let was_up = backend.up.swap(up, Ordering::Relaxed);
+            let was_draining = backend.draining.swap(draining, Ordering::Relaxed);
+            if (was_up, was_draining) != (up, draining) {
+                let state = match (up, draining) {
+                    (false, _) => "down",
+                    (true, true) => "draining",
+                    (true, false) => "ready",
+                };
+                tracing::info!("Backend {} is {state}", backend.url);
+            }
+        }
+    }
+
+    /// Fold one observed upstream latency into the moving average of the backend at
+    /// `url`; URLs outside the pool (e.g. per-model routes) are ignored.
+    pub fn record(&self, url: &str, latency: Duration) {
+        let Some(backend) = self.backends.iter().find(|b| b.url == url) else {
+            return;
+        };
+        let observed = latency.as_secs_f64() * 1000.0;
+        let mut ema = backend.latency_ema_ms.lock().unwrap();
+        *ema = Some(match *ema {
+            Some(prev) => self.alpha * observed + (1.0 - self.alpha) * prev,
+            None => observed,
+        });
+    }
+
+    fn record_outcome(&self, backend: &Backend, success: bool) {
+        let Some(policy) = self.breaker else {
+            return;
+        };
+        let mut open_until = backend.open_until.lock().unwrap();
+        if success {
+            backend.failures.store(0, Ordering::Relaxed);
+            if open_until.take().is_some() {
+                tracing::info!("Circuit closed for backend {}", backend.url);
+            }
+            return;
+        }
+        let failures = backend.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < policy.threshold {
+            return;
+        }
+        let now = Instant::now();
+        if open_until.is_none_or(|until| until <= now) {
+            tracing::warn!(
+                "Circuit opened for backend {} after {failures} consecutive failures",
+                backend.url
+            );
+        }
+        *open_until = Some(now + policy.cooldown);
+    }
+
+    /// Whether any backend is up, draining or not.
+    pub fn any_up(&self) -> bool {
+        self.backends.iter().any(Backend::is_up)
+    }
+
+    pub fn all_up(&self) -> bool {
+        self.backends.iter().all(Backend::is_up)
+    }
+
+    pub fn status(&self) -> serde_json::Value {
+        let backends: Vec<_> = self
+            .backends
+            .iter()
+            .map(|b| BackendStatus {
+                url: &b.url,
+                latency_ema_ms: b.latency_ema_ms(),
+                up: b.is_up(),
+                draining: b.is_draining(),
+                circuit_open: b.circuit_open(),
+                outstanding: b.outstanding(),
+            })
+            .collect();
+        json!({ "selection": self.selection, "backends": backends })
+    }
+}
+
+/// `GET /admin/backends`: each backend, its current latency average, whether it is
+/// up, draining or has its circuit open, and how many requests it has in flight.
+pub fn admin_route(
+    pool: &'static BackendPool,
+    keys: &'static [crate::auth::ApiKey],
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("admin" / "backends")
+        .and(warp::get())
+        .and(crate::auth::authorized(keys))
+        .map(move |_: RequestContext| warp::reply::json(&pool.status()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(selection: BackendSelection) -> BackendPool {
+        let urls = vec!["http://a".to_string(), "http://b".to_string()];
+        BackendPool::new(urls, selection, 0.5)
+    }
+
+    #[test]
+    fn test_ema_moves_toward_observed_latency() {
+        let pool = pool(BackendSelection::RoundRobin);
+        let backend = &pool.backends[0];
+        pool.record("http://a", Duration::from_millis(100));
+        assert_eq!(backend.latency_ema_ms(), Some(100.0));
+        pool.record("http://a", Duration::from_millis(200));
+        assert_eq!(backend.latency_ema_ms(), Some(150.0));
+        pool.record("http://a", Duration::from_millis(200));
+        assert_eq!(backend.latency_ema_ms(), Some(175.0));
+    }
+
+    #[test]
+    fn test_latency_selection_prefers_faster_backend() {
+        let pool = pool(BackendSelection::Latency);
+        // Unmeasured backends are tried before measured ones
+        pool.record("http://a", Duration::from_millis(40));
+        assert_eq!(pool.select().url(), "http://b");
+
+        pool.record("http://b", Duration::from_millis(300));
+        for _ in 0..3 {
+            assert_eq!(pool.select().url(), "http://a");
+        }
+    }
+
+    #[test]
+    fn test_least_outstanding_avoids_busy_backend() {
+        let urls = vec!["http://a".into(), "http://b".into(), "http://c".into()];
+        let pool = BackendPool::new(urls, BackendSelection::LeastOutstanding, 0.5);
+        // Idle backends share the load in turn
+        let mut seen: Vec<&str> = (0..3).map(|_| pool.select().url()).collect();
+        seen.sort();
+        assert_eq!(seen, ["http://a", "http://b", "http://c"]);
+
+        let busy = [pool.select(), pool.select()];
+        let busy_urls: Vec<&str> = busy.iter().map(Selected::url).collect();
+        assert_ne!(busy_urls[0], busy_urls[1]);
+        for _ in 0..3 {
+            let idle = pool.select();
+            assert!(!busy_urls.contains(&idle.url()), "{}", idle.url());
+        }
+        drop(busy);
+        assert!(pool.backends.iter().all(|b| b.outstanding() == 0));
+    }
+
+    /// A node whose `/ready` answers `status`, as a chat completions URL.
+    async fn node(status: u16) -> String {
+        let ready = warp::path("ready").map(move || {
+            let status = warp::http::StatusCode::from_u16(status).unwrap();
+            warp::reply::with_status("", status)
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(ready).incoming(listener).run());
+        format!("http://{addr}/v1/chat/completions")
+    }
+
+    #[tokio::test]
+    async fn test_draining_backend_excluded_from_selection() {
+        let (ready_url, draining_url) = (node(200).await, node(503).await);
+        let client = reqwest::Client::new();
+        let timeout = Duration::from_secs(1);
+        for selection in [
+            BackendSelection::RoundRobin,
+            BackendSelection::Latency,
+            BackendSelection::LeastOutstanding,
+        ] {
+            let urls = vec![draining_url.clone(), ready_url.clone()];
+            let pool = BackendPool::new(urls, selection, 0.5);
+            pool.check_health(&client, timeout).await;
+            assert!(pool.backends[0].is_draining());
+            assert!(!pool.backends[1].is_draining());
+            for _ in 0..4 {
+                assert_eq!(pool.select().url(), ready_url);
+            }
+        }
+
+        // With every backend draining, requests still go somewhere
+        let pool = BackendPool::new(
+            vec![draining_url.clone()],
+            BackendSelection::RoundRobin,
+            0.5,
+        );
+        pool.check_health(&client, timeout).await;
+        assert_eq!(pool.select().url(), draining_url);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_backend_marked_down_until_it_recovers() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}/v1", listener.local_addr().unwrap());
+        drop(listener);
+        let ready_url = node(200).await;
+        let pool = BackendPool::new(
+            vec![closed.clone(), ready_url.clone()],
+            BackendSelection::RoundRobin,
+            0.5,
+        );
+        let client = reqwest::Client::new();
+        pool.check_health(&client, Duration::from_secs(1)).await;
+        assert!(!pool.backends[0].is_up());
+        assert!(pool.backends[1].is_up());
+        assert!(pool.any_up());
+        for _ in 0..4 {
+            assert_eq!(pool.select().url(), ready_url);
+        }
+
+        // A node without /ready (404) is up: it answered
+        let legacy = node(404).await;
+        let pool = BackendPool::new(vec![legacy], BackendSelection::RoundRobin, 0.5);
+        pool.check_health(&client, Duration::from_secs(1)).await;
+        assert!(pool.backends[0].is_available());
+    }
+
+    #[tokio::test]
+    async fn test_admin_route_lists_backends() {
+        let pool: &'static BackendPool = Box::leak(Box::new(pool(BackendSelection::RoundRobin)));
+        pool.record("http://b", Duration::from_millis(12));
+        let resp = warp::test::request()
+            .path("/admin/backends")
+            .reply(&admin_route(pool, &[]))
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["selection"], "round_robin");
+        assert_eq!(
+            body["backends"][0]["latency_ema_ms"],
+            serde_json::Value::Null
+        );
+        assert_eq!(body["backends"][1]["latency_ema_ms"], 12.0);
+    }
+
+    #[test]
+    fn test_circuit_opens_after_consecutive_failures() {
+        let mut pool = pool(BackendSelection::RoundRobin);
+        pool.set_breaker(Some(BreakerPolicy {
+            threshold: 2,
+            cooldown: Duration::from_millis(50),
+        }));
+        let failing = |pool: &BackendPool, url: &str| loop {
+            let selected = pool.select();
+            if selected.url() == url {
+                selected.record_outcome(false);
+                return;
+            }
+        };
+        failing(&pool, "http://a");
+        // A success in between resets the count
+        pool.record_outcome(&pool.backends[0], true);
+        failing(&pool, "http://a");
+        assert!(!pool.backends[0].circuit_open());
+        failing(&pool, "http://a");
+        assert!(pool.backends[0].circuit_open());
+        for _ in 0..4 {
+            let selected = pool.select();
+            assert_eq!(selected.url(), "http://b");
+            assert!(!selected.circuit_open());
+        }
+
+        // With every circuit open the caller is told to fail fast
+        failing(&pool, "http://b");
+        failing(&pool, "http://b");
+        assert!(pool.select().circuit_open());
+
+        // After the cooldown a trial request is let through and its success closes
+        std::thread::sleep(Duration::from_millis(60));
+        let trial = pool.select();
+        assert!(!trial.circuit_open());
+        trial.record_outcome(true);
+        assert!(trial.backend.open_until.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_half_open_circuit_lets_one_trial_through() {
+        let mut pool = BackendPool::new(vec!["http://a".into()], BackendSelection::RoundRobin, 0.5);
+        pool.set_breaker(Some(BreakerPolicy {
+            threshold: 1,
+            cooldown: Duration::from_millis(50),
+        }));
+        pool.select().record_outcome(false);
+        assert!(pool.select().circuit_open());
+        std::thread::sleep(Duration::from_millis(60));
+
+        // Two requests arriving together after the cooldown: one trial, one fails fast
+        let barrier = std::sync::Barrier::new(2);
+        let sent: Vec<bool> = std::thread::scope(|scope| {
+            let requests: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let selected = pool.select();
+                        barrier.wait();
+                        !selected.circuit_open()
+                    })
+                })
+                .collect();
+            requests.into_iter().map(|r| r.join().unwrap()).collect()
+        });
+        assert_eq!(sent.iter().filter(|&&sent| sent).count(), 1, "{sent:?}");
+
+        // A trial dropped without an outcome frees the slot; a failed one reopens
+        let trial = pool.select();
+        assert!(!trial.circuit_open());
+        assert!(pool.select().circuit_open());
+        trial.record_outcome(false);
+        assert!(pool.select().circuit_open());
+        std::thread::sleep(Duration::from_millis(60));
+        let trial = pool.select();
+        assert!(!trial.circuit_open());
+        trial.record_outcome(true);
+        let (a, b) = (pool.select(), pool.select());
+        assert!(!a.circuit_open() && !b.circuit_open());
+    }
+}
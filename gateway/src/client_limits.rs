@@ -0,0 +1,121 @@
+//! Per-client concurrency limits, so one API key or IP cannot monopolize backends.
+//!
+//! Requests beyond `GATEWAY_MAX_CONCURRENT_PER_CLIENT` for the same identity are shed
+//! immediately rather than queued; other clients are unaffected.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use tracing::warn;
+use warp::Reply;
+
+use crate::context::RequestContext;
+use crate::{config, proxy};
+
+static CLIENT_LIMITER: LazyLock<ClientLimiter> =
+    LazyLock::new(|| ClientLimiter::new(config().max_concurrent_per_client));
+
+/// Claim one of the caller's slots; `None` means the request is shed.
+pub fn acquire(ctx: &RequestContext) -> Option<ClientSlot<'static>> {
+    let slot = CLIENT_LIMITER.try_acquire(ClientLimiter::identity(ctx));
+    if slot.is_none() {
+        warn!("Shed request: client is at its concurrency limit");
+    }
+    slot
+}
+
+pub fn overloaded_reply() -> warp::reply::Response {
+    proxy::error_reply(
+        "too many concurrent requests for this client".into(),
+        warp::http::StatusCode::SERVICE_UNAVAILABLE,
+    )
+    .into_response()
+}
+
+pub struct ClientLimiter {
+    max: Option<usize>,
+    active: Mutex<HashMap<String, usize>>,
+}
+
+/// Holds one of a client's slots until dropped.
+pub struct ClientSlot<'a> {
+    limiter: &'a ClientLimiter,
+    identity: String,
+}
+
+impl ClientLimiter {
+    /// `None` disables the limit.
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            max,
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The JWT user when present, else the API key, else the client IP (the socket
+    /// peer, or the client a trusted proxy forwarded for), else one shared anonymous
+    /// bucket.
+    pub fn identity(ctx: &RequestContext) -> String {
+        if let Some(claims) = &ctx.claims {
+            return format!("user:{}", claims.user);
+        }
+        match (&ctx.api_key, ctx.client_ip) {
+            (Some(key), _) => format!("key:{key}"),
+            (None, Some(ip)) => format!("ip:{ip}"),
+            (None, None) => "anonymous".into(),
+        }
+    }
+
+    /// Take a slot for `identity`, or `None` if that client is already at its limit.
+    pub fn try_acquire(&self, identity: String) -> Option<ClientSlot<'_>> {
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(identity.clone()).or_insert(0);
+        if self.max.is_some_and(|max| *count >= max) {
+            return None;
+        }
+        *count += 1;
+        Some(ClientSlot {
+            limiter: self,
+            identity,
+        })
+    }
+}
+
+impl Drop for ClientSlot<'_> {
+    fn drop(&mut self) {
+        let mut active = self.limiter.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.identity) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.identity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_over_limit_client_is_shed_while_others_proceed() {
+        let limiter = ClientLimiter::new(Some(2));
+        let a1 = limiter.try_acquire("key:a".into());
+        let a2 = limiter.try_acquire("key:a".into());
+        assert!(a1.is_some() && a2.is_some());
+        assert!(limiter.try_acquire("key:a".into()).is_none());
+
+        let b = limiter.try_acquire("key:b".into());
+        assert!(b.is_some());
+
+        drop(a1);
+        assert!(limiter.try_acquire("key:a".into()).is_some());
+    }
+
+    #[test]
+    fn test_idle_clients_are_forgotten() {
+        let limiter = ClientLimiter::new(Some(1));
+        drop(limiter.try_acquire("ip:10.0.0.1".into()));
+        assert!(limiter.active.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+//! Opt-in merging of rapid streamed deltas into fewer events.
+//!
+//! With `GATEWAY_COALESCE_MS` set, the streaming proxy holds the first content delta
+//! for up to that long and appends the text of every delta arriving meanwhile, so the
+//! browser receives one event per window instead of one per token. A chunk that cannot
+//! be merged (a new role, tool calls, anything that is not a completion chunk such as
+//! `[DONE]`) first releases what is held, so ordering and content are preserved.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use futures_util::{Stream, StreamExt, stream};
+use serde_json::Value;
+use tokio::time::Instant;
+
+/// Delta fields that can be concatenated across chunks.
+const MERGEABLE_DELTA_FIELDS: &[&str] = &["content"];
+
+/// Holds one merged chunk at a time.
+#[derive(Default)]
+pub struct Coalescer {
+    pending: Option<Value>,
+    started: Option<Instant>,
+}
+
+impl Coalescer {
+    /// When the held chunk must be released.
+    pub fn deadline(&self, window: Duration) -> Option<Instant> {
+        self.started.map(|started| started + window)
+    }
+
+    /// Accept one chunk, returning whatever must be forwarded now.
+    pub fn push(&mut self, chunk: String) -> Vec<String> {
+        let parsed = serde_json::from_str::<Value>(&chunk)
+            .ok()
+            .filter(|v| v["choices"].is_array());
+        let Some(parsed) = parsed else {
+            let mut ready: Vec<String> = self.flush().into_iter().collect();
+            ready.push(chunk);
+            return ready;
+        };
+        if self
+            .pending
+            .as_mut()
+            .is_some_and(|held| merge(held, &parsed))
+        {
+            return Vec::new();
+        }
+        let ready = self.flush().into_iter().collect();
+        self.pending = Some(parsed);
+        self.started = Some(Instant::now());
+        ready
+    }
+
+    /// Release the held chunk, if any.
+    pub fn flush(&mut self) -> Option<String> {
+        self.started = None;
+        self.pending.take().map(|chunk| chunk.to_string())
+    }
+}
+
+/// Append `next`'s delta text to `into`, or return `false` (leaving `into` unchanged)
+/// when `next` carries anything that cannot be concatenated.
+fn merge(into: &mut Value, next: &Value) -> bool {
+    let Some(next_choices) = next["choices"].as_array() else {
+        return false;
+    };
+    let mergeable = next_choices.iter().all(|choice| {
+        choice["delta"].as_object().is_some_and(|delta| {
+            delta
+                .keys()
+                .all(|k| MERGEABLE_DELTA_FIELDS.contains(&k.as_str()))
+        }) && into["choices"]
+            .as_array()
+            .is_some_and(|held| held.iter().any(|c| c["index"] == choice["index"]))
+    });
+    if !mergeable {
+        return false;
+    }
+    for choice in next_choices {
+        let Some(held) = into["choices"]
+            .as_array_mut()
+            .and_then(|held| held.iter_mut().find(|c| c["index"] == choice["index"]))
+        else {
+            continue;
+        };
+        if let Some(text) = choice["delta"]["content"].as_str() {
+            let content = &mut held["delta"]["content"];
+            let joined = format!("{}{text}", content.as_str().unwrap_or(""));
+            *content = Value::String(joined);
+        }
+        if !choice["finish_reason"].is_null() {
+            held["finish_reason"] = choice["finish_reason"].clone();
+        }
+    }
+    if !next["usage"].is_null() {
+        into["usage"] = next["usage"].clone();
+    }
+    true
+}
+
+/// Merge `chunks` arriving within `window` of the first held one.
+pub fn coalesce<S>(chunks: S, window: Duration) -> impl Stream<Item = String>
+where
+    S: Stream<Item = String> + Unpin,
+{
+    let state = (chunks, Coalescer::default(), VecDeque::new(), false);
+    stream::unfold(
+        state,
+        move |(mut chunks, mut coalescer, mut ready, mut done)| async move {
+            loop {
+                if let Some(chunk) = ready.pop_front() {
+                    return Some((chunk, (chunks, coalescer, ready, done)));
+                }
+                if done {
+                    return None;
+                }
+                let next = match coalescer.deadline(window) {
+                    Some(deadline) => tokio::time::timeout_at(deadline, chunks.next()).await,
+                    None => Ok(chunks.next().await),
+                };
+                match next {
+                    Ok(Some(chunk)) => ready.extend(coalescer.push(chunk)),
+                    Ok(None) => {
+                        ready.extend(coalescer.flush());
+                        done = true;
+                    }
+                    Err(_) => ready.extend(coalescer.flush()),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(content: &str) -> String {
+        serde_json::json!({
+            "object": "chat.completion.chunk",
+            "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+        })
+        .to_string()
+    }
+
+    fn content(chunk: &str) -> String {
+        let chunk: Value = serde_json::from_str(chunk).unwrap();
+        chunk["choices"][0]["delta"]["content"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_rapid_deltas_merged_into_fewer_events() {
+        let input = ["Echo", " ", "hello", " ", "world"].map(delta).to_vec();
+        let input = stream::iter(input).chain(stream::iter(["[DONE]".to_string()]));
+        let output: Vec<String> = coalesce(input, Duration::from_millis(50)).collect().await;
+
+        assert_eq!(output.len(), 2);
+        assert_eq!(content(&output[0]), "Echo hello world");
+        assert_eq!(output[1], "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn test_window_expiry_releases_held_delta() {
+        let slow = stream::iter([delta("a"), delta("b")])
+            .chain(stream::once(async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                delta("c")
+            }))
+            .boxed();
+        let output: Vec<String> = coalesce(slow, Duration::from_millis(20)).collect().await;
+        let contents: Vec<String> = output.iter().map(|c| content(c)).collect();
+        assert_eq!(contents, ["ab", "c"]);
+    }
+
+    #[test]
+    fn test_finish_reason_and_role_are_kept() {
+        let mut coalescer = Coalescer::default();
+        let role = r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":""}}]}"#;
+        assert!(coalescer.push(role.to_string()).is_empty());
+        assert!(coalescer.push(delta("hi")).is_empty());
+        let finish = r#"{"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#;
+        assert!(coalescer.push(finish.to_string()).is_empty());
+
+        let merged: Value = serde_json::from_str(&coalescer.flush().unwrap()).unwrap();
+        assert_eq!(merged["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(merged["choices"][0]["delta"]["content"], "hi");
+        assert_eq!(merged["choices"][0]["finish_reason"], "stop");
+    }
+}
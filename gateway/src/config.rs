@@ -0,0 +1,636 @@
+//! Gateway runtime configuration, read once from the environment at startup.
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use ipnet::IpNet;
+use serde::Serialize;
+use warp::http::HeaderName;
+
+use crate::ab_test::AbSplit;
+use crate::admission::QueuePolicy;
+use crate::auth::ApiKey;
+use crate::backends::BreakerPolicy;
+use crate::dataset::DatasetOptions;
+use crate::empty_reply::EmptyReply;
+use crate::jwt::JwtOptions;
+use crate::key_limits::KeyRateLimit;
+use crate::latency::LatencyModel;
+use crate::retry::Backoff;
+use crate::roles::RoleMap;
+use crate::{context, injection, template};
+
+/// How chat requests pick among `GATEWAY_LLM_BACKENDS`, or the URLs of a model route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendSelection {
+    /// Rotate through backends in order.
+    RoundRobin,
+    /// Prefer the backend with the lowest recent latency.
+    Latency,
+    /// Prefer the backend with the fewest requests in flight.
+    LeastOutstanding,
+}
+
+/// What to do when a prompt matches an injection pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectionMode {
+    /// Don't scan prompts.
+    Off,
+    /// Log a warning and forward the request.
+    Warn,
+    /// Refuse the request with `400`.
+    Block,
+}
+
+impl FromStr for InjectionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "block" => Ok(Self::Block),
+            other => anyhow::bail!("unknown injection mode {other:?}; expected off, warn or block"),
+        }
+    }
+}
+
+/// How idle chat streams are kept alive through proxies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeartbeatStyle {
+    /// An SSE comment line (`:`), ignored by clients.
+    #[default]
+    Comment,
+    /// An empty `data: {}` event, for intermediaries that drop comments.
+    Data,
+}
+
+impl FromStr for HeartbeatStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "comment" => Ok(Self::Comment),
+            "data" => Ok(Self::Data),
+            other => anyhow::bail!("unknown heartbeat style {other:?}; expected comment or data"),
+        }
+    }
+}
+
+impl FromStr for BackendSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "round_robin" => Ok(Self::RoundRobin),
+            "latency" => Ok(Self::Latency),
+            "least_outstanding" => Ok(Self::LeastOutstanding),
+            other => anyhow::bail!(
+                "unknown backend selection {other:?}; expected 'round_robin', 'latency' or \
+                 'least_outstanding'"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Requests slower than this are logged at `warn` (`GATEWAY_SLOW_REQUEST_MS`).
+    pub slow_request: Option<Duration>,
+    /// Limit on each chat backend call, streamed body included (`GATEWAY_CHAT_TIMEOUT_MS`).
+    pub chat_timeout: Option<Duration>,
+    /// Limit on each TTS backend call (`GATEWAY_TTS_TIMEOUT_MS`).
+    pub tts_timeout: Option<Duration>,
+    /// Limit on opening a connection to a chat backend (`GATEWAY_CHAT_CONNECT_TIMEOUT_MS`).
+    pub chat_connect_timeout: Option<Duration>,
+    /// Limit on opening a connection to tts-node (`GATEWAY_TTS_CONNECT_TIMEOUT_MS`).
+    pub tts_connect_timeout: Option<Duration>,
+    /// Content-proportional delay before proxying (`GATEWAY_LATENCY_BASE_MS`,
+    /// `GATEWAY_LATENCY_PER_CHAR_MS`); unset adds none.
+    pub injected_latency: Option<LatencyModel>,
+    /// Indent JSON error bodies for reading with curl (`GATEWAY_PRETTY_ERRORS`).
+    pub pretty_errors: bool,
+    /// How long shutdown waits for in-flight requests (`GATEWAY_DRAIN_TIMEOUT_SECS`).
+    pub drain_timeout: Duration,
+    /// How long to keep accepting requests after the shutdown signal while `/ready`
+    /// reports draining (`GATEWAY_DRAIN_GRACE_SECS`).
+    pub drain_grace: Duration,
+    /// `/ready` status once draining (`GATEWAY_DRAINING_STATUS`).
+    pub draining_status: u16,
+    /// `/ready` body once draining (`GATEWAY_DRAINING_BODY`).
+    pub draining_body: String,
+    /// Reject chat requests carrying unknown JSON fields (`GATEWAY_STRICT_FIELDS`).
+    pub strict_fields: bool,
+    /// Merge consecutive same-role chat messages before forwarding (`GATEWAY_MERGE_SAME_ROLE`).
+    pub merge_same_role: bool,
+    /// Accepted bearer keys (`GATEWAY_API_KEYS`, comma-separated, plus
+    /// `GATEWAY_API_KEYS_FILE`); empty disables auth.
+    pub api_keys: Vec<ApiKey>,
+    /// JWT authentication settings (`GATEWAY_JWT_*`); `None` accepts only API keys.
+    pub jwt: Option<JwtOptions>,
+    /// Longest allowed content of any single chat message (`GATEWAY_MAX_MESSAGE_CHARS`).
+    pub max_message_chars: Option<usize>,
+    /// Most image/audio/file content parts allowed in one chat request
+    /// (`GATEWAY_MAX_ATTACHMENTS`); unset means unlimited.
+    pub max_attachments: Option<usize>,
+    /// Canned assistant reply served with `200` when the chat backend fails
+    /// (`GATEWAY_FALLBACK_MESSAGE`); unset keeps the `502`.
+    pub fallback_message: Option<String>,
+    /// What to do with successful chat replies whose assistant content is empty
+    /// (`GATEWAY_EMPTY_REPLY`).
+    pub empty_reply: EmptyReply,
+    /// Fraction of successful requests logged at `info` (`GATEWAY_LOG_SAMPLE_RATE`).
+    pub log_sample_rate: f64,
+    /// Where completed chat exchanges are recorded as JSONL (`GATEWAY_DATASET_PATH`
+    /// plus `GATEWAY_DATASET_*` options); unset disables recording.
+    pub dataset: Option<DatasetOptions>,
+    /// Permitted model names, or prefixes ending in `*` (`GATEWAY_MODEL_ALLOWLIST`).
+    pub model_allowlist: Vec<String>,
+    /// `(model pattern, suffix)` rules appended to the last user message before
+    /// forwarding (`GATEWAY_PROMPT_SUFFIXES`, e.g. `qwen3-*=Respond in JSON.;*=Be brief.`).
+    pub prompt_suffixes: Vec<(String, String)>,
+    /// `(model pattern, template)` rules rendering the system message placed first in
+    /// the conversation (`GATEWAY_SYSTEM_PROMPTS`, e.g. `*=Today is {date}.`).
+    pub system_prompts: Vec<(String, String)>,
+    /// In-flight chat/TTS requests allowed per API key, or per client IP when
+    /// unauthenticated (`GATEWAY_MAX_CONCURRENT_PER_CLIENT`); unset means unlimited.
+    pub max_concurrent_per_client: Option<usize>,
+    /// `(model pattern, requests per minute)` rate limits on chat requests, from
+    /// `GATEWAY_MODEL_RATE_LIMITS`; models without a rule are unlimited.
+    pub model_rate_limits: Vec<(String, f64)>,
+    /// Chat requests proxied at once (`GATEWAY_MAX_IN_FLIGHT`), with later ones queued
+    /// per `GATEWAY_QUEUE_SIZE` and `GATEWAY_QUEUE_TIMEOUT_MS`; `None` disables the queue.
+    pub admission: Option<QueuePolicy>,
+    /// Per-client request and token rates (`GATEWAY_KEY_RATE_LIMIT_RPM`,
+    /// `GATEWAY_KEY_RATE_LIMIT_TPM`); unset rates are unlimited.
+    pub key_rate_limit: KeyRateLimit,
+    /// `(model pattern, split)` A/B assignments of a client-facing model to one of two
+    /// backend models (`GATEWAY_AB_SPLITS`).
+    pub ab_splits: Vec<(String, AbSplit)>,
+    /// Re-order indexed streaming chunks, holding at most this many back
+    /// (`GATEWAY_REORDER_WINDOW`); unset passes chunks through untouched.
+    pub reorder_window: Option<usize>,
+    /// Longest wait for a missing chunk before skipping it (`GATEWAY_REORDER_TIMEOUT_MS`).
+    pub reorder_timeout: Duration,
+    /// Merge streamed deltas arriving within this window into one event
+    /// (`GATEWAY_COALESCE_MS`); unset forwards every delta as it arrives.
+    pub coalesce_window: Option<Duration>,
+    /// Tokens forwarded per streamed reply before it is cut with `finish_reason: "length"`
+    /// (`GATEWAY_MAX_STREAM_TOKENS`); unset forwards everything.
+    pub max_stream_tokens: Option<usize>,
+    /// Idle time after which a streamed reply gets a heartbeat
+    /// (`GATEWAY_HEARTBEAT_SECS`; `0` disables).
+    pub heartbeat_interval: Option<Duration>,
+    /// What a heartbeat looks like (`GATEWAY_HEARTBEAT_STYLE`).
+    pub heartbeat_style: HeartbeatStyle,
+    /// Prefix of the `X-Trace-Url` response header, completed with the request id
+    /// (`GATEWAY_TRACE_URL_BASE`); unset omits the header.
+    pub trace_url_base: Option<String>,
+    /// Headers a client-supplied request id is read from, first match wins
+    /// (`GATEWAY_REQUEST_ID_HEADERS`).
+    pub request_id_headers: Vec<String>,
+    /// Header the request id is forwarded upstream and echoed to clients under
+    /// (`GATEWAY_REQUEST_ID_OUTPUT_HEADER`).
+    pub request_id_output_header: String,
+    /// Chat completion URL that receives a copy of every chat request, whose
+    /// responses are discarded (`GATEWAY_SHADOW_BACKEND`).
+    pub shadow_backend: Option<String>,
+    /// Chat completion URLs of the llm-node replicas (`GATEWAY_LLM_BACKENDS`);
+    /// empty uses the default local node.
+    pub llm_backends: Vec<String>,
+    /// Backend choice per request (`GATEWAY_BACKEND_SELECTION`).
+    pub backend_selection: BackendSelection,
+    /// Weight of the newest sample in each backend's latency average
+    /// (`GATEWAY_LATENCY_EMA_ALPHA`, 0 < alpha <= 1).
+    pub latency_ema_alpha: f64,
+    /// How often every backend's `/ready` is probed so down or draining nodes are
+    /// skipped (`GATEWAY_READINESS_INTERVAL_MS`); `None` (`0`) disables probing.
+    pub readiness_interval: Option<Duration>,
+    /// Open a backend's circuit after `GATEWAY_BREAKER_THRESHOLD` consecutive chat
+    /// failures, for `GATEWAY_BREAKER_COOLDOWN_SECS`; `None` disables the breaker.
+    pub breaker: Option<BreakerPolicy>,
+    /// Serve `GET /debug/echo` (`GATEWAY_DEBUG_ECHO`).
+    pub debug_echo: bool,
+    /// Backends from `GATEWAY_LLM_BACKENDS` that accept gzip request bodies
+    /// (`GATEWAY_GZIP_BACKENDS`); chat requests to them are compressed.
+    pub gzip_backends: Vec<String>,
+    /// Speak HTTP/2 to plain-HTTP backends without negotiating (`GATEWAY_UPSTREAM_HTTP2`).
+    pub upstream_http2: bool,
+    /// `(backend URL pattern, content type)` rules forcing the response type relayed
+    /// from matching backends (`GATEWAY_CONTENT_TYPE_OVERRIDES`).
+    pub content_type_overrides: Vec<(String, String)>,
+    /// `(backend URL pattern, role map)` rules renaming message roles for matching
+    /// backends (`GATEWAY_ROLE_MAPS`).
+    pub role_maps: Vec<(String, RoleMap)>,
+    /// Prompt-injection screening of user messages (`GATEWAY_INJECTION_MODE`).
+    pub injection_mode: InjectionMode,
+    /// Regexes for the screen (`GATEWAY_INJECTION_PATTERNS`, `;`-separated);
+    /// defaults to a few well-known phrasings.
+    pub injection_patterns: Vec<String>,
+    /// Times a failed chat request is re-sent upstream (`GATEWAY_MAX_RETRIES`).
+    pub max_retries: u32,
+    /// Wait between chat retries (`GATEWAY_RETRY_BACKOFF_MS`, `GATEWAY_RETRY_BACKOFF_MAX_MS`).
+    pub retry_backoff: Backoff,
+    /// Parse chat backend error bodies into metrics labels
+    /// (`GATEWAY_CLASSIFY_UPSTREAM_ERRORS`).
+    pub classify_upstream_errors: bool,
+    /// Share of chat requests that may be retries across all clients
+    /// (`GATEWAY_RETRY_BUDGET`, 0.0 to 1.0).
+    pub retry_budget: f64,
+    /// Most backend attempts one chat request may make, retries included
+    /// (`GATEWAY_MAX_TOTAL_ATTEMPTS`); unset means no cap beyond the retry limit.
+    pub max_total_attempts: Option<u32>,
+    /// How long cached embeddings are served (`GATEWAY_EMBEDDINGS_CACHE_TTL_SECS`).
+    pub embeddings_cache_ttl: Duration,
+    /// Most embeddings kept in the cache (`GATEWAY_EMBEDDINGS_CACHE_SIZE`); 0 disables it.
+    pub embeddings_cache_size: usize,
+    /// How long cached chat replies are served (`GATEWAY_CHAT_CACHE_TTL_SECS`).
+    pub chat_cache_ttl: Duration,
+    /// Most chat replies kept in the response cache (`GATEWAY_CHAT_CACHE_SIZE`); 0
+    /// disables it.
+    pub chat_cache_size: usize,
+    /// Proxies (addresses or CIDR ranges) whose `X-Forwarded-For` and `X-Real-IP`
+    /// headers are believed (`GATEWAY_TRUSTED_PROXIES`).
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            slow_request: None,
+            chat_timeout: None,
+            tts_timeout: None,
+            chat_connect_timeout: None,
+            tts_connect_timeout: None,
+            injected_latency: None,
+            pretty_errors: false,
+            drain_timeout: Duration::from_secs(30),
+            drain_grace: Duration::ZERO,
+            draining_status: 503,
+            draining_body: "draining".into(),
+            strict_fields: false,
+            merge_same_role: false,
+            api_keys: Vec::new(),
+            jwt: None,
+            max_message_chars: None,
+            max_attachments: None,
+            fallback_message: None,
+            empty_reply: EmptyReply::Pass,
+            log_sample_rate: 1.0,
+            dataset: None,
+            model_allowlist: Vec::new(),
+            prompt_suffixes: Vec::new(),
+            system_prompts: Vec::new(),
+            max_concurrent_per_client: None,
+            model_rate_limits: Vec::new(),
+            admission: None,
+            key_rate_limit: KeyRateLimit::default(),
+            ab_splits: Vec::new(),
+            reorder_window: None,
+            reorder_timeout: Duration::from_millis(250),
+            coalesce_window: None,
+            max_stream_tokens: None,
+            heartbeat_interval: Some(Duration::from_secs(15)),
+            heartbeat_style: HeartbeatStyle::Comment,
+            trace_url_base: None,
+            request_id_headers: vec![context::REQUEST_ID_HEADER.to_string()],
+            request_id_output_header: context::REQUEST_ID_HEADER.to_string(),
+            shadow_backend: None,
+            llm_backends: Vec::new(),
+            backend_selection: BackendSelection::RoundRobin,
+            latency_ema_alpha: 0.2,
+            readiness_interval: Some(Duration::from_secs(10)),
+            breaker: None,
+            debug_echo: false,
+            gzip_backends: Vec::new(),
+            upstream_http2: false,
+            content_type_overrides: Vec::new(),
+            role_maps: Vec::new(),
+            injection_mode: InjectionMode::Off,
+            injection_patterns: injection::DEFAULT_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            max_retries: 0,
+            retry_backoff: Backoff::default(),
+            classify_upstream_errors: false,
+            retry_budget: 0.1,
+            max_total_attempts: None,
+            embeddings_cache_ttl: Duration::from_secs(300),
+            embeddings_cache_size: 10_000,
+            chat_cache_ttl: Duration::from_secs(300),
+            chat_cache_size: 0,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
+        let log_sample_rate =
+            env_opt::<f64>("GATEWAY_LOG_SAMPLE_RATE")?.unwrap_or(defaults.log_sample_rate);
+        if !(0.0..=1.0).contains(&log_sample_rate) {
+            anyhow::bail!("GATEWAY_LOG_SAMPLE_RATE must be between 0.0 and 1.0");
+        }
+        let latency_ema_alpha =
+            env_opt::<f64>("GATEWAY_LATENCY_EMA_ALPHA")?.unwrap_or(defaults.latency_ema_alpha);
+        if !(latency_ema_alpha > 0.0 && latency_ema_alpha <= 1.0) {
+            anyhow::bail!("GATEWAY_LATENCY_EMA_ALPHA must be in (0.0, 1.0]");
+        }
+        let injection_patterns = match env_split("GATEWAY_INJECTION_PATTERNS", ';')? {
+            patterns if patterns.is_empty() => defaults.injection_patterns,
+            patterns => patterns,
+        };
+        injection::compile(&injection_patterns)?;
+        let max_total_attempts = env_opt::<u32>("GATEWAY_MAX_TOTAL_ATTEMPTS")?;
+        if max_total_attempts == Some(0) {
+            anyhow::bail!("GATEWAY_MAX_TOTAL_ATTEMPTS must be at least 1");
+        }
+        let retry_budget = env_opt::<f64>("GATEWAY_RETRY_BUDGET")?.unwrap_or(defaults.retry_budget);
+        if !(0.0..=1.0).contains(&retry_budget) {
+            anyhow::bail!("GATEWAY_RETRY_BUDGET must be between 0.0 and 1.0");
+        }
+        let request_id_headers = match env_list("GATEWAY_REQUEST_ID_HEADERS")? {
+            names if names.is_empty() => defaults.request_id_headers,
+            names => names.iter().map(|name| name.to_ascii_lowercase()).collect(),
+        };
+        let request_id_output_header = env_opt::<String>("GATEWAY_REQUEST_ID_OUTPUT_HEADER")?
+            .map_or(defaults.request_id_output_header, |name| {
+                name.to_ascii_lowercase()
+            });
+        for name in request_id_headers.iter().chain([&request_id_output_header]) {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                anyhow::bail!("invalid request id header name {name:?}");
+            }
+        }
+        let latency_base = env_opt::<u64>("GATEWAY_LATENCY_BASE_MS")?;
+        let latency_per_char = env_opt::<f64>("GATEWAY_LATENCY_PER_CHAR_MS")?;
+        if latency_per_char.is_some_and(|ms| !(ms.is_finite() && ms >= 0.0)) {
+            anyhow::bail!("GATEWAY_LATENCY_PER_CHAR_MS must be a non-negative number");
+        }
+        let injected_latency =
+            (latency_base.is_some() || latency_per_char.is_some()).then(|| LatencyModel {
+                base: Duration::from_millis(latency_base.unwrap_or(0)),
+                per_char_ms: latency_per_char.unwrap_or(0.0),
+            });
+        let max_stream_tokens = env_opt::<usize>("GATEWAY_MAX_STREAM_TOKENS")?;
+        if max_stream_tokens == Some(0) {
+            anyhow::bail!("GATEWAY_MAX_STREAM_TOKENS must be at least 1");
+        }
+        let model_rate_limits = env_rules("GATEWAY_MODEL_RATE_LIMITS")?
+            .into_iter()
+            .map(|(pattern, rate)| match rate.parse::<f64>() {
+                Ok(per_minute) if per_minute.is_finite() && per_minute > 0.0 => {
+                    Ok((pattern, per_minute))
+                }
+                _ => anyhow::bail!(
+                    "invalid GATEWAY_MODEL_RATE_LIMITS rate {rate:?} for {pattern:?}: \
+                     expected a positive number of requests per minute"
+                ),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let dataset = match env_opt::<PathBuf>("GATEWAY_DATASET_PATH")? {
+            Some(path) => {
+                let sample_rate = env_opt::<f64>("GATEWAY_DATASET_SAMPLE_RATE")?.unwrap_or(1.0);
+                if !(0.0..=1.0).contains(&sample_rate) {
+                    anyhow::bail!("GATEWAY_DATASET_SAMPLE_RATE must be between 0.0 and 1.0");
+                }
+                let max_mb = env_opt::<u64>("GATEWAY_DATASET_MAX_MB")?.unwrap_or(100);
+                if max_mb == 0 {
+                    anyhow::bail!("GATEWAY_DATASET_MAX_MB must be at least 1");
+                }
+                Some(DatasetOptions {
+                    path,
+                    sample_rate,
+                    redact: env_flag("GATEWAY_DATASET_REDACT")?.unwrap_or(false),
+                    max_bytes: max_mb * 1024 * 1024,
+                })
+            }
+            None => None,
+        };
+        let ab_splits = env_rules("GATEWAY_AB_SPLITS")?
+            .into_iter()
+            .map(|(pattern, spec)| {
+                let split = spec
+                    .parse()
+                    .with_context(|| format!("invalid GATEWAY_AB_SPLITS rule for {pattern:?}"))?;
+                Ok((pattern, split))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let role_maps = env_rules("GATEWAY_ROLE_MAPS")?
+            .into_iter()
+            .map(|(pattern, spec)| {
+                let map = RoleMap::parse(&spec)
+                    .with_context(|| format!("invalid GATEWAY_ROLE_MAPS rule for {pattern}"))?;
+                Ok((pattern, map))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let system_prompts = env_rules("GATEWAY_SYSTEM_PROMPTS")?;
+        for (pattern, prompt) in &system_prompts {
+            template::validate(prompt)
+                .with_context(|| format!("invalid GATEWAY_SYSTEM_PROMPTS rule for {pattern:?}"))?;
+        }
+        let draining_status =
+            env_opt::<u16>("GATEWAY_DRAINING_STATUS")?.unwrap_or(defaults.draining_status);
+        if !(100..=599).contains(&draining_status) {
+            anyhow::bail!("GATEWAY_DRAINING_STATUS must be an HTTP status code");
+        }
+        Ok(Self {
+            slow_request: env_opt::<u64>("GATEWAY_SLOW_REQUEST_MS")?.map(Duration::from_millis),
+            chat_timeout: env_opt::<u64>("GATEWAY_CHAT_TIMEOUT_MS")?.map(Duration::from_millis),
+            tts_timeout: env_opt::<u64>("GATEWAY_TTS_TIMEOUT_MS")?.map(Duration::from_millis),
+            chat_connect_timeout: env_opt::<u64>("GATEWAY_CHAT_CONNECT_TIMEOUT_MS")?
+                .map(Duration::from_millis),
+            tts_connect_timeout: env_opt::<u64>("GATEWAY_TTS_CONNECT_TIMEOUT_MS")?
+                .map(Duration::from_millis),
+            injected_latency,
+            pretty_errors: env_flag("GATEWAY_PRETTY_ERRORS")?.unwrap_or(defaults.pretty_errors),
+            drain_timeout: env_opt::<u64>("GATEWAY_DRAIN_TIMEOUT_SECS")?
+                .map_or(defaults.drain_timeout, Duration::from_secs),
+            drain_grace: env_opt::<u64>("GATEWAY_DRAIN_GRACE_SECS")?
+                .map_or(defaults.drain_grace, Duration::from_secs),
+            draining_status,
+            draining_body: env_opt("GATEWAY_DRAINING_BODY")?.unwrap_or(defaults.draining_body),
+            strict_fields: env_flag("GATEWAY_STRICT_FIELDS")?.unwrap_or(defaults.strict_fields),
+            merge_same_role: env_flag("GATEWAY_MERGE_SAME_ROLE")?
+                .unwrap_or(defaults.merge_same_role),
+            api_keys: crate::auth::keys_from_env()?,
+            jwt: JwtOptions::from_env()?,
+            max_message_chars: env_opt("GATEWAY_MAX_MESSAGE_CHARS")?,
+            max_attachments: env_opt("GATEWAY_MAX_ATTACHMENTS")?,
+            fallback_message: env_opt("GATEWAY_FALLBACK_MESSAGE")?,
+            empty_reply: EmptyReply::from_env()?,
+            log_sample_rate,
+            dataset,
+            model_allowlist: env_list("GATEWAY_MODEL_ALLOWLIST")?,
+            prompt_suffixes: env_rules("GATEWAY_PROMPT_SUFFIXES")?,
+            system_prompts,
+            max_concurrent_per_client: env_opt("GATEWAY_MAX_CONCURRENT_PER_CLIENT")?,
+            model_rate_limits,
+            admission: match env_opt::<usize>("GATEWAY_MAX_IN_FLIGHT")? {
+                None | Some(0) => None,
+                Some(max_in_flight) => Some(QueuePolicy {
+                    max_in_flight,
+                    queue_size: env_opt("GATEWAY_QUEUE_SIZE")?.unwrap_or(100),
+                    queue_timeout: Duration::from_millis(
+                        env_opt("GATEWAY_QUEUE_TIMEOUT_MS")?.unwrap_or(10_000),
+                    ),
+                }),
+            },
+            key_rate_limit: KeyRateLimit {
+                requests_per_minute: env_rate("GATEWAY_KEY_RATE_LIMIT_RPM")?,
+                tokens_per_minute: env_rate("GATEWAY_KEY_RATE_LIMIT_TPM")?,
+            },
+            ab_splits,
+            reorder_window: env_opt("GATEWAY_REORDER_WINDOW")?,
+            reorder_timeout: env_opt::<u64>("GATEWAY_REORDER_TIMEOUT_MS")?
+                .map_or(defaults.reorder_timeout, Duration::from_millis),
+            coalesce_window: env_opt("GATEWAY_COALESCE_MS")?.map(Duration::from_millis),
+            max_stream_tokens,
+            heartbeat_interval: match env_opt::<u64>("GATEWAY_HEARTBEAT_SECS")? {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => defaults.heartbeat_interval,
+            },
+            heartbeat_style: env_opt("GATEWAY_HEARTBEAT_STYLE")?
+                .unwrap_or(defaults.heartbeat_style),
+            trace_url_base: env_opt("GATEWAY_TRACE_URL_BASE")?,
+            request_id_headers,
+            request_id_output_header,
+            shadow_backend: env_opt("GATEWAY_SHADOW_BACKEND")?,
+            llm_backends: env_list("GATEWAY_LLM_BACKENDS")?,
+            backend_selection: env_opt("GATEWAY_BACKEND_SELECTION")?
+                .unwrap_or(defaults.backend_selection),
+            latency_ema_alpha,
+            readiness_interval: match env_opt::<u64>("GATEWAY_READINESS_INTERVAL_MS")? {
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(ms)),
+                None => defaults.readiness_interval,
+            },
+            breaker: match env_opt::<u32>("GATEWAY_BREAKER_THRESHOLD")? {
+                None | Some(0) => None,
+                Some(threshold) => Some(BreakerPolicy {
+                    threshold,
+                    cooldown: Duration::from_secs(
+                        env_opt("GATEWAY_BREAKER_COOLDOWN_SECS")?.unwrap_or(30),
+                    ),
+                }),
+            },
+            debug_echo: env_flag("GATEWAY_DEBUG_ECHO")?.unwrap_or(defaults.debug_echo),
+            gzip_backends: env_list("GATEWAY_GZIP_BACKENDS")?,
+            upstream_http2: env_flag("GATEWAY_UPSTREAM_HTTP2")?.unwrap_or(defaults.upstream_http2),
+            content_type_overrides: env_rules("GATEWAY_CONTENT_TYPE_OVERRIDES")?,
+            role_maps,
+            injection_mode: env_opt("GATEWAY_INJECTION_MODE")?.unwrap_or(defaults.injection_mode),
+            injection_patterns,
+            max_retries: env_opt("GATEWAY_MAX_RETRIES")?.unwrap_or(defaults.max_retries),
+            retry_backoff: Backoff {
+                base: env_opt::<u64>("GATEWAY_RETRY_BACKOFF_MS")?
+                    .map_or(defaults.retry_backoff.base, Duration::from_millis),
+                max: env_opt::<u64>("GATEWAY_RETRY_BACKOFF_MAX_MS")?
+                    .map_or(defaults.retry_backoff.max, Duration::from_millis),
+            },
+            classify_upstream_errors: env_flag("GATEWAY_CLASSIFY_UPSTREAM_ERRORS")?
+                .unwrap_or(defaults.classify_upstream_errors),
+            retry_budget,
+            max_total_attempts,
+            embeddings_cache_ttl: env_opt::<u64>("GATEWAY_EMBEDDINGS_CACHE_TTL_SECS")?
+                .map_or(defaults.embeddings_cache_ttl, Duration::from_secs),
+            embeddings_cache_size: env_opt("GATEWAY_EMBEDDINGS_CACHE_SIZE")?
+                .unwrap_or(defaults.embeddings_cache_size),
+            chat_cache_ttl: env_opt::<u64>("GATEWAY_CHAT_CACHE_TTL_SECS")?
+                .map_or(defaults.chat_cache_ttl, Duration::from_secs),
+            chat_cache_size: env_opt("GATEWAY_CHAT_CACHE_SIZE")?
+                .unwrap_or(defaults.chat_cache_size),
+            trusted_proxies: env_list("GATEWAY_TRUSTED_PROXIES")?
+                .iter()
+                .map(|proxy| parse_net(proxy))
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+}
+
+/// Parse an optional environment variable; unset or empty means `None`.
+pub fn env_opt<T>(key: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    match std::env::var(key) {
+        Ok(raw) if !raw.trim().is_empty() => raw
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(Into::<anyhow::Error>::into)
+            .with_context(|| format!("invalid {key}={raw:?}")),
+        _ => Ok(None),
+    }
+}
+
+/// Parse an optional boolean flag, accepting `1`/`0` as well as `true`/`false`.
+pub fn env_flag(key: &str) -> anyhow::Result<Option<bool>> {
+    let Some(raw) = env_opt::<String>(key)? else {
+        return Ok(None);
+    };
+    match raw.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(Some(true)),
+        "0" | "false" | "no" | "off" => Ok(Some(false)),
+        _ => anyhow::bail!("invalid {key}={raw:?}; expected 1 or 0"),
+    }
+}
+
+/// Parse an optional comma-separated list, dropping empty entries.
+pub fn env_list(key: &str) -> anyhow::Result<Vec<String>> {
+    env_split(key, ',')
+}
+
+/// Parse an optional list split on `sep`, for entries that may contain commas.
+pub fn env_split(key: &str, sep: char) -> anyhow::Result<Vec<String>> {
+    Ok(env_opt::<String>(key)?
+        .map(|raw| {
+            raw.split(sep)
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// An address range, or a single address as a range of one.
+fn parse_net(raw: &str) -> anyhow::Result<IpNet> {
+    raw.parse::<IpNet>()
+        .or_else(|_| raw.parse::<IpAddr>().map(IpNet::from))
+        .with_context(|| format!("invalid GATEWAY_TRUSTED_PROXIES entry {raw:?}"))
+}
+
+/// Parse an optional per-minute rate, which must be positive.
+fn env_rate(key: &str) -> anyhow::Result<Option<f64>> {
+    match env_opt::<f64>(key)? {
+        Some(rate) if !(rate.is_finite() && rate > 0.0) => {
+            anyhow::bail!("{key} must be a positive number, got {rate}")
+        }
+        rate => Ok(rate),
+    }
+}
+
+/// Parse `pattern=value` rules separated by `;`, in priority order.
+pub fn env_rules(key: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let Some(raw) = env_opt::<String>(key)? else {
+        return Ok(Vec::new());
+    };
+    raw.split(';')
+        .filter(|rule| !rule.trim().is_empty())
+        .map(|rule| {
+            let (pattern, value) = rule
+                .split_once('=')
+                .with_context(|| format!("invalid {key} rule {rule:?}; expected pattern=value"))?;
+            Ok((pattern.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,277 @@
+//! Per-request metadata gathered once by a warp filter and passed to every handler.
+
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use ipnet::IpNet;
+use warp::Filter;
+use warp::http::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::jwt::Claims;
+use crate::serve::PeerAddr;
+
+/// Default for both the headers a request id is read from and the one it is sent as.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+pub const PRIORITY_HEADER: &str = "x-priority";
+pub const SESSION_HEADER: &str = "x-session-id";
+pub const EMIT_TIMING_HEADER: &str = "x-emit-timing";
+
+/// Scheduling hint supplied by the client via `X-Priority`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl FromStr for Priority {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "normal" => Ok(Self::Normal),
+            "high" => Ok(Self::High),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Client-supplied id from the first `GATEWAY_REQUEST_ID_HEADERS` header present
+    /// (`X-Request-Id` by default), or a fresh UUID.
+    pub request_id: String,
+    /// Bearer token from `Authorization`, if any.
+    pub api_key: Option<String>,
+    /// Name of the configured key that matched, set by [`crate::auth::authorized`].
+    pub key_name: Option<String>,
+    /// Claims of an accepted JWT, set by [`crate::auth::authorized`].
+    pub claims: Option<Arc<Claims>>,
+    /// The connection's peer, or when that is a trusted proxy the client it forwarded
+    /// for (see [`client_ip`]). Set by [`request_context`].
+    pub client_ip: Option<IpAddr>,
+    pub priority: Priority,
+    /// `X-Session-Id` on a streaming chat request; subscribers of that session
+    /// receive a copy of the stream.
+    pub session_id: Option<String>,
+    /// W3C `traceparent` from the client, continued by the gateway's spans and
+    /// passed on to backends.
+    pub traceparent: Option<String>,
+    /// `X-Emit-Timing: 1` asks for inter-token timing at the end of a streamed reply.
+    pub emit_timing: bool,
+    pub started: Instant,
+}
+
+impl RequestContext {
+    /// `id_headers` are tried in order for the request id; empty means `X-Request-Id`.
+    pub fn from_headers(headers: &HeaderMap, id_headers: &[String]) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+
+        let request_id = if id_headers.is_empty() {
+            header(REQUEST_ID_HEADER)
+        } else {
+            id_headers.iter().find_map(|name| header(name))
+        };
+
+        Self {
+            request_id: request_id.map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string),
+            api_key: header("authorization")
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .map(|key| key.trim().to_string()),
+            key_name: None,
+            claims: None,
+            client_ip: None,
+            priority: header(PRIORITY_HEADER)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            session_id: header(SESSION_HEADER).map(str::to_string),
+            traceparent: header("traceparent").map(str::to_string),
+            emit_timing: header(EMIT_TIMING_HEADER)
+                .is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// The client's address. Anyone can send `X-Forwarded-For`, so it is only read when
+/// the `peer` is one of the `trusted` proxies: the right-most hop that isn't trusted
+/// itself is the client, as every hop after it was appended by a trusted proxy. With
+/// no such header a trusted peer's `X-Real-IP` names the client.
+pub fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    let peer = peer?;
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .collect();
+    if hops.is_empty() {
+        let real_ip = headers.get("x-real-ip").and_then(|v| v.to_str().ok());
+        return real_ip.and_then(|ip| ip.trim().parse().ok()).or(Some(peer));
+    }
+    let mut nearest = peer;
+    for hop in hops.iter().rev() {
+        // A hop that isn't an address can't be vouched for; stop at the last one that was
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        if !is_trusted(&ip) {
+            return Some(ip);
+        }
+        nearest = ip;
+    }
+    Some(nearest)
+}
+
+/// Build a [`RequestContext`] for every request; never rejects.
+pub fn request_context() -> impl Filter<Extract = (RequestContext,), Error = Infallible> + Clone {
+    warp::header::headers_cloned()
+        .and(warp::ext::optional::<PeerAddr>())
+        .map(|headers: HeaderMap, peer: Option<PeerAddr>| {
+            let config = crate::CONFIG.get();
+            let id_headers = config.map_or(&[][..], |config| &config.request_id_headers);
+            let trusted = config.map_or(&[][..], |config| &config.trusted_proxies);
+            let mut ctx = RequestContext::from_headers(&headers, id_headers);
+            ctx.client_ip = client_ip(&headers, peer.map(|PeerAddr(addr)| addr.ip()), trusted);
+            ctx
+        })
+}
+
+/// The header a request id is forwarded upstream and echoed to the client under
+/// (`GATEWAY_REQUEST_ID_OUTPUT_HEADER`).
+pub fn request_id_header() -> &'static str {
+    crate::CONFIG
+        .get()
+        .map_or(REQUEST_ID_HEADER, |config| &config.request_id_output_header)
+}
+
+/// Echo `request_id` on `reply` under `name`.
+pub fn echo_request_id(
+    mut reply: warp::reply::Response,
+    name: &str,
+    request_id: &str,
+) -> warp::reply::Response {
+    let name = HeaderName::from_bytes(name.as_bytes());
+    if let (Ok(name), Ok(value)) = (name, HeaderValue::from_str(request_id)) {
+        reply.headers_mut().insert(name, value);
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_context_populated_from_headers() {
+        let ctx = warp::test::request()
+            .header("x-request-id", "req-123")
+            .header("authorization", "Bearer sk-test")
+            .header("x-forwarded-for", "203.0.113.7, 10.0.0.1")
+            .header("x-priority", "HIGH")
+            .header("x-session-id", "demo")
+            .header("x-emit-timing", "1")
+            .extension(PeerAddr("198.51.100.4:50000".parse().unwrap()))
+            .filter(&request_context())
+            .await
+            .unwrap();
+
+        assert_eq!(ctx.request_id, "req-123");
+        assert_eq!(ctx.api_key.as_deref(), Some("sk-test"));
+        // No trusted proxies: the forwarded address is ignored for the peer's
+        assert_eq!(ctx.client_ip, Some("198.51.100.4".parse().unwrap()));
+        assert_eq!(ctx.priority, Priority::High);
+        assert_eq!(ctx.session_id.as_deref(), Some("demo"));
+        assert!(ctx.emit_timing);
+    }
+
+    #[test]
+    fn test_forwarded_for_believed_only_from_trusted_proxies() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let proxy = Some("10.0.0.1".parse().unwrap());
+        let forwarded = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", HeaderValue::from_static(value));
+            headers
+        };
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+
+        // A client talking to the gateway directly can't pick its own address
+        let spoofed = forwarded("192.0.2.99");
+        assert_eq!(
+            client_ip(&spoofed, ip("198.51.100.4"), &trusted),
+            ip("198.51.100.4")
+        );
+        // Behind proxies, the right-most untrusted hop; the client's own prefix is ignored
+        let chain = forwarded("192.0.2.99, 203.0.113.7, 10.0.0.2");
+        assert_eq!(client_ip(&chain, proxy, &trusted), ip("203.0.113.7"));
+        assert_eq!(
+            client_ip(&forwarded("10.0.0.3, 10.0.0.2"), proxy, &trusted),
+            ip("10.0.0.3")
+        );
+        assert_eq!(
+            client_ip(&forwarded("junk, 10.0.0.2"), proxy, &trusted),
+            ip("10.0.0.2")
+        );
+
+        let mut real_ip = HeaderMap::new();
+        real_ip.insert("x-real-ip", HeaderValue::from_static("192.0.2.1"));
+        assert_eq!(client_ip(&real_ip, proxy, &trusted), ip("192.0.2.1"));
+        assert_eq!(
+            client_ip(&real_ip, ip("198.51.100.4"), &trusted),
+            ip("198.51.100.4")
+        );
+        assert_eq!(client_ip(&HeaderMap::new(), None, &trusted), None);
+    }
+
+    #[test]
+    fn test_alternate_request_id_header_echoed_under_output_name() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("ignored"));
+        headers.insert("x-correlation-id", HeaderValue::from_static("corr-7"));
+        let id_headers = ["traceparent".to_string(), "x-correlation-id".to_string()];
+        let ctx = RequestContext::from_headers(&headers, &id_headers);
+        assert_eq!(ctx.request_id, "corr-7");
+
+        let reply = echo_request_id(
+            warp::reply::Reply::into_response("ok"),
+            "x-correlation-id",
+            &ctx.request_id,
+        );
+        assert_eq!(reply.headers()["x-correlation-id"], "corr-7");
+        assert!(!reply.headers().contains_key("x-request-id"));
+    }
+
+    #[tokio::test]
+    async fn test_context_defaults_without_headers() {
+        let ctx = warp::test::request()
+            .header("x-real-ip", "192.0.2.1")
+            .filter(&request_context())
+            .await
+            .unwrap();
+
+        assert_eq!(ctx.request_id.len(), 36);
+        assert_eq!(ctx.api_key, None);
+        assert_eq!(ctx.client_ip, None);
+        assert_eq!(ctx.priority, Priority::Normal);
+        assert_eq!(ctx.session_id, None);
+        assert!(!ctx.emit_timing);
+    }
+}
@@ -0,0 +1,129 @@
+//! `POST /v1/chat/completions/count-tokens`: a chat request's prompt token cost,
+//! without generating.
+//!
+//! The request goes through the same prompt rules and model routing as a completion,
+//! then to the chosen backend's `/v1/tokenize`, which counts with the tokenizer behind
+//! its `usage` figures. The reply is `{"prompt_tokens": N}`.
+
+use std::convert::Infallible;
+
+use reqwest::Client;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::context::{self, RequestContext};
+use crate::proxy::{self, ProxyReply};
+use crate::{ChatCompletionRequest, apply_prompt_rules, auth, chat_target};
+
+/// The tokenize endpoint beside a backend's chat completions URL.
+pub fn tokenize_url(chat_target: &str) -> String {
+    let base = chat_target
+        .strip_suffix("/chat/completions")
+        .unwrap_or(chat_target.trim_end_matches('/'));
+    format!("{base}/tokenize")
+}
+
+pub fn route(
+    client: Client,
+    keys: &'static [auth::ApiKey],
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    warp::path!("v1" / "chat" / "completions" / "count-tokens")
+        .and(warp::post())
+        .and(auth::authorized(keys))
+        .and(warp::body::json())
+        .and_then(
+            move |ctx: RequestContext, mut body: ChatCompletionRequest| {
+                let client = client.clone();
+                async move {
+                    apply_prompt_rules(&mut body);
+                    let reply = match chat_target(&body) {
+                        Some(target) => {
+                            forward(&client, &tokenize_url(target.url()), &body, &ctx.request_id)
+                                .await
+                        }
+                        None => {
+                            let error = format!("no route configured for model '{}'", body.model);
+                            proxy::error_reply(error, StatusCode::BAD_REQUEST)
+                        }
+                    };
+                    Ok::<_, Infallible>(reply.into_response())
+                }
+            },
+        )
+}
+
+pub async fn forward(
+    client: &Client,
+    url: &str,
+    body: &ChatCompletionRequest,
+    request_id: &str,
+) -> ProxyReply {
+    let resp = client
+        .post(url)
+        .header(context::request_id_header(), request_id)
+        .json(body)
+        .send()
+        .await;
+    match resp {
+        Ok(resp) => proxy::relay(resp, "application/json", None).await,
+        Err(e) => proxy::error_reply(
+            format!("llm-node unreachable: {e}"),
+            StatusCode::BAD_GATEWAY,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::ChatMessage;
+
+    #[test]
+    fn test_tokenize_url_beside_chat_endpoint() {
+        assert_eq!(
+            tokenize_url("http://localhost:9000/v1/chat/completions"),
+            "http://localhost:9000/v1/tokenize"
+        );
+        assert_eq!(tokenize_url("http://gpu:9000/"), "http://gpu:9000/tokenize");
+    }
+
+    #[tokio::test]
+    async fn test_count_forwarded_to_tokenizer() {
+        // Counts one token per message, like a tokenizer would report in `usage`
+        let backend = warp::path!("v1" / "tokenize")
+            .and(warp::body::json())
+            .map(|body: Value| {
+                let messages = body["messages"].as_array().map_or(0, Vec::len);
+                warp::reply::json(&json!({ "prompt_tokens": messages }))
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(backend).incoming(listener).run());
+
+        let body = ChatCompletionRequest {
+            model: "m".into(),
+            messages: vec![
+                ChatMessage {
+                    role: "system".into(),
+                    content: "Be brief.".into(),
+                },
+                ChatMessage {
+                    role: "user".into(),
+                    content: "hi".into(),
+                },
+            ],
+            ..Default::default()
+        };
+        let url = tokenize_url(&format!("http://{addr}/v1/chat/completions"));
+        let route = warp::any().then(move || {
+            let (url, body) = (url.clone(), body.clone());
+            async move { forward(&Client::new(), &url, &body, "req-1").await }
+        });
+        let resp = warp::test::request().reply(&route).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let counted: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(counted, json!({ "prompt_tokens": 2 }));
+    }
+}
@@ -0,0 +1,258 @@
+//! Capture of real chat traffic as a JSONL dataset (`GATEWAY_DATASET_PATH`), for
+//! building eval sets.
+//!
+//! Each completed, successful, non-streamed chat exchange may be appended as one
+//! record: the request as forwarded (after prompt rules) and the JSON response the
+//! client received. Records are handed to a writer thread, so capture adds no latency;
+//! a sampling rate thins them and optional redaction masks email addresses and phone
+//! numbers in every string. When the file would exceed its size limit it is renamed
+//! to `<path>.1` (replacing the previous one) and a fresh file is started.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use regex_automata::meta::Regex;
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::{ChatCompletionRequest, config, request_log};
+
+const EMAIL: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+const PHONE: &str =
+    r"\+[0-9]{1,3}[ .-]?[0-9][0-9 .-]{6,}[0-9]|\(?[0-9]{3}\)?[ .-]?[0-9]{3}[ .-][0-9]{4}";
+/// Replacement for each pattern above, by index.
+const MASKS: [&str; 2] = ["[email]", "[phone]"];
+
+static PII: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new_many(&[EMAIL, PHONE]).expect("valid PII patterns"));
+
+static RECORDER: LazyLock<Option<Recorder>> = LazyLock::new(|| {
+    config()
+        .dataset
+        .as_ref()
+        .map(|options| Recorder::start(options.path.clone(), options.max_bytes))
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetOptions {
+    pub path: PathBuf,
+    /// Fraction (0.0–1.0) of exchanges recorded.
+    pub sample_rate: f64,
+    /// Mask email addresses and phone numbers.
+    pub redact: bool,
+    /// Size at which the file is rotated.
+    pub max_bytes: u64,
+}
+
+pub fn enabled() -> bool {
+    config().dataset.is_some()
+}
+
+/// Queue a record of `request` and its `response` body, subject to sampling. Responses
+/// that are not JSON (e.g. MessagePack) are skipped.
+pub fn record(request_id: &str, request: &ChatCompletionRequest, response: &[u8]) {
+    let (Some(options), Some(recorder)) = (config().dataset.as_ref(), RECORDER.as_ref()) else {
+        return;
+    };
+    if !request_log::should_sample(options.sample_rate) {
+        return;
+    }
+    if let Some(line) = entry(request_id, request, response, options.redact) {
+        recorder.send(line);
+    }
+}
+
+/// One JSONL line for the exchange, or `None` if `response` is not JSON.
+pub fn entry(
+    request_id: &str,
+    request: &ChatCompletionRequest,
+    response: &[u8],
+    redact: bool,
+) -> Option<String> {
+    let response: Value = serde_json::from_slice(response).ok()?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut record = json!({
+        "timestamp": timestamp,
+        "request_id": request_id,
+        "request": request,
+        "response": response,
+    });
+    if redact {
+        for field in ["request", "response"] {
+            redact_strings(&mut record[field]);
+        }
+    }
+    Some(record.to_string())
+}
+
+fn redact_strings(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = redact_pii(s),
+        Value::Array(items) => items.iter_mut().for_each(redact_strings),
+        Value::Object(fields) => fields.values_mut().for_each(redact_strings),
+        _ => {}
+    }
+}
+
+fn redact_pii(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for found in PII.find_iter(text) {
+        out.push_str(&text[last..found.start()]);
+        out.push_str(MASKS[found.pattern().as_usize()]);
+        last = found.end();
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// Appends lines to a dataset file on a background thread. Dropping it flushes the
+/// queue and waits for the writer.
+pub struct Recorder {
+    lines: Option<Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn start(path: PathBuf, max_bytes: u64) -> Self {
+        let (tx, rx) = mpsc::channel::<String>();
+        let writer = thread::spawn(move || {
+            let mut file = None;
+            for line in rx {
+                if let Err(e) = append(&path, max_bytes, &mut file, &line) {
+                    warn!("Failed to write dataset record to {}: {e}", path.display());
+                    file = None;
+                }
+            }
+        });
+        Self {
+            lines: Some(tx),
+            writer: Some(writer),
+        }
+    }
+
+    pub fn send(&self, line: String) {
+        if let Some(lines) = &self.lines {
+            let _ = lines.send(line);
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.lines.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// The file rotated out of the way once `path` is full.
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+fn append(
+    path: &Path,
+    max_bytes: u64,
+    file: &mut Option<(File, u64)>,
+    line: &str,
+) -> std::io::Result<()> {
+    let needed = line.len() as u64 + 1;
+    if file.is_none() {
+        let size = fs::metadata(path).map_or(0, |m| m.len());
+        let opened = OpenOptions::new().create(true).append(true).open(path)?;
+        *file = Some((opened, size));
+    }
+    if file
+        .as_ref()
+        .is_some_and(|(_, size)| *size > 0 && size + needed > max_bytes)
+    {
+        file.take();
+        fs::rename(path, rotated_path(path))?;
+        let opened = OpenOptions::new().create(true).append(true).open(path)?;
+        *file = Some((opened, 0));
+    }
+    let (file, size) = file.as_mut().expect("opened above");
+    writeln!(file, "{line}")?;
+    *size += needed;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatMessage;
+
+    fn request(content: &str) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "m".into(),
+            messages: vec![ChatMessage {
+                role: "user".into(),
+                content: content.into(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("gateway-dataset-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_completed_exchange_written_as_jsonl() {
+        let path = temp_path();
+        let response = json!({"choices": [{"message": {"role": "assistant", "content": "Hi!"}}]});
+        let recorder = Recorder::start(path.clone(), 1 << 20);
+        let line = entry(
+            "req-1",
+            &request("Mail me at ada@example.com or call 555-123-4567"),
+            response.to_string().as_bytes(),
+            true,
+        )
+        .unwrap();
+        recorder.send(line);
+        drop(recorder);
+
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let record: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["request_id"], "req-1");
+        assert_eq!(
+            record["request"]["messages"][0]["content"],
+            "Mail me at [email] or call [phone]"
+        );
+        assert_eq!(record["response"], response);
+        assert!(record["timestamp"].as_u64().is_some());
+
+        assert!(entry("req-2", &request("hi"), b"\x81\xa1a\x01", false).is_none());
+    }
+
+    #[test]
+    fn test_file_rotated_by_size() {
+        let path = temp_path();
+        let recorder = Recorder::start(path.clone(), 25);
+        for line in ["first record", "second record", "third"] {
+            recorder.send(line.to_string());
+        }
+        drop(recorder);
+
+        let current = fs::read_to_string(&path).unwrap();
+        let previous = fs::read_to_string(rotated_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(rotated_path(&path)).unwrap();
+        assert_eq!(previous, "first record\n");
+        assert_eq!(current, "second record\nthird\n");
+    }
+}
@@ -0,0 +1,99 @@
+//! `GET /debug/echo`, enabled by `GATEWAY_DEBUG_ECHO=1`.
+//!
+//! Reflects what the gateway actually received, which helps diagnose CORS and auth
+//! header problems from the browser without access to server logs. Credentials are
+//! replaced with `[redacted]` so the response can be shared safely.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use warp::http::{HeaderMap, Method};
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+const REDACTED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie"];
+
+#[derive(Debug, Serialize)]
+struct Echo {
+    method: String,
+    path: String,
+    headers: BTreeMap<String, String>,
+}
+
+fn echo(method: &Method, path: &str, headers: &HeaderMap) -> Echo {
+    let mut echoed = BTreeMap::new();
+    for (name, value) in headers {
+        let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+            "[redacted]".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        echoed
+            .entry(name.to_string())
+            .and_modify(|joined: &mut String| {
+                joined.push_str(", ");
+                joined.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    Echo {
+        method: method.to_string(),
+        path: path.to_string(),
+        headers: echoed,
+    }
+}
+
+/// The echo route; answers `404` like any unknown path unless `enabled`.
+pub fn echo_route(
+    enabled: bool,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("debug" / "echo")
+        .and(warp::get())
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(warp::header::headers_cloned())
+        .map(|method: Method, path: FullPath, headers: HeaderMap| {
+            warp::reply::json(&echo(&method, path.as_str(), &headers))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::StatusCode;
+
+    fn request() -> warp::test::RequestBuilder {
+        warp::test::request()
+            .method("GET")
+            .path("/debug/echo")
+            .header("origin", "http://localhost:3000")
+            .header("authorization", "Bearer sk-secret")
+    }
+
+    #[tokio::test]
+    async fn test_echo_reflects_headers_and_redacts_auth() {
+        let resp = request().reply(&echo_route(true)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["method"], "GET");
+        assert_eq!(body["path"], "/debug/echo");
+        assert_eq!(body["headers"]["origin"], "http://localhost:3000");
+        assert_eq!(body["headers"]["authorization"], "[redacted]");
+        assert!(!String::from_utf8_lossy(resp.body()).contains("sk-secret"));
+    }
+
+    #[tokio::test]
+    async fn test_echo_hidden_when_disabled() {
+        let resp = request().reply(&echo_route(false)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
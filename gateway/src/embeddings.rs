@@ -0,0 +1,349 @@
+//! `POST /v1/embeddings`, proxied to llm-node with a response cache.
+//!
+//! Embeddings are deterministic, so each input string's vector is cached under
+//! `(model, other request options, input)` for `GATEWAY_EMBEDDINGS_CACHE_TTL_SECS`,
+//! holding at most `GATEWAY_EMBEDDINGS_CACHE_SIZE` vectors (oldest evicted first). For
+//! array inputs only the uncached items are sent upstream and the response is assembled
+//! in the original order. Token-array inputs are forwarded uncached.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::context::RequestContext;
+use crate::{auth, config, proxy};
+
+pub static CACHE: LazyLock<EmbeddingsCache> = LazyLock::new(|| {
+    EmbeddingsCache::new(
+        config().embeddings_cache_ttl,
+        config().embeddings_cache_size,
+    )
+});
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    model: String,
+    options: String,
+    input: String,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, (Instant, Value)>,
+    /// Keys in insertion order, for evicting the oldest when full.
+    order: VecDeque<CacheKey>,
+}
+
+pub struct EmbeddingsCache {
+    ttl: Duration,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl EmbeddingsCache {
+    /// A `capacity` of 0 disables caching.
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Value> {
+        let state = self.state.lock().unwrap();
+        // Expired entries stay until overwritten or evicted
+        let (stored, embedding) = state.entries.get(key)?;
+        (stored.elapsed() < self.ttl).then(|| embedding.clone())
+    }
+
+    fn insert(&self, key: CacheKey, embedding: Value) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let fresh = (Instant::now(), embedding);
+        if state.entries.insert(key.clone(), fresh).is_none() {
+            state.order.push_back(key);
+        }
+        while state.entries.len() > self.capacity {
+            let Some(oldest) = state.order.pop_front() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsRequest {
+    model: String,
+    input: Input,
+    /// Everything else (e.g. `dimensions`), which changes the vectors returned.
+    #[serde(flatten)]
+    options: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Input {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// `POST /v1/embeddings` proxying to the given llm-node URL.
+pub fn route(
+    client: Client,
+    target: String,
+    cache: &'static EmbeddingsCache,
+    keys: &'static [auth::ApiKey],
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    warp::path!("v1" / "embeddings")
+        .and(warp::post())
+        .and(auth::authorized(keys))
+        .and(warp::body::json())
+        .and_then(move |_ctx: RequestContext, body: Value| {
+            let (client, target) = (client.clone(), target.clone());
+            async move { Ok::<_, Infallible>(handle(&client, &target, cache, body).await) }
+        })
+}
+
+async fn handle(
+    client: &Client,
+    target: &str,
+    cache: &EmbeddingsCache,
+    body: Value,
+) -> warp::reply::Response {
+    let Ok(req) = serde_json::from_value::<EmbeddingsRequest>(body.clone()) else {
+        return forward(client, target, &body).await;
+    };
+    let items = match req.input {
+        Input::One(input) => vec![input],
+        Input::Many(inputs) => inputs,
+    };
+    let options = Value::Object(req.options.clone()).to_string();
+    let key = |input: &str| CacheKey {
+        model: req.model.clone(),
+        options: options.clone(),
+        input: input.to_string(),
+    };
+
+    let mut embeddings: Vec<Option<Value>> = items.iter().map(|i| cache.get(&key(i))).collect();
+    let mut misses: Vec<&str> = Vec::new();
+    for (item, embedding) in items.iter().zip(&embeddings) {
+        if embedding.is_none() && !misses.contains(&item.as_str()) {
+            misses.push(item);
+        }
+    }
+
+    let mut usage = json!({ "prompt_tokens": 0, "total_tokens": 0 });
+    if !misses.is_empty() {
+        let mut upstream = req.options;
+        upstream.insert("model".into(), json!(req.model));
+        upstream.insert("input".into(), json!(misses));
+        let fetched = match fetch(client, target, &Value::Object(upstream)).await {
+            Ok(fetched) => fetched,
+            Err(reply) => return reply,
+        };
+        for (index, embedding) in fetched.embeddings {
+            let Some(input) = misses.get(index) else {
+                continue;
+            };
+            cache.insert(key(input), embedding.clone());
+            for (item, slot) in items.iter().zip(&mut embeddings) {
+                if item == input {
+                    *slot = Some(embedding.clone());
+                }
+            }
+        }
+        usage = fetched.usage;
+    }
+
+    let Some(embeddings) = embeddings.into_iter().collect::<Option<Vec<_>>>() else {
+        let error = "llm-node returned fewer embeddings than inputs".to_string();
+        return proxy::error_reply(error, StatusCode::BAD_GATEWAY).into_response();
+    };
+    let data: Vec<Value> = embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| {
+            json!({ "object": "embedding", "index": index, "embedding": embedding })
+        })
+        .collect();
+    warp::reply::json(&json!({
+        "object": "list",
+        "data": data,
+        "model": req.model,
+        "usage": usage,
+    }))
+    .into_response()
+}
+
+struct Fetched {
+    /// `(index into the upstream input, embedding)` pairs.
+    embeddings: Vec<(usize, Value)>,
+    usage: Value,
+}
+
+/// Request embeddings upstream; failures come back as the reply to send.
+async fn fetch(
+    client: &Client,
+    target: &str,
+    body: &Value,
+) -> Result<Fetched, warp::reply::Response> {
+    let resp = match client.post(target).json(body).send().await {
+        Ok(resp) if resp.status().is_success() => resp,
+        Ok(resp) => {
+            return Err(proxy::relay(resp, "application/json", None)
+                .await
+                .into_response());
+        }
+        Err(e) => {
+            let error = format!("llm-node unreachable: {e}");
+            return Err(proxy::error_reply(error, StatusCode::BAD_GATEWAY).into_response());
+        }
+    };
+    let Ok(mut parsed) = resp.json::<Value>().await else {
+        let error = "llm-node returned an invalid embeddings response".to_string();
+        return Err(proxy::error_reply(error, StatusCode::BAD_GATEWAY).into_response());
+    };
+    let embeddings = parsed["data"]
+        .as_array_mut()
+        .map(std::mem::take)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|mut item| {
+            let index = item["index"].as_u64()? as usize;
+            Some((index, item["embedding"].take()))
+        })
+        .collect();
+    Ok(Fetched {
+        embeddings,
+        usage: parsed["usage"].take(),
+    })
+}
+
+/// Pass a request the cache cannot key through unchanged.
+async fn forward(client: &Client, target: &str, body: &Value) -> warp::reply::Response {
+    match client.post(target).json(body).send().await {
+        Ok(resp) => proxy::relay(resp, "application/json", None)
+            .await
+            .into_response(),
+        Err(e) => {
+            let error = format!("llm-node unreachable: {e}");
+            proxy::error_reply(error, StatusCode::BAD_GATEWAY).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// A backend embedding each input as `[length]`, recording the inputs it was sent.
+    async fn backend() -> (String, Arc<Mutex<Vec<Value>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let route = warp::body::json().map(move |body: Value| {
+            log.lock().unwrap().push(body["input"].clone());
+            let data: Vec<Value> = body["input"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .map(|(i, input)| {
+                    let embedding = [input.as_str().unwrap().len()];
+                    json!({ "index": i, "embedding": embedding })
+                })
+                .collect();
+            warp::reply::json(&json!({ "data": data, "usage": { "prompt_tokens": 1 } }))
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/embeddings", listener.local_addr().unwrap());
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        (url, seen)
+    }
+
+    async fn embed(
+        filter: &(impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + 'static),
+        input: Value,
+    ) -> Value {
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/v1/embeddings")
+            .json(&json!({ "model": "embed-small", "input": input }))
+            .reply(filter)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        serde_json::from_slice(resp.body()).unwrap()
+    }
+
+    fn filter(
+        url: String,
+    ) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + 'static {
+        let cache = Box::leak(Box::new(EmbeddingsCache::new(Duration::from_secs(60), 100)));
+        route(Client::new(), url, cache, &[])
+    }
+
+    #[tokio::test]
+    async fn test_repeated_request_hits_backend_once() {
+        let (url, seen) = backend().await;
+        let filter = filter(url);
+
+        let first = embed(&filter, json!("hello")).await;
+        let second = embed(&filter, json!("hello")).await;
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert_eq!(first["data"], second["data"]);
+        assert_eq!(second["data"][0]["embedding"], json!([5]));
+    }
+
+    #[tokio::test]
+    async fn test_array_input_fetches_only_uncached_items() {
+        let (url, seen) = backend().await;
+        let filter = filter(url);
+
+        embed(&filter, json!(["a", "bb"])).await;
+        let mixed = embed(&filter, json!(["bb", "ccc", "a", "ccc"])).await;
+        assert_eq!(*seen.lock().unwrap(), [json!(["a", "bb"]), json!(["ccc"])]);
+
+        let vectors: Vec<&Value> = mixed["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| &d["embedding"])
+            .collect();
+        assert_eq!(
+            vectors,
+            [&json!([2]), &json!([3]), &json!([1]), &json!([3])]
+        );
+        assert_eq!(mixed["data"][3]["index"], 3);
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest_and_expires() {
+        let cache = EmbeddingsCache::new(Duration::from_secs(60), 2);
+        let key = |input: &str| CacheKey {
+            model: "m".into(),
+            options: "{}".into(),
+            input: input.into(),
+        };
+        for input in ["a", "b", "c"] {
+            cache.insert(key(input), json!([1]));
+        }
+        assert!(cache.get(&key("a")).is_none());
+        assert!(cache.get(&key("c")).is_some());
+
+        let expired = EmbeddingsCache::new(Duration::ZERO, 2);
+        expired.insert(key("a"), json!([1]));
+        assert!(expired.get(&key("a")).is_none());
+    }
+}
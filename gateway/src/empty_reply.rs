@@ -0,0 +1,145 @@
+//! Handling of chat replies whose assistant content is empty (`GATEWAY_EMPTY_REPLY`).
+//!
+//! Backends occasionally answer `200` with `""` (or only whitespace) as the content,
+//! which many clients render as a blank bubble. For non-streamed replies the gateway
+//! can pass those through (the default), fill in a placeholder, or turn the reply into
+//! a `502`. Choices carrying `tool_calls` legitimately have no content and are left
+//! alone.
+
+use anyhow::{Context, bail};
+use serde_json::Value;
+
+use crate::config::env_opt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EmptyReply {
+    /// Forward the reply unchanged.
+    #[default]
+    Pass,
+    /// Replace empty content with this text.
+    Placeholder(String),
+    /// Answer `502` instead.
+    Error,
+}
+
+impl EmptyReply {
+    /// `GATEWAY_EMPTY_REPLY` (`pass`, `placeholder` or `error`), with the text from
+    /// `GATEWAY_EMPTY_REPLY_PLACEHOLDER` for `placeholder`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match env_opt::<String>("GATEWAY_EMPTY_REPLY")?.as_deref() {
+            None | Some("pass") => Ok(Self::Pass),
+            Some("error") => Ok(Self::Error),
+            Some("placeholder") => env_opt("GATEWAY_EMPTY_REPLY_PLACEHOLDER")?
+                .map(Self::Placeholder)
+                .context("GATEWAY_EMPTY_REPLY=placeholder needs GATEWAY_EMPTY_REPLY_PLACEHOLDER"),
+            Some(other) => {
+                bail!("unknown GATEWAY_EMPTY_REPLY {other:?}; expected pass, placeholder or error")
+            }
+        }
+    }
+}
+
+/// The assistant messages of `body`'s choices that have no text or tool calls.
+fn empty_messages(body: &mut Value) -> Vec<&mut Value> {
+    let Some(choices) = body["choices"].as_array_mut() else {
+        return Vec::new();
+    };
+    choices
+        .iter_mut()
+        .map(|choice| &mut choice["message"])
+        .filter(|message| {
+            let blank = message["content"]
+                .as_str()
+                .is_none_or(|content| content.trim().is_empty());
+            blank && message["tool_calls"].is_null()
+        })
+        .collect()
+}
+
+/// Apply `policy` to a successful JSON chat reply: the bytes to send, or the `502`
+/// error message. Bodies that aren't JSON chat completions pass through untouched.
+pub fn handle(policy: &EmptyReply, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    if *policy == EmptyReply::Pass {
+        return Ok(bytes);
+    }
+    let Ok(mut body) = serde_json::from_slice::<Value>(&bytes) else {
+        return Ok(bytes);
+    };
+    let empty = empty_messages(&mut body);
+    if empty.is_empty() {
+        return Ok(bytes);
+    }
+    tracing::warn!(
+        "Backend returned {} empty assistant message(s)",
+        empty.len()
+    );
+    match policy {
+        EmptyReply::Pass => Ok(bytes),
+        EmptyReply::Error => Err("backend returned an empty assistant message".into()),
+        EmptyReply::Placeholder(text) => {
+            for message in empty {
+                message["content"] = Value::String(text.clone());
+            }
+            Ok(serde_json::to_vec(&body).unwrap_or(bytes))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(contents: &[&str]) -> Vec<u8> {
+        let choices: Vec<Value> = contents
+            .iter()
+            .enumerate()
+            .map(|(index, content)| {
+                serde_json::json!({
+                    "index": index,
+                    "message": { "role": "assistant", "content": content },
+                })
+            })
+            .collect();
+        serde_json::to_vec(&serde_json::json!({ "id": "x", "choices": choices })).unwrap()
+    }
+
+    #[test]
+    fn test_empty_content_replaced_with_placeholder() {
+        let policy = EmptyReply::Placeholder("(no reply)".into());
+        let out = handle(&policy, reply(&["", "Hi", "  \n"])).unwrap();
+        let out: Value = serde_json::from_slice(&out).unwrap();
+        let contents: Vec<&str> = out["choices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["message"]["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents, ["(no reply)", "Hi", "(no reply)"]);
+
+        // Non-empty replies and other formats are forwarded byte for byte
+        let full = reply(&["Hi"]);
+        assert_eq!(handle(&policy, full.clone()).unwrap(), full);
+        assert_eq!(
+            handle(&policy, b"\x81\xa2id".to_vec()).unwrap(),
+            b"\x81\xa2id"
+        );
+    }
+
+    #[test]
+    fn test_empty_content_rejected_in_error_mode() {
+        let err = handle(&EmptyReply::Error, reply(&[""])).unwrap_err();
+        assert_eq!(err, "backend returned an empty assistant message");
+        assert!(handle(&EmptyReply::Error, reply(&["Hi"])).is_ok());
+
+        let tool_call = serde_json::json!({ "choices": [{ "message": {
+            "role": "assistant", "content": null, "tool_calls": [{ "id": "call_1" }],
+        }}]});
+        let tool_call = serde_json::to_vec(&tool_call).unwrap();
+        assert!(handle(&EmptyReply::Error, tool_call).is_ok());
+
+        assert_eq!(
+            handle(&EmptyReply::Pass, reply(&[""])).unwrap(),
+            reply(&[""])
+        );
+    }
+}
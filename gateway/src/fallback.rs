@@ -0,0 +1,78 @@
+//! Graceful degradation when the chat backend cannot serve a request.
+//!
+//! With `GATEWAY_FALLBACK_MESSAGE` set, backend failures are answered with a `200`
+//! chat completion carrying that message, so simple clients keep working; otherwise
+//! the gateway replies `502` with the error.
+
+use serde_json::json;
+use warp::http::StatusCode;
+
+use crate::proxy::{self, ProxyReply};
+
+/// A well-formed `chat.completion` whose only choice is the canned `message`.
+pub fn canned_completion(model: &str, message: &str) -> serde_json::Value {
+    json!({
+        "id": uuid::Uuid::new_v4().to_string(),
+        "object": "chat.completion",
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": message },
+            "finish_reason": "stop",
+        }],
+    })
+}
+
+/// Reply for a failed backend call: the canned completion if configured, else `502`.
+pub fn backend_failure(error: String, model: &str, fallback: Option<&str>) -> ProxyReply {
+    match fallback {
+        Some(message) => {
+            tracing::warn!("{error}; serving fallback response");
+            let body = serde_json::to_vec(&canned_completion(model, message)).unwrap_or_default();
+            proxy::json_reply(body, StatusCode::OK)
+        }
+        None => proxy::error_reply(error, StatusCode::BAD_GATEWAY),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    async fn send_to_closed_port() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let err = reqwest::get(format!("http://{addr}")).await.unwrap_err();
+        format!("llm-node unreachable: {err}")
+    }
+
+    async fn reply(
+        fallback: Option<&'static str>,
+    ) -> warp::http::Response<warp::hyper::body::Bytes> {
+        let error = send_to_closed_port().await;
+        let filter = warp::any().map(move || backend_failure(error.clone(), "qwen3", fallback));
+        warp::test::request().reply(&filter).await
+    }
+
+    #[tokio::test]
+    async fn test_backend_down_serves_canned_response_when_enabled() {
+        let resp = reply(Some("Please retry shortly")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let value: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(value["model"], "qwen3");
+        assert_eq!(value["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(
+            value["choices"][0]["message"]["content"],
+            "Please retry shortly"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backend_down_is_502_by_default() {
+        let resp = reply(None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    }
+}
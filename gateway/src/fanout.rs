@@ -0,0 +1,198 @@
+//! Fan-out of streaming chat completions to passive viewers.
+//!
+//! A streaming chat request sent with `X-Session-Id: <session>` publishes every event
+//! to that session, and `GET /v1/chat/completions/subscribe/{session}` streams the same
+//! events to any number of subscribers over a broadcast channel. Subscribers only see
+//! events published after they join; their stream ends when the producer finishes.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{LazyLock, Mutex};
+
+use futures_util::stream;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+use warp::sse::Event;
+use warp::{Filter, Rejection, Reply};
+
+use crate::auth::{self, ApiKey};
+use crate::context::RequestContext;
+
+/// Events buffered per session; a subscriber further behind skips ahead.
+const CAPACITY: usize = 256;
+
+pub static SESSIONS: LazyLock<Sessions> = LazyLock::new(Sessions::default);
+
+struct Session {
+    tx: broadcast::Sender<String>,
+    producers: usize,
+}
+
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    fn with_session<T>(&self, id: &str, f: impl FnOnce(&mut Session) -> T) -> T {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.entry(id.to_string()).or_insert_with(|| Session {
+            tx: broadcast::channel(CAPACITY).0,
+            producers: 0,
+        });
+        f(session)
+    }
+
+    /// Start producing into session `id`; the session closes when the last
+    /// [`Publisher`] is dropped.
+    pub fn publish(&'static self, id: &str) -> Publisher {
+        let tx = self.with_session(id, |session| {
+            session.producers += 1;
+            session.tx.clone()
+        });
+        Publisher {
+            sessions: self,
+            id: id.to_string(),
+            tx,
+        }
+    }
+
+    /// Join session `id`, which need not have a producer yet.
+    pub fn subscribe(&'static self, id: &str) -> Subscription {
+        let rx = self.with_session(id, |session| session.tx.subscribe());
+        Subscription {
+            sessions: self,
+            id: id.to_string(),
+            rx: Some(rx),
+        }
+    }
+
+    fn finish(&self, id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            return;
+        };
+        session.producers -= 1;
+        if session.producers == 0 {
+            sessions.remove(id);
+        }
+    }
+
+    /// Forget a session nobody is producing into or watching any more.
+    fn release(&self, id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions
+            .get(id)
+            .is_some_and(|s| s.producers == 0 && s.tx.receiver_count() == 0)
+        {
+            sessions.remove(id);
+        }
+    }
+}
+
+/// The producing side of a session.
+pub struct Publisher {
+    sessions: &'static Sessions,
+    id: String,
+    tx: broadcast::Sender<String>,
+}
+
+impl Publisher {
+    pub fn send(&self, payload: &str) {
+        // Fails only when nobody is subscribed, which is fine
+        let _ = self.tx.send(payload.to_string());
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        self.sessions.finish(&self.id);
+    }
+}
+
+/// The viewing side of a session.
+pub struct Subscription {
+    sessions: &'static Sessions,
+    id: String,
+    rx: Option<broadcast::Receiver<String>>,
+}
+
+impl Subscription {
+    /// The next event, or `None` once the producer has finished.
+    pub async fn next(&mut self) -> Option<String> {
+        let rx = self.rx.as_mut()?;
+        loop {
+            match rx.recv().await {
+                Ok(payload) => return Some(payload),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Subscriber of session {} skipped {skipped} events", self.id);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Drop the receiver first so it no longer counts as a subscriber
+        self.rx.take();
+        self.sessions.release(&self.id);
+    }
+}
+
+/// `GET /v1/chat/completions/subscribe/{session}`: the session's events as SSE.
+pub fn subscribe_route(
+    sessions: &'static Sessions,
+    keys: &'static [ApiKey],
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    warp::path!("v1" / "chat" / "completions" / "subscribe" / String)
+        .and(warp::get())
+        .and(auth::authorized(keys))
+        .map(move |session: String, _ctx: RequestContext| {
+            let events = stream::unfold(sessions.subscribe(&session), |mut sub| async move {
+                let payload = sub.next().await?;
+                Some((Ok::<_, Infallible>(Event::default().data(payload)), sub))
+            });
+            warp::sse::reply(events).into_response()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions() -> &'static Sessions {
+        Box::leak(Box::default())
+    }
+
+    #[tokio::test]
+    async fn test_late_subscriber_sees_only_later_events() {
+        let sessions = sessions();
+        let publisher = sessions.publish("demo");
+        let mut early = sessions.subscribe("demo");
+        publisher.send("a");
+        let mut late = sessions.subscribe("demo");
+        publisher.send("b");
+        drop(publisher);
+
+        assert_eq!(early.next().await.as_deref(), Some("a"));
+        assert_eq!(early.next().await.as_deref(), Some("b"));
+        assert_eq!(early.next().await, None);
+        assert_eq!(late.next().await.as_deref(), Some("b"));
+        assert_eq!(late.next().await, None);
+        assert!(sessions.sessions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_subscription_is_forgotten() {
+        let sessions = sessions();
+        drop(sessions.subscribe("idle"));
+        assert!(sessions.sessions.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Backend health probing and the aggregate `GET /healthz` report.
+//!
+//! Every `GATEWAY_READINESS_INTERVAL_MS` the gateway probes the `/ready` endpoint of
+//! each llm-node and tts-node it can route to, tracking which are up, down or draining
+//! (see [`crate::backends`]); chat requests avoid the ones that aren't available.
+//! `/healthz` reports every service's backends as last probed: `200` with status `ok`
+//! when all are up, `degraded` when some are down but each service still has one,
+//! and `503` with `down` once a service has none left.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::backends::BackendPool;
+
+/// Longest a single probe may take, so one hung node can't stall the others' checks.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A set of interchangeable backends, e.g. one model route's replicas.
+pub struct Service {
+    /// `llm`, `llm:<model pattern>`, `language:<code>` or `tts`.
+    pub name: String,
+    pub pool: &'static BackendPool,
+}
+
+/// Probe every service's backends every `interval`, forever.
+pub async fn watch(services: Arc<Vec<Service>>, client: reqwest::Client, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        for service in services.iter() {
+            service
+                .pool
+                .check_health(&client, interval.min(PROBE_TIMEOUT))
+                .await;
+        }
+    }
+}
+
+fn report(services: &[Service]) -> (StatusCode, serde_json::Value) {
+    let (code, status) = if !services.iter().all(|s| s.pool.any_up()) {
+        (StatusCode::SERVICE_UNAVAILABLE, "down")
+    } else if !services.iter().all(|s| s.pool.all_up()) {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+    let services: Vec<_> = services
+        .iter()
+        .map(|service| {
+            let mut pool = service.pool.status();
+            pool["name"] = json!(service.name);
+            pool
+        })
+        .collect();
+    (code, json!({ "status": status, "services": services }))
+}
+
+/// `GET /healthz`: per-backend state of every service.
+pub fn route(
+    services: Arc<Vec<Service>>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("healthz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || {
+            let (code, body) = report(&services);
+            warp::reply::with_status(warp::reply::json(&body), code)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BackendSelection;
+
+    fn pool(urls: &[&str]) -> &'static BackendPool {
+        let urls = urls.iter().map(|u| u.to_string()).collect();
+        Box::leak(Box::new(BackendPool::new(
+            urls,
+            BackendSelection::RoundRobin,
+            0.5,
+        )))
+    }
+
+    fn closed_port() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/v1/chat/completions",
+            listener.local_addr().unwrap()
+        );
+        drop(listener);
+        url
+    }
+
+    #[tokio::test]
+    async fn test_healthz_reports_each_backend() {
+        let live = warp::path("ready").map(|| "ready");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_url = format!(
+            "http://{}/v1/chat/completions",
+            listener.local_addr().unwrap()
+        );
+        tokio::spawn(warp::serve(live).incoming(listener).run());
+        let dead_url = closed_port();
+
+        let llm = pool(&[&live_url, &dead_url]);
+        let tts = pool(&["http://127.0.0.1:1/v1/audio/speech"]);
+        let services = Arc::new(vec![
+            Service {
+                name: "llm".into(),
+                pool: llm,
+            },
+            Service {
+                name: "tts".into(),
+                pool: tts,
+            },
+        ]);
+        let client = reqwest::Client::new();
+        llm.check_health(&client, PROBE_TIMEOUT).await;
+
+        let resp = warp::test::request()
+            .path("/healthz")
+            .reply(&route(services.clone()))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["services"][0]["name"], "llm");
+        let backends = &body["services"][0]["backends"];
+        assert_eq!(backends[0]["url"], live_url.as_str());
+        assert_eq!(backends[0]["up"], true);
+        assert_eq!(backends[1]["up"], false);
+
+        // The only tts-node is unreachable: the gateway is down
+        tts.check_health(&client, PROBE_TIMEOUT).await;
+        let resp = warp::test::request()
+            .path("/healthz")
+            .reply(&route(services))
+            .await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["status"], "down");
+    }
+}
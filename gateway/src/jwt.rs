@@ -0,0 +1,446 @@
+//! JWT bearer authentication for deployments behind an OIDC identity provider.
+//!
+//! Enabled by `GATEWAY_JWT_ISSUER`. Tokens must be signed with RS256 or ES256 by a
+//! key from the issuer's JWKS, which is read from `GATEWAY_JWT_JWKS_URL` or else
+//! discovered through `<issuer>/.well-known/openid-configuration`. `iss`, `exp` and
+//! `nbf` are always checked (with [`LEEWAY`] for clock skew), `aud` when
+//! `GATEWAY_JWT_AUDIENCE` is set. The JWKS is cached and fetched again when a token
+//! names an unknown `kid` (at most every [`MIN_REFRESH`]) or the copy is older than
+//! [`MAX_KEY_AGE`], so provider key rotation needs no restart.
+//!
+//! Accepted tokens put their [`Claims`] on the request context, where per-client
+//! limits and logs pick up the user.
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::config::env_opt;
+
+/// Allowed clock skew when checking `exp` and `nbf`.
+pub const LEEWAY: Duration = Duration::from_secs(60);
+/// Least time between JWKS fetches triggered by unknown `kid`s.
+pub const MIN_REFRESH: Duration = Duration::from_secs(30);
+/// Cached keys older than this are fetched again before use.
+pub const MAX_KEY_AGE: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtOptions {
+    pub issuer: String,
+    /// Required `aud` (`GATEWAY_JWT_AUDIENCE`); unset skips the check.
+    pub audience: Option<String>,
+    /// `GATEWAY_JWT_JWKS_URL`; unset uses OIDC discovery on the issuer.
+    pub jwks_url: Option<String>,
+    /// Claim naming the user (`GATEWAY_JWT_USER_CLAIM`, default `sub`).
+    pub user_claim: String,
+}
+
+impl JwtOptions {
+    /// `None` unless `GATEWAY_JWT_ISSUER` is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(issuer) = env_opt::<String>("GATEWAY_JWT_ISSUER")? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            issuer,
+            audience: env_opt("GATEWAY_JWT_AUDIENCE")?,
+            jwks_url: env_opt("GATEWAY_JWT_JWKS_URL")?,
+            user_claim: env_opt("GATEWAY_JWT_USER_CLAIM")?.unwrap_or_else(|| "sub".into()),
+        }))
+    }
+}
+
+/// The payload of an accepted token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims {
+    /// Value of the configured user claim.
+    pub user: String,
+    /// Every claim in the token.
+    pub values: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    Rsa {
+        n: Vec<u8>,
+        e: Vec<u8>,
+    },
+    /// Uncompressed P-256 point (`0x04 || x || y`).
+    EcP256(Vec<u8>),
+}
+
+#[derive(Debug, Default)]
+struct KeySet {
+    keys: HashMap<String, Key>,
+    fetched: Option<Instant>,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: String,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+impl Jwk {
+    /// The usable key, or `None` for types and curves we don't verify.
+    fn key(&self) -> Option<Key> {
+        let decode = |part: &Option<String>| URL_SAFE_NO_PAD.decode(part.as_deref()?).ok();
+        match (self.kty.as_str(), self.crv.as_deref()) {
+            ("RSA", _) => Some(Key::Rsa {
+                n: decode(&self.n)?,
+                e: decode(&self.e)?,
+            }),
+            ("EC", Some("P-256")) => {
+                let mut point = vec![0x04];
+                point.extend(decode(&self.x)?);
+                point.extend(decode(&self.y)?);
+                Some(Key::EcP256(point))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+pub struct Validator {
+    options: JwtOptions,
+    client: reqwest::Client,
+    keys: RwLock<KeySet>,
+    /// Serializes JWKS fetches so a burst of new-`kid` tokens fetches once.
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+static VALIDATOR: OnceLock<Validator> = OnceLock::new();
+
+/// Enable JWT authentication for [`crate::auth::authorized`] routes.
+pub fn install(validator: Validator) {
+    if VALIDATOR.set(validator).is_err() {
+        tracing::warn!("JWT validator already installed");
+    }
+}
+
+pub fn validator() -> Option<&'static Validator> {
+    VALIDATOR.get()
+}
+
+fn decode_part(part: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| "token is not base64url".to_string())
+}
+
+fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |now| now.as_secs_f64())
+}
+
+impl Validator {
+    pub fn new(options: JwtOptions, client: reqwest::Client) -> Self {
+        Self {
+            options,
+            client,
+            keys: RwLock::new(KeySet::default()),
+            refreshing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Check `token`'s signature and claims; `Err` carries the reason for the logs.
+    pub async fn validate(&self, token: &str) -> Result<Claims, String> {
+        let mut parts = token.split('.');
+        let (Some(header_part), Some(payload_part), Some(signature_part), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("token is not a JWS compact serialization".into());
+        };
+        let header: Header = serde_json::from_slice(&decode_part(header_part)?)
+            .map_err(|err| format!("bad token header: {err}"))?;
+        let key = self.key(header.kid.as_deref()).await?;
+
+        let message = &token[..header_part.len() + 1 + payload_part.len()];
+        let signature = decode_part(signature_part)?;
+        let verified = match (header.alg.as_str(), &key) {
+            ("RS256", Key::Rsa { n, e }) => RsaPublicKeyComponents { n, e }
+                .verify(
+                    &signature::RSA_PKCS1_2048_8192_SHA256,
+                    message.as_bytes(),
+                    &signature,
+                )
+                .is_ok(),
+            ("ES256", Key::EcP256(point)) => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message.as_bytes(), &signature)
+                    .is_ok()
+            }
+            (alg, _) => return Err(format!("algorithm {alg:?} not accepted for this key")),
+        };
+        if !verified {
+            return Err("bad signature".into());
+        }
+
+        let values: Map<String, Value> = serde_json::from_slice(&decode_part(payload_part)?)
+            .map_err(|err| format!("bad token payload: {err}"))?;
+        self.check_claims(values, now_secs())
+    }
+
+    fn check_claims(&self, values: Map<String, Value>, now: f64) -> Result<Claims, String> {
+        let leeway = LEEWAY.as_secs_f64();
+        if values.get("iss").and_then(Value::as_str) != Some(self.options.issuer.as_str()) {
+            return Err("wrong issuer".into());
+        }
+        if let Some(audience) = &self.options.audience {
+            let matches = match values.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|aud| aud == audience.as_str()),
+                _ => false,
+            };
+            if !matches {
+                return Err("wrong audience".into());
+            }
+        }
+        match values.get("exp").and_then(Value::as_f64) {
+            None => return Err("token has no expiry".into()),
+            Some(exp) if exp + leeway <= now => return Err("token expired".into()),
+            Some(_) => {}
+        }
+        if values
+            .get("nbf")
+            .and_then(Value::as_f64)
+            .is_some_and(|nbf| nbf - leeway > now)
+        {
+            return Err("token not yet valid".into());
+        }
+        let user = values
+            .get(&self.options.user_claim)
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("token has no {:?} claim", self.options.user_claim))?
+            .to_string();
+        Ok(Claims { user, values })
+    }
+
+    /// The signing key for `kid`, fetching the JWKS when it's unknown or stale. Without
+    /// a `kid` the issuer must publish exactly one usable key.
+    async fn key(&self, kid: Option<&str>) -> Result<Key, String> {
+        let lookup = |set: &KeySet| match kid {
+            Some(kid) => set.keys.get(kid).cloned(),
+            None if set.keys.len() == 1 => set.keys.values().next().cloned(),
+            None => None,
+        };
+        let (found, fetched) = {
+            let set = self.keys.read().unwrap();
+            (lookup(&set), set.fetched)
+        };
+        let age = fetched.map(|at| at.elapsed());
+        let fresh = age.is_some_and(|age| age < MAX_KEY_AGE);
+        if let (Some(key), true) = (&found, fresh) {
+            return Ok(key.clone());
+        }
+        if found.is_none() && age.is_some_and(|age| age < MIN_REFRESH) {
+            return Err("unknown signing key".into());
+        }
+
+        let _refreshing = self.refreshing.lock().await;
+        // Another request may have fetched while we waited.
+        let refetch = self.keys.read().unwrap().fetched == fetched;
+        if refetch {
+            match self.fetch_keys().await {
+                Ok(keys) => {
+                    *self.keys.write().unwrap() = KeySet {
+                        keys,
+                        fetched: Some(Instant::now()),
+                    };
+                }
+                // Keep serving the old keys if the provider is briefly unreachable.
+                Err(err) => tracing::warn!("Failed to fetch JWKS: {err:#}"),
+            }
+        }
+        lookup(&self.keys.read().unwrap()).ok_or_else(|| "unknown signing key".into())
+    }
+
+    async fn fetch_keys(&self) -> anyhow::Result<HashMap<String, Key>> {
+        let url = match &self.options.jwks_url {
+            Some(url) => url.clone(),
+            None => {
+                let discovery = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.options.issuer.trim_end_matches('/')
+                );
+                let discovery: Discovery = self.get_json(&discovery).await?;
+                discovery.jwks_uri
+            }
+        };
+        let jwks: Jwks = self.get_json(&url).await?;
+        Ok(jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| Some((jwk.kid.clone(), jwk.key()?)))
+            .collect())
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> anyhow::Result<T> {
+        Ok(self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+    use serde_json::json;
+    use warp::Filter;
+
+    use super::*;
+
+    const ISSUER: &str = "https://idp.example";
+
+    struct Signer {
+        pair: EcdsaKeyPair,
+        rng: SystemRandom,
+    }
+
+    impl Signer {
+        fn new() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+            Self { pair, rng }
+        }
+
+        fn jwk(&self, kid: &str) -> Value {
+            let point = self.pair.public_key().as_ref();
+            json!({
+                "kty": "EC", "crv": "P-256", "kid": kid,
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+            })
+        }
+
+        fn token(&self, header: Value, claims: Value) -> String {
+            let message = format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(header.to_string()),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            );
+            let signature = self.pair.sign(&self.rng, message.as_bytes()).unwrap();
+            format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref()))
+        }
+    }
+
+    /// Serve `jwks` and return a validator pointed at it.
+    async fn validator(jwks: Value, audience: Option<&str>) -> Validator {
+        let route = warp::path!("jwks").map(move || warp::reply::json(&jwks));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        let options = JwtOptions {
+            issuer: ISSUER.into(),
+            audience: audience.map(str::to_string),
+            jwks_url: Some(format!("http://{addr}/jwks")),
+            user_claim: "sub".into(),
+        };
+        Validator::new(options, reqwest::Client::new())
+    }
+
+    fn claims(extra: Value) -> Value {
+        let mut claims = json!({ "iss": ISSUER, "sub": "alice", "exp": now_secs() + 300.0 });
+        claims
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        claims
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_yields_claims() {
+        let signer = Signer::new();
+        let validator = validator(json!({ "keys": [signer.jwk("k1")] }), Some("gateway")).await;
+        let header = json!({ "alg": "ES256", "kid": "k1" });
+
+        let token = signer.token(
+            header.clone(),
+            claims(json!({ "aud": ["gateway"], "tier": "pro" })),
+        );
+        let accepted = validator.validate(&token).await.unwrap();
+        assert_eq!(accepted.user, "alice");
+        assert_eq!(accepted.values["tier"], "pro");
+
+        let token = signer.token(header.clone(), claims(json!({ "aud": "other" })));
+        assert_eq!(
+            validator.validate(&token).await.unwrap_err(),
+            "wrong audience"
+        );
+
+        let expired = claims(json!({ "aud": "gateway", "exp": now_secs() - 120.0 }));
+        let token = signer.token(header.clone(), expired);
+        assert_eq!(
+            validator.validate(&token).await.unwrap_err(),
+            "token expired"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forged_tokens_rejected() {
+        let signer = Signer::new();
+        let validator = validator(json!({ "keys": [signer.jwk("k1")] }), None).await;
+        let good = signer.token(json!({ "alg": "ES256", "kid": "k1" }), claims(json!({})));
+
+        // Payload swapped after signing
+        let mut parts: Vec<&str> = good.split('.').collect();
+        let forged = URL_SAFE_NO_PAD.encode(claims(json!({ "sub": "mallory" })).to_string());
+        parts[1] = &forged;
+        let err = validator.validate(&parts.join(".")).await.unwrap_err();
+        assert_eq!(err, "bad signature");
+
+        // Signed by a key the issuer doesn't publish
+        let other = Signer::new().token(json!({ "alg": "ES256", "kid": "k2" }), claims(json!({})));
+        assert_eq!(
+            validator.validate(&other).await.unwrap_err(),
+            "unknown signing key"
+        );
+
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(json!({ "alg": "none", "kid": "k1" }).to_string()),
+            URL_SAFE_NO_PAD.encode(claims(json!({})).to_string())
+        );
+        let err = validator.validate(&unsigned).await.unwrap_err();
+        assert!(err.contains("not accepted"), "{err}");
+    }
+}
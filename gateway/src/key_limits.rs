@@ -0,0 +1,329 @@
+//! Per-client rate limits (`GATEWAY_KEY_RATE_LIMIT_RPM`, `GATEWAY_KEY_RATE_LIMIT_TPM`),
+//! keyed like the concurrency limit: JWT user, API key, else client IP.
+//!
+//! Each client gets a request bucket and a token bucket, each holding a minute's
+//! allowance and refilling continuously. A chat request is charged an estimate of its
+//! prompt tokens up front and corrected from the reply's `usage.total_tokens` when
+//! one is reported, so the token bucket may go negative after a long completion; the
+//! client is then refused with `429` until it refills. A streamed reply is corrected
+//! once it ends, from the usage of its final chunk when the backend sends one, else
+//! by estimating the content relayed (see [`StreamCharge`]). TTS requests only count
+//! against the request bucket.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tracing::warn;
+
+use crate::client_limits::ClientLimiter;
+use crate::context::RequestContext;
+use crate::{ChatMessage, config, model_limits};
+
+/// Bucket maps larger than this drop clients whose buckets have refilled.
+const PRUNE_AT: usize = 1024;
+/// Characters per token for the up-front estimate.
+const CHARS_PER_TOKEN: usize = 4;
+
+static KEY_LIMITER: LazyLock<KeyLimiter> =
+    LazyLock::new(|| KeyLimiter::new(config().key_rate_limit));
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct KeyRateLimit {
+    pub requests_per_minute: Option<f64>,
+    pub tokens_per_minute: Option<f64>,
+}
+
+impl KeyRateLimit {
+    fn is_unlimited(&self) -> bool {
+        self.requests_per_minute.is_none() && self.tokens_per_minute.is_none()
+    }
+}
+
+/// Admit one request from `ctx`'s client charged `tokens`; `Err` holds the wait
+/// before retrying.
+pub fn check(ctx: &RequestContext, tokens: u64) -> Result<(), Duration> {
+    let result =
+        KEY_LIMITER.try_admit(&ClientLimiter::identity(ctx), tokens as f64, Instant::now());
+    if result.is_err() {
+        warn!("Rejected request: client is over its rate limit");
+    }
+    result
+}
+
+/// Correct the up-front `estimate` for `ctx`'s client with the usage in a JSON chat
+/// reply. Replies without usage keep the estimate.
+pub fn charge_usage(ctx: &RequestContext, estimate: u64, reply: &[u8]) {
+    let Some(used) = total_tokens(reply) else {
+        return;
+    };
+    KEY_LIMITER.charge(
+        &ClientLimiter::identity(ctx),
+        used as f64 - estimate as f64,
+        Instant::now(),
+    );
+}
+
+/// Tallies a streamed chat reply as it is relayed, to charge `ctx`'s client for it
+/// once it ends.
+pub struct StreamCharge {
+    identity: String,
+    estimate: u64,
+    usage: Option<u64>,
+    content_chars: usize,
+}
+
+impl StreamCharge {
+    pub fn new(ctx: &RequestContext, estimate: u64) -> Self {
+        Self {
+            identity: ClientLimiter::identity(ctx),
+            estimate,
+            usage: None,
+            content_chars: 0,
+        }
+    }
+
+    /// Count one relayed chunk: its `usage.total_tokens`, if any, and its content.
+    pub fn observe(&mut self, payload: &str) {
+        let Ok(chunk) = serde_json::from_str::<Value>(payload) else {
+            return;
+        };
+        if let Some(used) = chunk["usage"]["total_tokens"].as_u64() {
+            self.usage = Some(used);
+        }
+        let Some(choices) = chunk["choices"].as_array() else {
+            return;
+        };
+        for choice in choices {
+            if let Some(content) = choice["delta"]["content"].as_str() {
+                self.content_chars += content.chars().count();
+            }
+        }
+    }
+
+    /// Tokens still owed beyond the up-front estimate: the reported usage less the
+    /// estimate, else the relayed content estimated like a prompt.
+    fn correction(&self) -> f64 {
+        match self.usage {
+            Some(used) => used as f64 - self.estimate as f64,
+            None => self.content_chars.div_ceil(CHARS_PER_TOKEN) as f64,
+        }
+    }
+
+    pub fn finish(self) {
+        KEY_LIMITER.charge(&self.identity, self.correction(), Instant::now());
+    }
+}
+
+pub fn rate_limited_reply(retry_after: Duration) -> warp::reply::Response {
+    model_limits::too_many_requests("rate limit exceeded for this client".into(), retry_after)
+}
+
+/// Rough prompt size: one token per [`CHARS_PER_TOKEN`] characters, rounded up.
+pub fn estimate_tokens(messages: &[ChatMessage]) -> u64 {
+    let chars: usize = messages.iter().map(|m| m.content.chars().count()).sum();
+    chars.div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+fn total_tokens(reply: &[u8]) -> Option<u64> {
+    let reply: Value = serde_json::from_slice(reply).ok()?;
+    reply["usage"]["total_tokens"].as_u64()
+}
+
+struct Bucket {
+    level: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(per_minute: f64, now: Instant) -> Self {
+        Self {
+            level: per_minute.max(1.0),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, per_minute: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.level = (self.level + elapsed * per_minute / 60.0).min(per_minute.max(1.0));
+        self.updated = now;
+    }
+
+    /// Time until the level reaches `needed`.
+    fn wait_for(&self, needed: f64, per_minute: f64) -> Option<Duration> {
+        (self.level < needed)
+            .then(|| Duration::from_secs_f64((needed - self.level) * 60.0 / per_minute))
+    }
+}
+
+struct ClientBuckets {
+    requests: Bucket,
+    tokens: Bucket,
+}
+
+pub struct KeyLimiter {
+    limits: KeyRateLimit,
+    clients: Mutex<HashMap<String, ClientBuckets>>,
+}
+
+impl KeyLimiter {
+    pub fn new(limits: KeyRateLimit) -> Self {
+        Self {
+            limits,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refilled<'a>(
+        &self,
+        clients: &'a mut HashMap<String, ClientBuckets>,
+        identity: &str,
+        now: Instant,
+    ) -> &'a mut ClientBuckets {
+        let rpm = self.limits.requests_per_minute.unwrap_or(1.0);
+        let tpm = self.limits.tokens_per_minute.unwrap_or(1.0);
+        let buckets = clients
+            .entry(identity.to_string())
+            .or_insert_with(|| ClientBuckets {
+                requests: Bucket::full(rpm, now),
+                tokens: Bucket::full(tpm, now),
+            });
+        buckets.requests.refill(rpm, now);
+        buckets.tokens.refill(tpm, now);
+        buckets
+    }
+
+    /// Take one request and `tokens` from `identity`'s buckets. A request is admitted
+    /// while any tokens remain, even if `tokens` overdraws them.
+    pub fn try_admit(&self, identity: &str, tokens: f64, now: Instant) -> Result<(), Duration> {
+        if self.limits.is_unlimited() {
+            return Ok(());
+        }
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= PRUNE_AT {
+            self.prune(&mut clients, now);
+        }
+        let buckets = self.refilled(&mut clients, identity, now);
+        let request_wait = self
+            .limits
+            .requests_per_minute
+            .and_then(|rpm| buckets.requests.wait_for(1.0, rpm));
+        let token_wait = self
+            .limits
+            .tokens_per_minute
+            .and_then(|tpm| buckets.tokens.wait_for(f64::MIN_POSITIVE, tpm));
+        if let Some(wait) = request_wait.into_iter().chain(token_wait).max() {
+            return Err(wait);
+        }
+        buckets.requests.level -= 1.0;
+        buckets.tokens.level -= tokens;
+        Ok(())
+    }
+
+    /// Take `tokens` more from `identity`'s token bucket; negative gives them back.
+    pub fn charge(&self, identity: &str, tokens: f64, now: Instant) {
+        if self.limits.tokens_per_minute.is_none() {
+            return;
+        }
+        let mut clients = self.clients.lock().unwrap();
+        let buckets = self.refilled(&mut clients, identity, now);
+        buckets.tokens.level -= tokens;
+    }
+
+    /// Forget clients whose buckets are full again; they'd be recreated identically.
+    fn prune(&self, clients: &mut HashMap<String, ClientBuckets>, now: Instant) {
+        let rpm = self.limits.requests_per_minute.unwrap_or(1.0);
+        let tpm = self.limits.tokens_per_minute.unwrap_or(1.0);
+        clients.retain(|_, buckets| {
+            buckets.requests.refill(rpm, now);
+            buckets.tokens.refill(tpm, now);
+            buckets.requests.level < rpm.max(1.0) || buckets.tokens.level < tpm.max(1.0)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_limit_per_client() {
+        let limiter = KeyLimiter::new(KeyRateLimit {
+            requests_per_minute: Some(2.0),
+            tokens_per_minute: None,
+        });
+        let now = Instant::now();
+        assert!(limiter.try_admit("key:a", 0.0, now).is_ok());
+        assert!(limiter.try_admit("key:a", 0.0, now).is_ok());
+        assert_eq!(
+            limiter.try_admit("key:a", 0.0, now),
+            Err(Duration::from_secs(30))
+        );
+        // Other clients have their own buckets
+        assert!(limiter.try_admit("ip:10.0.0.1", 0.0, now).is_ok());
+
+        let later = now + Duration::from_secs(30);
+        assert!(limiter.try_admit("key:a", 0.0, later).is_ok());
+    }
+
+    #[test]
+    fn test_token_limit_allows_overdraft_then_waits() {
+        let limiter = KeyLimiter::new(KeyRateLimit {
+            requests_per_minute: None,
+            tokens_per_minute: Some(600.0),
+        });
+        let now = Instant::now();
+        assert!(limiter.try_admit("key:a", 100.0, now).is_ok());
+        // The reply used far more than the estimate
+        limiter.charge("key:a", 800.0, now);
+        let wait = limiter.try_admit("key:a", 100.0, now).unwrap_err();
+        assert!(
+            wait > Duration::from_secs(29) && wait <= Duration::from_secs(30),
+            "{wait:?}"
+        );
+
+        let later = now + Duration::from_secs(31);
+        assert!(limiter.try_admit("key:a", 100.0, later).is_ok());
+    }
+
+    #[test]
+    fn test_usage_read_from_reply() {
+        let reply = br#"{"choices": [], "usage": {"prompt_tokens": 3, "total_tokens": 12}}"#;
+        assert_eq!(total_tokens(reply), Some(12));
+        assert_eq!(total_tokens(b"{}"), None);
+        assert_eq!(total_tokens(b"\x81\xa2id"), None);
+    }
+
+    fn stream_charge(estimate: u64) -> StreamCharge {
+        StreamCharge {
+            identity: "key:a".into(),
+            estimate,
+            usage: None,
+            content_chars: 0,
+        }
+    }
+
+    #[test]
+    fn test_stream_charged_from_final_usage_chunk() {
+        let mut charge = stream_charge(10);
+        charge.observe(r#"{"choices":[{"delta":{"content":"Echo hi"}}]}"#);
+        charge.observe(r#"{"choices":[],"usage":{"prompt_tokens":9,"total_tokens":40}}"#);
+        charge.observe("[DONE]");
+        assert_eq!(charge.correction(), 30.0);
+    }
+
+    #[test]
+    fn test_stream_without_usage_charged_for_relayed_content() {
+        let mut charge = stream_charge(10);
+        for content in ["Echo ", "hi", "!"] {
+            charge.observe(&format!(
+                r#"{{"choices":[{{"delta":{{"content":"{content}"}}}}]}}"#
+            ));
+        }
+        charge.observe("{}");
+        charge.observe("[DONE]");
+        // 8 characters relayed on top of the prompt estimate already taken
+        assert_eq!(charge.correction(), 2.0);
+    }
+}
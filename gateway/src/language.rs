@@ -0,0 +1,114 @@
+//! Prompt language detection for `GATEWAY_LANGUAGE_ROUTES`, using `whatlang`.
+//!
+//! A language is only reported when the detector's confidence reaches
+//! [`MIN_CONFIDENCE`], so short or mixed-language prompts keep their model routing.
+
+use whatlang::Lang;
+
+use crate::ChatMessage;
+use crate::backends::BackendPool;
+use crate::routes::RoutingTable;
+
+/// Languages [`detect`] can report, as ISO 639-1 codes.
+pub const SUPPORTED: &[&str] = &[
+    "en", "fr", "de", "es", "it", "pt", "nl", "ru", "el", "ar", "he", "zh", "ja", "ko",
+];
+
+/// Lowest `whatlang` confidence (0 to 1) acted on. Full sentences in one language
+/// score well above it; a word or two, or a prompt mixing languages, falls below.
+const MIN_CONFIDENCE: f64 = 0.4;
+
+fn iso_639_1(lang: Lang) -> Option<&'static str> {
+    let code = match lang {
+        Lang::Eng => "en",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Spa => "es",
+        Lang::Ita => "it",
+        Lang::Por => "pt",
+        Lang::Nld => "nl",
+        Lang::Rus => "ru",
+        Lang::Ell => "el",
+        Lang::Ara => "ar",
+        Lang::Heb => "he",
+        Lang::Cmn => "zh",
+        Lang::Jpn => "ja",
+        Lang::Kor => "ko",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// The language of `text`, if it can be told with reasonable confidence.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    if info.confidence() < MIN_CONFIDENCE {
+        return None;
+    }
+    iso_639_1(info.lang())
+}
+
+/// The backends for the language of the last user message, if it has a route.
+pub fn route<'a>(routes: &'a RoutingTable, messages: &[ChatMessage]) -> Option<&'a BackendPool> {
+    let last_user = messages.iter().rev().find(|m| m.role == "user")?;
+    let language = detect(&last_user.content)?;
+    tracing::debug!("Detected prompt language {language}");
+    routes.target(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(content: &str) -> Vec<ChatMessage> {
+        vec![ChatMessage {
+            role: "user".into(),
+            content: content.into(),
+        }]
+    }
+
+    #[test]
+    fn test_french_prompt_routes_to_french_backend() {
+        let routes: RoutingTable = "fr=http://fr-node:9000,de=http://de-node:9000"
+            .parse()
+            .unwrap();
+        let messages =
+            user("Bonjour, pouvez-vous m'expliquer pourquoi le ciel est bleu pendant la journée ?");
+        assert_eq!(
+            route(&routes, &messages).map(|pool| pool.select().url()),
+            Some("http://fr-node:9000/v1/chat/completions")
+        );
+        // English has no route here, so model routing applies
+        let messages = user("Can you explain why the sky is blue during the day?");
+        assert!(route(&routes, &messages).is_none());
+    }
+
+    #[test]
+    fn test_detects_full_sentences() {
+        assert_eq!(detect("今日はいい天気ですね"), Some("ja"));
+        assert_eq!(
+            detect("Wie ist das Wetter und was ist der Plan?"),
+            Some("de")
+        );
+        assert_eq!(
+            detect("¿Cómo estás? Quiero aprender a programar en Rust."),
+            Some("es")
+        );
+    }
+
+    #[test]
+    fn test_short_and_mixed_prompts_fall_back() {
+        for text in ["ok", "Bonjour", "merci", "hola amigo", "12345 !!!"] {
+            assert_eq!(detect(text), None, "{text}");
+        }
+        let mixed = [
+            "Bonjour! Can you explain how the weather works today please?",
+            "Explain this: le chat est sur la table, the dog is under it",
+        ];
+        for text in mixed {
+            assert_eq!(detect(text), None, "{text}");
+        }
+        let routes: RoutingTable = "fr=http://fr-node:9000".parse().unwrap();
+        assert!(route(&routes, &user(mixed[1])).is_none());
+    }
+}
@@ -0,0 +1,70 @@
+//! Deterministic, content-proportional latency for reproducible load tests.
+//!
+//! With `GATEWAY_LATENCY_BASE_MS` or `GATEWAY_LATENCY_PER_CHAR_MS` set, chat and TTS
+//! requests sleep for `base + per_char * input_chars` before they are proxied. The
+//! same request always waits the same time, so latency tracks request size instead of
+//! noise. Chat input is the total message content; TTS input is the text to speak.
+
+use std::time::Duration;
+
+use tracing::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyModel {
+    pub base: Duration,
+    pub per_char_ms: f64,
+}
+
+impl LatencyModel {
+    pub fn delay(&self, input_chars: usize) -> Duration {
+        self.base + Duration::from_secs_f64(self.per_char_ms * input_chars as f64 / 1000.0)
+    }
+}
+
+/// Sleep for the modelled delay, if latency injection is enabled.
+pub async fn inject(model: Option<LatencyModel>, input_chars: usize) {
+    let Some(model) = model else {
+        return;
+    };
+    let delay = model.delay(input_chars);
+    debug!("Injecting {delay:?} of latency for {input_chars} input chars");
+    tokio::time::sleep(delay).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    async fn timed(model: LatencyModel, input_chars: usize) -> Duration {
+        let started = Instant::now();
+        inject(Some(model), input_chars).await;
+        started.elapsed()
+    }
+
+    #[tokio::test]
+    async fn test_delay_proportional_to_input_size() {
+        let model = LatencyModel {
+            base: Duration::from_millis(10),
+            per_char_ms: 0.5,
+        };
+        assert_eq!(model.delay(0), Duration::from_millis(10));
+        assert_eq!(model.delay(100), Duration::from_millis(60));
+        assert_eq!(model.delay(400), Duration::from_millis(210));
+
+        let short = timed(model, 100).await;
+        let long = timed(model, 400).await;
+        assert!(short >= Duration::from_millis(60), "{short:?}");
+        assert!(long >= Duration::from_millis(210), "{long:?}");
+        assert!(
+            long - short >= Duration::from_millis(100),
+            "{short:?} vs {long:?}"
+        );
+
+        // Disabled injection returns at once
+        let started = Instant::now();
+        inject(None, 1_000_000).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+}
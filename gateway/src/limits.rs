@@ -0,0 +1,113 @@
+//! Size limits enforced on chat requests before they reach a backend.
+
+use serde_json::Value;
+
+use crate::ChatMessage;
+
+/// Reject the first message whose content is longer than `max_chars` characters.
+pub fn check_message_chars(
+    messages: &[ChatMessage],
+    max_chars: Option<usize>,
+) -> Result<(), String> {
+    let Some(max) = max_chars else {
+        return Ok(());
+    };
+    for (index, message) in messages.iter().enumerate() {
+        let chars = message.content.chars().count();
+        if chars > max {
+            return Err(format!(
+                "messages[{index}] content is {chars} characters; the limit is {max}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Non-text content parts (images, audio, files) across every message of a raw chat
+/// body. Counted on the JSON itself so array-valued `content` is covered too.
+fn count_attachments(body: &Value) -> usize {
+    let Some(messages) = body["messages"].as_array() else {
+        return 0;
+    };
+    messages
+        .iter()
+        .filter_map(|m| m["content"].as_array())
+        .flatten()
+        .filter(|part| part["type"].as_str().is_some_and(|kind| kind != "text"))
+        .count()
+}
+
+/// True when any message of a raw chat body has array-valued `content`.
+pub fn has_content_parts(bytes: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(bytes).is_ok_and(|body| {
+        body["messages"]
+            .as_array()
+            .is_some_and(|messages| messages.iter().any(|m| m["content"].is_array()))
+    })
+}
+
+/// Reject a raw chat body carrying more than `max_attachments` non-text parts.
+///
+/// `ChatMessage` only takes string `content` so far, so a body within the limit still
+/// fails the typed parse that follows (see [`has_content_parts`]); this check runs
+/// first so the limit is reported, and applies unchanged once content parts parse.
+pub fn check_attachments(bytes: &[u8], max_attachments: Option<usize>) -> Result<(), String> {
+    let Some(max) = max_attachments else {
+        return Ok(());
+    };
+    // Malformed bodies are reported by the typed parse that follows
+    let Ok(body) = serde_json::from_slice::<Value>(bytes) else {
+        return Ok(());
+    };
+    let count = count_attachments(&body);
+    if count > max {
+        return Err(format!(
+            "request has {count} attachments; the limit is {max}"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".into(),
+            content: content.into(),
+        }
+    }
+
+    #[test]
+    fn test_message_within_limit_passes() {
+        let messages = [msg("hello"), msg("world")];
+        assert_eq!(check_message_chars(&messages, Some(5)), Ok(()));
+        assert_eq!(check_message_chars(&messages, None), Ok(()));
+    }
+
+    #[test]
+    fn test_too_long_message_names_index() {
+        let messages = [msg("ok"), msg(&"x".repeat(11))];
+        let err = check_message_chars(&messages, Some(10)).unwrap_err();
+        assert!(err.starts_with("messages[1]"), "{err}");
+    }
+
+    #[test]
+    fn test_too_many_image_parts_rejected() {
+        let image = serde_json::json!({ "type": "image_url", "image_url": { "url": "data:," } });
+        let text = serde_json::json!({ "type": "text", "text": "compare these" });
+        let body = serde_json::json!({
+            "model": "m",
+            "messages": [{ "role": "user", "content": [text, image, image, image] }]
+        })
+        .to_string();
+        let err = check_attachments(body.as_bytes(), Some(2)).unwrap_err();
+        assert!(err.contains("3 attachments"), "{err}");
+        assert_eq!(check_attachments(body.as_bytes(), Some(3)), Ok(()));
+        assert_eq!(check_attachments(body.as_bytes(), None), Ok(()));
+
+        let plain = br#"{"model":"m","messages":[{"role":"user","content":"hi"}]}"#;
+        assert_eq!(check_attachments(plain, Some(0)), Ok(()));
+    }
+}
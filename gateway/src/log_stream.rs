@@ -0,0 +1,210 @@
+//! `GET /admin/logs/stream`: live gateway logs over server-sent events.
+//!
+//! A tracing layer keeps the most recent [`BUFFER_LINES`] log lines in a ring buffer
+//! and broadcasts new ones. A client first receives the buffered lines, then each new
+//! line as it is logged. Configured API keys and bearer tokens are redacted from
+//! every line before it leaves the gateway.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt::{self, Write as _};
+use std::sync::{LazyLock, Mutex};
+
+use futures_util::{Stream, StreamExt, stream};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use warp::sse;
+use warp::{Filter, Rejection, Reply};
+
+use crate::auth::{self, ApiKey};
+use crate::context::RequestContext;
+
+/// Lines kept for clients that connect after they were logged.
+pub const BUFFER_LINES: usize = 1000;
+
+const REDACTED: &str = "[REDACTED]";
+
+pub static LOGS: LazyLock<LogBuffer> = LazyLock::new(|| LogBuffer::new(BUFFER_LINES));
+
+pub struct LogBuffer {
+    lines: Mutex<VecDeque<String>>,
+    capacity: usize,
+    tx: broadcast::Sender<String>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self {
+            lines: Mutex::default(),
+            capacity,
+            tx,
+        }
+    }
+
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line.clone());
+        // Sent under the lock so `follow` never misses or repeats a line
+        let _ = self.tx.send(line);
+    }
+
+    /// The buffered lines, then every new one.
+    fn follow(&self) -> impl Stream<Item = String> + Send + 'static {
+        let lines = self.lines.lock().unwrap();
+        let backlog: Vec<String> = lines.iter().cloned().collect();
+        let rx = self.tx.subscribe();
+        drop(lines);
+        let live = stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(line) => return Some((line, rx)),
+                    // A slow reader skips what it missed rather than ending the stream
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        stream::iter(backlog).chain(live)
+    }
+}
+
+/// Tracing layer feeding [`LogBuffer`].
+pub struct LogLayer {
+    buffer: &'static LogBuffer,
+}
+
+impl LogLayer {
+    pub fn new(buffer: &'static LogBuffer) -> Self {
+        Self { buffer }
+    }
+}
+
+#[derive(Default)]
+struct LineVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = LineVisitor::default();
+        event.record(&mut visitor);
+        let meta = event.metadata();
+        self.buffer.push(format!(
+            "{} {}: {}{}",
+            meta.level(),
+            meta.target(),
+            visitor.message,
+            visitor.fields
+        ));
+    }
+}
+
+/// Mask `keys` and any bearer token in `line`.
+fn redact(line: &str, keys: &[ApiKey]) -> String {
+    let mut line = keys
+        .iter()
+        .filter(|key| !key.key.is_empty())
+        .fold(line.to_string(), |line, key| {
+            line.replace(key.key.as_str(), REDACTED)
+        });
+    let mut from = 0;
+    while let Some(found) = line[from..].find("Bearer ") {
+        let start = from + found + "Bearer ".len();
+        let end = line[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ',' | ')'))
+            .map_or(line.len(), |len| start + len);
+        line.replace_range(start..end, REDACTED);
+        from = start + REDACTED.len();
+    }
+    line
+}
+
+/// `GET /admin/logs/stream`.
+pub fn route(
+    buffer: &'static LogBuffer,
+    keys: &'static [ApiKey],
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    warp::path!("admin" / "logs" / "stream")
+        .and(warp::get())
+        .and(auth::authorized(keys))
+        .map(move |_ctx: RequestContext| {
+            let events = buffer.follow().map(move |line| {
+                Ok::<_, Infallible>(sse::Event::default().data(redact(&line, keys)))
+            });
+            sse::reply(sse::keep_alive().stream(events)).into_response()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    fn log_with(buffer: &'static LogBuffer, f: impl FnOnce()) {
+        let subscriber = tracing_subscriber::registry().with(LogLayer::new(buffer));
+        tracing::subscriber::with_default(subscriber, f);
+    }
+
+    #[tokio::test]
+    async fn test_logged_line_appears_in_stream() {
+        let buffer: &'static LogBuffer = Box::leak(Box::new(LogBuffer::new(10)));
+        log_with(buffer, || tracing::info!("before connecting"));
+
+        let mut lines = Box::pin(buffer.follow());
+        log_with(buffer, || tracing::warn!(model = "m", "after connecting"));
+
+        let first = lines.next().await.unwrap();
+        assert!(first.starts_with("INFO "), "{first}");
+        assert!(first.ends_with(": before connecting"), "{first}");
+        let live = tokio::time::timeout(Duration::from_secs(1), lines.next());
+        let second = live.await.unwrap().unwrap();
+        assert!(second.contains("after connecting model=\"m\""), "{second}");
+    }
+
+    #[test]
+    fn test_buffer_is_bounded() {
+        let buffer: &'static LogBuffer = Box::leak(Box::new(LogBuffer::new(3)));
+        log_with(buffer, || {
+            for i in 0..5 {
+                tracing::info!("line {i}");
+            }
+        });
+        let lines = buffer.lines.lock().unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("line 2"));
+    }
+
+    #[test]
+    fn test_secrets_redacted() {
+        let keys = [ApiKey {
+            name: "prod".into(),
+            key: "sk-live-123".into(),
+        }];
+        let line = r#"auth failed for sk-live-123 with "Bearer abc.def" and Bearer xyz"#;
+        assert_eq!(
+            redact(line, &keys),
+            r#"auth failed for [REDACTED] with "Bearer [REDACTED]" and Bearer [REDACTED]"#
+        );
+    }
+}
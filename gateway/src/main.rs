@@ -1,20 +1,122 @@
 //! API Gateway that routes requests to backend LLM and TTS services.
 //! Exposes OpenAI-compatible endpoints and handles CORS for browser access.
 
+mod ab_test;
+mod admission;
+mod auth;
+mod backends;
+mod client_limits;
+mod coalesce;
+mod config;
+mod context;
+mod count_tokens;
+mod dataset;
+mod debug;
+mod embeddings;
+mod empty_reply;
+mod fallback;
+mod fanout;
+mod health;
+mod injection;
+mod jwt;
+mod key_limits;
+mod language;
+mod latency;
+mod limits;
+mod log_stream;
+mod metrics;
+mod model_limits;
+mod models;
+mod normalize;
+mod proxy;
+mod realtime;
+mod reorder;
+mod request_log;
+mod response_cache;
+mod retry;
+mod roles;
+mod routes;
+mod schema;
+mod serve;
+mod shadow;
+mod shutdown;
+mod sse;
+mod strict;
+mod template;
+#[cfg(test)]
+mod test_support;
+mod timing;
+mod token_limit;
+mod trace_url;
+mod upstream_errors;
+mod version;
+
+use std::collections::HashMap;
 use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
 
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tokio::sync::OnceCell;
-use tracing::{Level, info};
-use warp::Filter;
+use tracing::{Level, debug, info, warn};
+use tracing_subscriber::util::SubscriberInitExt;
+use warp::{Filter, Reply};
+
+use crate::backends::{BackendPool, Selected};
+use crate::config::Config;
+use crate::context::RequestContext;
+use crate::routes::RoutingTable;
 
 static HTTP_CLIENT: OnceCell<Client> = OnceCell::const_new();
+/// Upstream client for tts-node, with its own connect timeout.
+static TTS_CLIENT: OnceCell<Client> = OnceCell::const_new();
+static CONFIG: OnceCell<Config> = OnceCell::const_new();
+static ROUTES: OnceCell<RoutingTable> = OnceCell::const_new();
+static LANGUAGE_ROUTES: OnceCell<RoutingTable> = OnceCell::const_new();
+/// Falls back to the default configuration when none is loaded, as in tests.
+static LLM_BACKENDS: LazyLock<BackendPool> = LazyLock::new(|| match CONFIG.get() {
+    Some(config) => backend_pool(config),
+    None => backend_pool(&Config::default()),
+});
+/// tts-node has a single backend; the pool tracks its health.
+static TTS_BACKENDS: LazyLock<BackendPool> = LazyLock::new(|| {
+    let urls = vec![TTS_TARGET.to_string()];
+    BackendPool::new(urls, config().backend_selection, config().latency_ema_alpha)
+});
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+const DEFAULT_LLM_TARGET: &str = "http://localhost:9000/v1/chat/completions";
+const EMBEDDINGS_TARGET: &str = "http://localhost:9000/v1/embeddings";
+const TTS_TARGET: &str = "http://localhost:9001/v1/audio/speech";
+/// Loudness tts-node reports for buffered audio, passed through to clients.
+const AUDIO_LUFS_HEADER: &str = "x-audio-lufs";
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 struct ChatCompletionRequest {
     model: String,
     messages: Vec<ChatMessage>,
+    /// Relay the completion as server-sent events while it is generated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    /// End-user identifier, available to system prompt templates as `{user}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    /// Passed through to the backend, e.g. `{"type": "json_object"}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    /// Schema the reply content must match; checked by the gateway, not forwarded.
+    #[serde(default, skip_serializing)]
+    json_schema: Option<serde_json::Value>,
+    /// Sampling temperature, passed through; the node's default applies when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    /// Choices to generate, passed through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    /// Every other field (`max_tokens`, `top_p`, `tools`, ...), passed through as sent.
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -23,11 +125,41 @@ struct ChatMessage {
     content: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 struct TtsRequest {
     input: String,
+    /// `"text"` or `"phonemes"`; passed through for tts-node to validate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    input_type: Option<String>,
     voice: Option<String>,
     format: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gain: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extensible: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trailing_silence_ms: Option<u32>,
+    /// 8, 16 or `"f32"`; passed through for tts-node to validate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bit_depth: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dither: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sample_rate: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pitch: Option<f32>,
+    /// `"le"` or `"be"`; passed through for tts-node to validate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    byte_order: Option<String>,
+    /// `"beep"` or `"none"`; passed through for tts-node to validate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preamble: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -35,54 +167,349 @@ struct ErrorResponse {
     error: String,
 }
 
-/// Determine which LLM backend to route to based on model name.
+/// The `GATEWAY_LLM_BACKENDS` pool, or the single default llm-node instance.
+fn backend_pool(config: &Config) -> BackendPool {
+    let urls = match config.llm_backends.as_slice() {
+        [] => vec![DEFAULT_LLM_TARGET.to_string()],
+        urls => urls.to_vec(),
+    };
+    let mut pool = BackendPool::new(urls, config.backend_selection, config.latency_ema_alpha);
+    pool.set_breaker(config.breaker);
+    pool
+}
+
+/// Determine which LLM backends serve a model.
 ///
-/// Currently all models route to the single llm-node instance.
-/// Future enhancement: route different model prefixes to different backends:
-/// - qwen3-* -> llm-node-gpu0
-/// - llama-3-* -> llm-node-gpu1
-/// - etc.
-fn get_llm_target(_model: &str) -> &'static str {
-    "http://localhost:9000/v1/chat/completions"
+/// Uses the `GATEWAY_ROUTES` table when configured, where `None` means no route
+/// matches; otherwise every model goes to the `GATEWAY_LLM_BACKENDS` pool.
+fn get_llm_target(model: &str) -> Option<&'static BackendPool> {
+    match ROUTES.get() {
+        Some(routes) => routes.target(model),
+        None => Some(&LLM_BACKENDS),
+    }
+}
+
+/// The backend for a chat request: a replica of the route for its prompt language when
+/// `GATEWAY_LANGUAGE_ROUTES` has one, of its model route when `GATEWAY_ROUTES` is set,
+/// otherwise of the backend pool.
+fn chat_target(body: &ChatCompletionRequest) -> Option<Selected<'static>> {
+    let by_language = LANGUAGE_ROUTES
+        .get()
+        .and_then(|routes| language::route(routes, &body.messages));
+    by_language
+        .or_else(|| get_llm_target(&body.model))
+        .map(BackendPool::select)
+}
+
+/// Every backend set chat and speech requests can be routed to.
+fn health_services() -> Vec<health::Service> {
+    let service = |name: String, pool| health::Service { name, pool };
+    let mut services = Vec::new();
+    match ROUTES.get() {
+        Some(routes) => services.extend(
+            routes
+                .routes()
+                .map(|(pattern, pool)| service(format!("llm:{pattern}"), pool)),
+        ),
+        None => services.push(service("llm".into(), &LLM_BACKENDS)),
+    }
+    if let Some(routes) = LANGUAGE_ROUTES.get() {
+        services.extend(
+            routes
+                .routes()
+                .map(|(code, pool)| service(format!("language:{code}"), pool)),
+        );
+    }
+    services.push(service("tts".into(), &TTS_BACKENDS));
+    services
+}
+
+fn config() -> &'static Config {
+    CONFIG.get().expect("config not initialized")
+}
+
+/// Log a dedicated `warn` line when a request took longer than the threshold.
+///
+/// Returns whether the request was considered slow.
+fn warn_if_slow(
+    route: &str,
+    model: &str,
+    target: &str,
+    elapsed: Duration,
+    threshold: Option<Duration>,
+) -> bool {
+    match threshold {
+        Some(limit) if elapsed > limit => {
+            warn!(
+                "Slow {route} request: model={model}, target={target}, duration_ms={}",
+                elapsed.as_millis()
+            );
+            true
+        }
+        _ => false,
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
+    let subscriber = tracing_subscriber::fmt()
         .with_max_level(Level::INFO)
         .with_env_filter("gateway=info,warp=info")
-        .init();
+        .finish();
+    let subscriber = tracing_subscriber::layer::SubscriberExt::with(
+        subscriber,
+        log_stream::LogLayer::new(&log_stream::LOGS),
+    );
+    #[cfg(feature = "otel")]
+    let subscriber = tracing_subscriber::layer::SubscriberExt::with(
+        subscriber,
+        common::otel::layer_from_env("gateway"),
+    );
+    subscriber.init();
 
+    CONFIG.set(Config::from_env()?).expect("config already set");
     HTTP_CLIENT
-        .set(Client::builder().build()?)
+        .set(proxy::client(
+            config().upstream_http2,
+            config().chat_connect_timeout,
+        )?)
         .expect("client already set");
+    TTS_CLIENT
+        .set(proxy::client(
+            config().upstream_http2,
+            config().tts_connect_timeout,
+        )?)
+        .expect("client already set");
+    if let Some(options) = config().jwt.clone() {
+        let client = HTTP_CLIENT.get().expect("client not initialized").clone();
+        jwt::install(jwt::Validator::new(options, client));
+    }
+    let (selection, alpha) = (config().backend_selection, config().latency_ema_alpha);
+    if let Some(mut routes) = routes::from_env(selection, alpha)? {
+        routes.set_breaker(config().breaker);
+        ROUTES.set(routes).expect("routes already set");
+    }
+    if let Some(mut routes) = routes::language_from_env(selection, alpha)? {
+        routes.set_breaker(config().breaker);
+        LANGUAGE_ROUTES
+            .set(routes)
+            .expect("language routes already set");
+    }
 
     let chat = warp::path!("v1" / "chat" / "completions")
         .and(warp::post())
-        .and(warp::body::json())
-        .and_then(handle_chat);
+        .and(auth::authorized(&config().api_keys))
+        .and(warp::header::optional::<String>("accept"))
+        .and(strict::chat_body(
+            config().strict_fields,
+            config().max_message_chars,
+            config().max_attachments,
+        ))
+        .and_then(
+            |ctx: RequestContext, accept: Option<String>, body: ChatCompletionRequest| async move {
+                let request_id = ctx.request_id.clone();
+                let reply = handle_chat(ctx, accept, body).await?.into_response();
+                let reply =
+                    context::echo_request_id(reply, context::request_id_header(), &request_id);
+                let base = config().trace_url_base.as_deref();
+                Ok::<_, Infallible>(trace_url::attach(reply, base, &request_id))
+            },
+        )
+        .recover(strict::recover_invalid_body);
 
     let tts = warp::path!("v1" / "audio" / "speech")
         .and(warp::post())
+        .and(auth::authorized(&config().api_keys))
         .and(warp::body::json())
-        .and_then(handle_tts);
+        .and_then(|ctx: RequestContext, body: TtsRequest| async move {
+            let request_id = ctx.request_id.clone();
+            let reply = handle_tts(ctx, body).await?.into_response();
+            let reply = context::echo_request_id(reply, context::request_id_header(), &request_id);
+            let base = config().trace_url_base.as_deref();
+            Ok::<_, Infallible>(trace_url::attach(reply, base, &request_id))
+        });
+
+    let client = HTTP_CLIENT.get().expect("client not initialized").clone();
+    let services = Arc::new(health_services());
+    if let Some(interval) = config().readiness_interval {
+        tokio::spawn(health::watch(services.clone(), client.clone(), interval));
+    }
+    let healthz = health::route(services);
+    let embeddings = embeddings::route(
+        client.clone(),
+        EMBEDDINGS_TARGET.to_string(),
+        &embeddings::CACHE,
+        &config().api_keys,
+    );
+    let count_tokens = count_tokens::route(client.clone(), &config().api_keys);
+    let tts_client = TTS_CLIENT.get().expect("client not initialized").clone();
+    let realtime = realtime::route(
+        tts_client,
+        TTS_TARGET.to_string(),
+        &config().api_keys,
+        config().tts_timeout,
+        |ctx: &RequestContext| -> Result<_, Box<dyn warp::Reply>> {
+            let Some(slot) = client_limits::acquire(ctx) else {
+                return Err(Box::new(client_limits::overloaded_reply()));
+            };
+            if let Err(retry_after) = key_limits::check(ctx, 0) {
+                return Err(Box::new(key_limits::rate_limited_reply(retry_after)));
+            }
+            Ok(slot)
+        },
+    );
+
+    let validate = auth::validate_route(&config().api_keys);
+    let version = version::route();
+    let metrics = metrics::route();
+    let admin_backends = backends::admin_route(&LLM_BACKENDS, &config().api_keys);
+    let admin_logs = log_stream::route(&log_stream::LOGS, &config().api_keys);
+    let debug_echo = debug::echo_route(config().debug_echo);
+    let subscribe = fanout::subscribe_route(&fanout::SESSIONS, &config().api_keys);
+    let draining = shutdown::listen_for_signals();
+    let draining_status = warp::http::StatusCode::from_u16(config().draining_status)?;
+    let ready = shutdown::ready_route(
+        draining.clone(),
+        draining_status,
+        config().draining_body.clone(),
+    );
 
-    let routes = chat.or(tts).with(warp::cors().allow_any_origin());
+    let routes = chat
+        .or(count_tokens)
+        .or(subscribe)
+        .or(tts)
+        .or(embeddings)
+        .or(realtime)
+        .or(validate)
+        .or(version)
+        .or(metrics)
+        .or(ready)
+        .or(healthz)
+        .or(admin_backends)
+        .or(admin_logs)
+        .or(debug_echo)
+        .recover(auth::recover_unauthorized)
+        .with(warp::cors().allow_any_origin());
 
-    let addr = ([0, 0, 0, 0], 8080);
-    info!(
-        "gateway listening on http://{}.{}.{}.{}:{}",
-        addr.0[0], addr.0[1], addr.0[2], addr.0[3], addr.1
+    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("gateway listening on http://{addr}");
+
+    let server = serve::run(
+        listener,
+        routes,
+        shutdown::stop_accepting(draining.clone(), config().drain_grace),
     );
-    warp::serve(routes).run(addr).await;
+    shutdown::serve_with_drain_timeout(
+        server,
+        draining,
+        config().drain_grace + config().drain_timeout,
+        &shutdown::IN_FLIGHT,
+    )
+    .await;
 
     Ok(())
 }
 
-async fn handle_chat(body: ChatCompletionRequest) -> Result<impl warp::Reply, Infallible> {
-    let target = get_llm_target(&body.model);
+/// Merge, system-prompt and suffix rules, applied before the messages are sent or counted.
+fn apply_prompt_rules(body: &mut ChatCompletionRequest) {
+    if config().merge_same_role {
+        body.messages = normalize::merge_consecutive_roles(std::mem::take(&mut body.messages));
+    }
+    if let Some(template) = models::lookup(&config().system_prompts, &body.model) {
+        let prompt = template::render(template, body);
+        normalize::prepend_system(&mut body.messages, prompt);
+    }
+    if let Some(suffix) = models::lookup(&config().prompt_suffixes, &body.model) {
+        normalize::append_suffix(&mut body.messages, suffix);
+    }
+}
 
-    info!(
+#[tracing::instrument(
+    skip_all,
+    fields(
+        request_id = %ctx.request_id,
+        client_ip = ?ctx.client_ip,
+        priority = ?ctx.priority,
+        authenticated = ctx.api_key.is_some(),
+        key_name = ctx.key_name.as_deref(),
+        otel.kind = "server",
+        traceparent = ctx.traceparent.as_deref(),
+        user = ctx.claims.as_ref().map(|claims| claims.user.as_str()),
+        model = %body.model,
+    )
+)]
+async fn handle_chat(
+    ctx: RequestContext,
+    accept: Option<String>,
+    mut body: ChatCompletionRequest,
+) -> Result<impl warp::Reply, Infallible> {
+    #[cfg(feature = "otel")]
+    common::otel::set_parent(&tracing::Span::current(), ctx.traceparent.as_deref());
+    let _in_flight = shutdown::track();
+    let Some(_client_slot) = client_limits::acquire(&ctx) else {
+        return Ok(client_limits::overloaded_reply());
+    };
+    let estimated_tokens = key_limits::estimate_tokens(&body.messages);
+    if let Err(retry_after) = key_limits::check(&ctx, estimated_tokens) {
+        return Ok(key_limits::rate_limited_reply(retry_after));
+    }
+    if !models::is_allowed(&body.model, &config().model_allowlist) {
+        warn!("Rejected chat request for model not on the allowlist");
+        let error = format!("model_not_found: model '{}' is not available", body.model);
+        return Ok(proxy::error_reply(error, warp::http::StatusCode::NOT_FOUND).into_response());
+    }
+    let variant = config()
+        .ab_splits
+        .iter()
+        .find(|(pattern, _)| models::matches(pattern, &body.model))
+        .map(|(_, split)| split.assign(&body.model, body.user.as_deref()).to_string());
+    if let Some(variant) = &variant {
+        debug!("A/B split assigned {} to {variant}", body.model);
+        body.model = variant.clone();
+    }
+    if let Err(retry_after) = model_limits::check(&body.model) {
+        return Ok(model_limits::rate_limited_reply(&body.model, retry_after));
+    }
+    if let Err(error) = injection::screen(&body.messages) {
+        return Ok(proxy::error_reply(error, warp::http::StatusCode::BAD_REQUEST).into_response());
+    }
+    let validator = match body.json_schema.as_ref().map(schema::compile).transpose() {
+        Ok(validator) => validator,
+        Err(error) => {
+            return Ok(
+                proxy::error_reply(error, warp::http::StatusCode::BAD_REQUEST).into_response(),
+            );
+        }
+    };
+    apply_prompt_rules(&mut body);
+    let cache_key = response_cache::key(&body, accept.as_deref());
+    if let Some(reply) = cache_key.as_ref().and_then(response_cache::lookup) {
+        debug!("Serving chat reply from the response cache");
+        let target = response_cache::CACHE_TARGET;
+        return Ok(finish_chat(reply, &ctx, &body.model, target, variant));
+    }
+    let admitted = match admission::admit().await {
+        Ok(admitted) => admitted,
+        Err(rejected) => return Ok(admission::rejected_reply(rejected)),
+    };
+    let Some(selected) = chat_target(&body) else {
+        warn!("Rejected chat request for model without a route");
+        let error = format!("no route configured for model '{}'", body.model);
+        return Ok(proxy::error_reply(error, warp::http::StatusCode::BAD_REQUEST).into_response());
+    };
+    let target = selected.url();
+    if selected.circuit_open() {
+        warn!("Failing fast: circuit open for {target}");
+        let error = format!("circuit open for backend {target}");
+        let reply = match config().fallback_message.as_deref() {
+            Some(fallback) => fallback::backend_failure(error, &body.model, Some(fallback)),
+            None => proxy::error_reply(error, warp::http::StatusCode::SERVICE_UNAVAILABLE),
+        };
+        return Ok(reply.into_response());
+    }
+
+    debug!(
         "Chat request: model={}, messages={}, target={}",
         body.model,
         body.messages.len(),
@@ -90,72 +517,264 @@ async fn handle_chat(body: ChatCompletionRequest) -> Result<impl warp::Reply, In
     );
 
     let client = HTTP_CLIENT.get().expect("client not initialized");
-    let resp = client.post(target).json(&body).send().await;
+    // The shadow sees the client's roles; only the primary's are renamed
+    let mirror = config()
+        .shadow_backend
+        .as_deref()
+        .map(|shadow| shadow::mirror(client, shadow, &body, &ctx.request_id));
+    // Recorded as the model saw it, but with the client's roles
+    let dataset_request = dataset::enabled().then(|| body.clone());
+    let role_map = roles::for_backend(&config().role_maps, target);
+    if let Some(map) = role_map {
+        map.forward(&mut body.messages);
+    }
+    let compress = config().gzip_backends.iter().any(|url| url == target);
+    let upstream = client
+        .post(target)
+        .header(context::request_id_header(), &ctx.request_id);
+    let upstream = proxy::with_traceparent(upstream, &ctx);
+    let upstream = proxy::with_timeout(upstream, config().chat_timeout);
+    let mut upstream = proxy::json_body(upstream, &body, compress);
+    // Pass content negotiation through (e.g. llm-node's MessagePack responses)
+    if let Some(accept) = &accept {
+        upstream = upstream.header("accept", accept);
+    }
+    let input_chars = body
+        .messages
+        .iter()
+        .map(|m| m.content.chars().count())
+        .sum();
+    latency::inject(config().injected_latency, input_chars).await;
+    let sent = std::time::Instant::now();
+    let attempts = retry::Attempts::new(config().max_total_attempts);
+    // A stream may already have reached the client when it fails, so it is never retried
+    let max_retries = if body.stream == Some(true) {
+        0
+    } else {
+        config().max_retries
+    };
+    let resp = retry::send(
+        upstream,
+        max_retries,
+        config().retry_backoff,
+        &retry::BUDGET,
+        &attempts,
+    )
+    .await;
+    proxy::log_version("Chat", &resp);
+    if let Some(mirror) = mirror {
+        // A failed primary (0) never matches; the shadow's outcome is only logged
+        mirror.primary_done(resp.as_ref().map_or(0, |r| r.status().as_u16()));
+    }
+    // Only completed calls count; fast connection failures would look like low latency
+    let succeeded = resp.as_ref().is_ok_and(|r| !r.status().is_server_error());
+    if succeeded {
+        selected.record(sent.elapsed());
+    }
+    selected.record_outcome(succeeded);
 
-    match resp {
-        Ok(r) => {
-            let status_code = r.status().as_u16();
-            let bytes = r.bytes().await.unwrap_or_default();
-            let warp_status =
-                warp::http::StatusCode::from_u16(status_code).unwrap_or(warp::http::StatusCode::OK);
-            Ok(warp::reply::with_status(
-                warp::reply::with_header(bytes.to_vec(), "Content-Type", "application/json"),
-                warp_status,
-            ))
+    let fallback_message = config().fallback_message.as_deref();
+    // Set by the relay when an empty reply is to become a 502
+    let empty_reply_error = Mutex::new(None);
+    let mut reply = match resp {
+        Ok(r) if retry::gave_up(&r, &attempts, max_retries) && fallback_message.is_none() => {
+            retry::gave_up_reply(r.status(), &attempts).into_response()
+        }
+        Ok(r) if r.status().is_server_error() && fallback_message.is_some() => {
+            let error = format!("llm-node returned {}", r.status());
+            fallback::backend_failure(error, &body.model, fallback_message).into_response()
         }
-        Err(e) => {
-            let error = ErrorResponse {
-                error: format!("llm-node unreachable: {e}"),
+        Ok(r) if r.status().is_success() && sse::is_stream(&r) => {
+            let session = ctx.session_id.as_deref();
+            let options = sse::StreamOptions {
+                role_map,
+                framing: sse::Framing::for_accept(accept.as_deref()),
+                ..sse::StreamOptions::from_config()
+            };
+            let bytes = metrics::ByteCounter::new("chat", &body.model, target);
+            let charge = key_limits::StreamCharge::new(&ctx, estimated_tokens);
+            let held = (selected, admitted);
+            let (bytes, charge) = (Some(bytes), Some(charge));
+            sse::relay_stream(r, options, session, ctx.emit_timing, bytes, charge, held)
+        }
+        Ok(r) => {
+            let forced = models::lookup(&config().content_type_overrides, target);
+            let content_type = proxy::content_type(&r, "application/json", forced);
+            let success = r.status().is_success();
+            let classify = !success && config().classify_upstream_errors;
+            let restore = |bytes: Vec<u8>| {
+                if classify {
+                    upstream_errors::record(&bytes);
+                }
+                let bytes = match role_map {
+                    Some(map) => map.restore(&bytes).unwrap_or(bytes),
+                    None => bytes,
+                };
+                let bytes = if success {
+                    empty_reply::handle(&config().empty_reply, bytes)
+                } else {
+                    Ok(bytes)
+                };
+                let bytes = match bytes {
+                    Ok(bytes) => bytes,
+                    Err(error) => {
+                        *empty_reply_error.lock().unwrap() = Some(error);
+                        return Vec::new();
+                    }
+                };
+                if success {
+                    key_limits::charge_usage(&ctx, estimated_tokens, &bytes);
+                    if let Some(key) = cache_key {
+                        response_cache::store(key, &content_type, &bytes);
+                    }
+                }
+                metrics::PROXIED_BYTES.add(&["chat", &body.model, target], bytes.len() as u64);
+                if let Some(request) = dataset_request.as_ref().filter(|_| success) {
+                    dataset::record(&ctx.request_id, request, &bytes);
+                }
+                bytes
             };
-            let json_body = serde_json::to_vec(&error).unwrap_or_default();
-            Ok(warp::reply::with_status(
-                warp::reply::with_header(json_body, "Content-Type", "application/json"),
-                warp::http::StatusCode::BAD_GATEWAY,
-            ))
+            match validator.as_ref().filter(|_| r.status().is_success()) {
+                Some(validator) => schema::relay_conforming(r, validator, restore).await,
+                None => proxy::relay_with(r, "application/json", forced, restore).await,
+            }
+            .into_response()
         }
+        Err(e) if e.is_timeout() && fallback_message.is_none() => {
+            proxy::timeout_reply(&format!("llm-node timed out: {e}")).into_response()
+        }
+        Err(e) => fallback::backend_failure(
+            format!("llm-node unreachable: {e}"),
+            &body.model,
+            fallback_message,
+        )
+        .into_response(),
+    };
+    if let Some(error) = empty_reply_error.into_inner().unwrap() {
+        reply = proxy::error_reply(error, warp::http::StatusCode::BAD_GATEWAY).into_response();
     }
+
+    Ok(finish_chat(reply, &ctx, &body.model, target, variant))
 }
 
-async fn handle_tts(body: TtsRequest) -> Result<impl warp::Reply, Infallible> {
-    let client = HTTP_CLIENT.get().expect("client not initialized");
-    let target = "http://localhost:9001/v1/audio/speech";
+/// Shared by proxied and cached chat replies: tag the A/B variant, then record
+/// metrics and the request log.
+fn finish_chat(
+    mut reply: warp::reply::Response,
+    ctx: &RequestContext,
+    model: &str,
+    target: &str,
+    variant: Option<String>,
+) -> warp::reply::Response {
+    if let Some(variant) = variant.and_then(|v| warp::http::HeaderValue::from_str(&v).ok()) {
+        reply.headers_mut().insert(ab_test::VARIANT_HEADER, variant);
+    }
+    metrics::observe_request("chat", model, target, reply.status(), ctx.started.elapsed());
+    request_log::log_outcome(
+        "chat",
+        &format!("model={model}, target={target}"),
+        reply.status(),
+        ctx.started.elapsed(),
+        config().log_sample_rate,
+    );
+    warn_if_slow(
+        "chat",
+        model,
+        target,
+        ctx.started.elapsed(),
+        config().slow_request,
+    );
+    reply
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        request_id = %ctx.request_id,
+        client_ip = ?ctx.client_ip,
+        priority = ?ctx.priority,
+        authenticated = ctx.api_key.is_some(),
+        key_name = ctx.key_name.as_deref(),
+        otel.kind = "server",
+        traceparent = ctx.traceparent.as_deref(),
+        user = ctx.claims.as_ref().map(|claims| claims.user.as_str()),
+        chars = body.input.len(),
+    )
+)]
+async fn handle_tts(ctx: RequestContext, body: TtsRequest) -> Result<impl warp::Reply, Infallible> {
+    #[cfg(feature = "otel")]
+    common::otel::set_parent(&tracing::Span::current(), ctx.traceparent.as_deref());
+    let _in_flight = shutdown::track();
+    let Some(_client_slot) = client_limits::acquire(&ctx) else {
+        return Ok(client_limits::overloaded_reply());
+    };
+    if let Err(retry_after) = key_limits::check(&ctx, 0) {
+        return Ok(key_limits::rate_limited_reply(retry_after));
+    }
+    let client = TTS_CLIENT.get().expect("client not initialized");
+    let target = TTS_TARGET;
 
-    info!(
+    debug!(
         "TTS request: {} chars, voice={:?}, format={:?}",
         body.input.len(),
         body.voice,
         body.format
     );
 
-    let resp = client.post(target).json(&body).send().await;
-    match resp {
+    let upstream = client
+        .post(target)
+        .header(context::request_id_header(), &ctx.request_id)
+        .json(&body);
+    let upstream = proxy::with_traceparent(upstream, &ctx);
+    latency::inject(config().injected_latency, body.input.chars().count()).await;
+    let resp = proxy::with_timeout(upstream, config().tts_timeout)
+        .send()
+        .await;
+    proxy::log_version("TTS", &resp);
+    let reply = match resp {
         Ok(r) => {
-            let status_code = r.status().as_u16();
-            let content_type = r
-                .headers()
-                .get("content-type")
-                .and_then(|v| v.to_str().ok())
-                .unwrap_or("application/octet-stream")
-                .to_string();
-            let bytes = r.bytes().await.unwrap_or_default();
-            let warp_status =
-                warp::http::StatusCode::from_u16(status_code).unwrap_or(warp::http::StatusCode::OK);
-            Ok(warp::reply::with_status(
-                warp::reply::with_header(bytes.to_vec(), "Content-Type", content_type),
-                warp_status,
-            ))
-        }
-        Err(e) => {
-            let error = ErrorResponse {
-                error: format!("TTS node unreachable: {e}"),
+            let forced = models::lookup(&config().content_type_overrides, target);
+            let lufs = r.headers().get(AUDIO_LUFS_HEADER).cloned();
+            let voice = body.voice.as_deref().unwrap_or("default");
+            let count = |bytes: Vec<u8>| {
+                metrics::PROXIED_BYTES.add(&["tts", voice, target], bytes.len() as u64);
+                bytes
             };
-            let json_body = serde_json::to_vec(&error).unwrap_or_default();
-            Ok(warp::reply::with_status(
-                warp::reply::with_header(json_body, "Content-Type", "application/json"),
-                warp::http::StatusCode::BAD_GATEWAY,
-            ))
+            let mut reply = proxy::relay_with(r, "application/octet-stream", forced, count)
+                .await
+                .into_response();
+            if let Some(lufs) = lufs {
+                reply.headers_mut().insert(AUDIO_LUFS_HEADER, lufs);
+            }
+            reply
         }
-    }
+        Err(e) if e.is_timeout() => {
+            proxy::timeout_reply(&format!("TTS node timed out: {e}")).into_response()
+        }
+        Err(e) => proxy::error_reply(
+            format!("TTS node unreachable: {e}"),
+            warp::http::StatusCode::BAD_GATEWAY,
+        )
+        .into_response(),
+    };
+
+    let voice = body.voice.as_deref().unwrap_or("default");
+    metrics::observe_request("tts", voice, target, reply.status(), ctx.started.elapsed());
+    request_log::log_outcome(
+        "tts",
+        &format!("voice={voice}, target={target}"),
+        reply.status(),
+        ctx.started.elapsed(),
+        config().log_sample_rate,
+    );
+    warn_if_slow(
+        "tts",
+        voice,
+        target,
+        ctx.started.elapsed(),
+        config().slow_request,
+    );
+    Ok(reply)
 }
 
 #[cfg(test)]
@@ -164,19 +783,35 @@ mod tests {
 
     #[test]
     fn test_get_llm_target_returns_default() {
-        // Currently all models route to the same endpoint
-        assert_eq!(
-            get_llm_target("qwen3-8b-instruct"),
-            "http://localhost:9000/v1/chat/completions"
-        );
-        assert_eq!(
-            get_llm_target("llama-3.1-8b"),
-            "http://localhost:9000/v1/chat/completions"
-        );
-        assert_eq!(
-            get_llm_target("unknown-model"),
-            "http://localhost:9000/v1/chat/completions"
-        );
+        // Without GATEWAY_ROUTES or GATEWAY_LLM_BACKENDS all models route to one endpoint
+        for model in ["qwen3-8b-instruct", "llama-3.1-8b", "unknown-model"] {
+            let pool = get_llm_target(model).expect("default pool");
+            assert_eq!(
+                pool.select().url(),
+                "http://localhost:9000/v1/chat/completions"
+            );
+            let body = ChatCompletionRequest {
+                model: model.into(),
+                messages: Vec::new(),
+                ..Default::default()
+            };
+            let selected = chat_target(&body).expect("default route");
+            assert_eq!(selected.url(), "http://localhost:9000/v1/chat/completions");
+        }
+    }
+
+    #[test]
+    fn test_backend_pool_spreads_load_by_outstanding_requests() {
+        let config = Config {
+            llm_backends: vec!["http://a".into(), "http://b".into()],
+            backend_selection: config::BackendSelection::LeastOutstanding,
+            ..Config::default()
+        };
+        let pool = backend_pool(&config);
+        let busy = pool.select();
+        for _ in 0..3 {
+            assert_ne!(pool.select().url(), busy.url());
+        }
     }
 
     #[test]
@@ -196,6 +831,7 @@ mod tests {
                 role: "user".into(),
                 content: "hello".into(),
             }],
+            ..Default::default()
         };
         let json = serde_json::to_string(&req).unwrap();
         assert!(json.contains("test"));
@@ -209,10 +845,81 @@ mod tests {
             input: "Hello world".into(),
             voice: Some("en_US".into()),
             format: Some("wav".into()),
+            ..Default::default()
         };
         let json = serde_json::to_string(&req).unwrap();
         assert!(json.contains("Hello world"));
         assert!(json.contains("en_US"));
         assert!(json.contains("wav"));
     }
+
+    #[tokio::test]
+    async fn test_chat_and_tts_use_their_own_timeouts() {
+        let slow = warp::any().then(|| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "done"
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(warp::serve(slow).incoming(listener).run());
+
+        let config = Config {
+            chat_timeout: Some(Duration::from_millis(50)),
+            tts_timeout: Some(Duration::from_secs(5)),
+            ..Config::default()
+        };
+        let client = Client::new();
+        let chat = proxy::with_timeout(client.post(&url), config.chat_timeout);
+        let err = chat.send().await.unwrap_err();
+        assert!(err.is_timeout(), "{err}");
+        let message = format!("llm-node timed out: {err}");
+        let timed_out = warp::any().map(move || proxy::timeout_reply(&message));
+        let resp = warp::test::request().reply(&timed_out).await;
+        assert_eq!(resp.status(), warp::http::StatusCode::GATEWAY_TIMEOUT);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["error"]["type"], "timeout_error");
+        assert_eq!(body["error"]["code"], "upstream_timeout");
+
+        let tts = proxy::with_timeout(client.post(&url), config.tts_timeout);
+        let resp = tts.send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "done");
+    }
+
+    #[test]
+    fn test_slow_request_logs_warning() {
+        let threshold = Some(Duration::from_millis(100));
+        let logs = test_support::capture_logs(|| {
+            assert!(warn_if_slow(
+                "chat",
+                "qwen3-8b",
+                "http://localhost:9000/v1/chat/completions",
+                Duration::from_millis(250),
+                threshold,
+            ));
+        });
+        assert!(logs.contains("WARN"));
+        assert!(logs.contains("model=qwen3-8b"));
+        assert!(logs.contains("duration_ms=250"));
+    }
+
+    #[test]
+    fn test_fast_or_unconfigured_request_is_not_logged() {
+        let logs = test_support::capture_logs(|| {
+            assert!(!warn_if_slow(
+                "chat",
+                "m",
+                "t",
+                Duration::from_millis(50),
+                Some(Duration::from_millis(100)),
+            ));
+            assert!(!warn_if_slow(
+                "chat",
+                "m",
+                "t",
+                Duration::from_secs(60),
+                None
+            ));
+        });
+        assert!(logs.is_empty());
+    }
 }
@@ -0,0 +1,336 @@
+//! Process-wide counters served at `GET /metrics` in the Prometheus text format.
+//!
+//! Chat and TTS requests are counted by route, model, backend and status (so error
+//! rates are a ratio of `status=~"5.."` to all), timed in a latency histogram, and the
+//! response bytes relayed from backends are summed.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use warp::Filter;
+use warp::http::StatusCode;
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A counter split by label values, e.g. `name{type="x",code="y"}`.
+pub struct LabeledCounter {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl LabeledCounter {
+    const fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Count one event; `values` pairs up with the counter's label names.
+    pub fn inc(&self, values: &[&str]) {
+        self.add(values, 1);
+    }
+
+    pub fn add(&self, values: &[&str], amount: u64) {
+        let key = values.iter().map(|v| v.to_string()).collect();
+        *self.values.lock().unwrap().entry(key).or_insert(0) += amount;
+    }
+
+    fn render(&self) -> String {
+        let mut out = format!("# HELP {0} {1}\n# TYPE {0} counter\n", self.name, self.help);
+        for (values, count) in self.values.lock().unwrap().iter() {
+            let labels = label_pairs(self.labels, values).join(",");
+            out.push_str(&format!("{}{{{labels}}} {count}\n", self.name));
+        }
+        out
+    }
+}
+
+/// Per-label-set state of a [`LabeledHistogram`].
+#[derive(Default)]
+struct Observations {
+    /// Observations per bucket (not cumulative), plus one for `+Inf`.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// A histogram split by label values, with fixed bucket bounds.
+pub struct LabeledHistogram {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    /// Upper bounds, ascending.
+    bounds: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, Observations>>,
+}
+
+impl LabeledHistogram {
+    const fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+        bounds: &'static [f64],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            bounds,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, values: &[&str], value: f64) {
+        let key = values.iter().map(|v| v.to_string()).collect();
+        let mut all = self.values.lock().unwrap();
+        let observations = all.entry(key).or_default();
+        if observations.buckets.is_empty() {
+            observations.buckets = vec![0; self.bounds.len() + 1];
+        }
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        observations.buckets[bucket] += 1;
+        observations.sum += value;
+        observations.count += 1;
+    }
+
+    fn render(&self) -> String {
+        let mut out = format!(
+            "# HELP {0} {1}\n# TYPE {0} histogram\n",
+            self.name, self.help
+        );
+        for (values, observations) in self.values.lock().unwrap().iter() {
+            let labels = label_pairs(self.labels, values);
+            let les = self
+                .bounds
+                .iter()
+                .map(f64::to_string)
+                .chain(["+Inf".into()]);
+            let mut cumulative = 0;
+            for (le, count) in les.zip(&observations.buckets) {
+                cumulative += count;
+                let mut with_le = labels.clone();
+                with_le.push(format!("le=\"{le}\""));
+                out.push_str(&format!(
+                    "{}_bucket{{{}}} {cumulative}\n",
+                    self.name,
+                    with_le.join(",")
+                ));
+            }
+            let labels = labels.join(",");
+            out.push_str(&format!(
+                "{}_sum{{{labels}}} {}\n",
+                self.name, observations.sum
+            ));
+            out.push_str(&format!(
+                "{}_count{{{labels}}} {}\n",
+                self.name, observations.count
+            ));
+        }
+        out
+    }
+}
+
+fn label_pairs(labels: &[&str], values: &[String]) -> Vec<String> {
+    labels
+        .iter()
+        .zip(values)
+        .map(|(label, value)| format!("{label}=\"{}\"", escape_label(value)))
+        .collect()
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+pub static UPSTREAM_RETRIES: Counter = Counter::new(
+    "gateway_upstream_retries_total",
+    "Chat requests re-sent to the backend after a failed attempt.",
+);
+pub static RETRY_BUDGET_EXHAUSTED: Counter = Counter::new(
+    "gateway_retry_budget_exhausted_total",
+    "Retries skipped because the retry budget was empty.",
+);
+
+pub static SHADOW_REQUESTS: Counter = Counter::new(
+    "gateway_shadow_requests_total",
+    "Chat requests mirrored to the shadow backend.",
+);
+pub static SHADOW_FAILURES: Counter = Counter::new(
+    "gateway_shadow_failures_total",
+    "Mirrored requests the shadow backend failed to answer.",
+);
+pub static SHADOW_MISMATCHES: Counter = Counter::new(
+    "gateway_shadow_status_mismatches_total",
+    "Mirrored requests where the shadow's status differed from the primary's.",
+);
+
+pub static UPSTREAM_ERRORS: LabeledCounter = LabeledCounter::new(
+    "gateway_upstream_errors_total",
+    "Chat backend error replies by OpenAI error type and code (GATEWAY_CLASSIFY_UPSTREAM_ERRORS).",
+    &["type", "code"],
+);
+
+pub static REQUESTS: LabeledCounter = LabeledCounter::new(
+    "gateway_requests_total",
+    "Chat and TTS requests answered, by route, model, backend and status.",
+    &["route", "model", "backend", "status"],
+);
+
+pub static REQUEST_DURATION: LabeledHistogram = LabeledHistogram::new(
+    "gateway_request_duration_seconds",
+    "Time from receiving a chat or TTS request to its reply headers.",
+    &["route", "model", "backend"],
+    &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0],
+);
+
+pub static PROXIED_BYTES: LabeledCounter = LabeledCounter::new(
+    "gateway_proxied_bytes_total",
+    "Response body bytes relayed from backends (event data only for streams).",
+    &["route", "model", "backend"],
+);
+
+/// Record a finished request in [`REQUESTS`] and [`REQUEST_DURATION`].
+pub fn observe_request(
+    route: &str,
+    model: &str,
+    backend: &str,
+    status: StatusCode,
+    elapsed: Duration,
+) {
+    REQUESTS.inc(&[route, model, backend, status.as_str()]);
+    REQUEST_DURATION.observe(&[route, model, backend], elapsed.as_secs_f64());
+}
+
+/// Adds to [`PROXIED_BYTES`] under fixed labels as a stream is relayed.
+pub struct ByteCounter {
+    labels: [String; 3],
+}
+
+impl ByteCounter {
+    pub fn new(route: &str, model: &str, backend: &str) -> Self {
+        Self {
+            labels: [route.into(), model.into(), backend.into()],
+        }
+    }
+
+    pub fn add(&self, bytes: usize) {
+        let [route, model, backend] = &self.labels;
+        PROXIED_BYTES.add(&[route, model, backend], bytes as u64);
+    }
+}
+
+const COUNTERS: &[&Counter] = &[
+    &UPSTREAM_RETRIES,
+    &RETRY_BUDGET_EXHAUSTED,
+    &SHADOW_REQUESTS,
+    &SHADOW_FAILURES,
+    &SHADOW_MISMATCHES,
+];
+
+const LABELED_COUNTERS: &[&LabeledCounter] = &[&UPSTREAM_ERRORS, &REQUESTS, &PROXIED_BYTES];
+
+const HISTOGRAMS: &[&LabeledHistogram] = &[&REQUEST_DURATION];
+
+fn render() -> String {
+    let plain = COUNTERS.iter().map(|c| {
+        format!(
+            "# HELP {0} {1}\n# TYPE {0} counter\n{0} {2}\n",
+            c.name,
+            c.help,
+            c.get()
+        )
+    });
+    plain
+        .chain(LABELED_COUNTERS.iter().map(|c| c.render()))
+        .chain(HISTOGRAMS.iter().map(|h| h.render()))
+        .collect()
+}
+
+/// `GET /metrics`.
+pub fn route() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::with_header(render(), "content-type", "text/plain; version=0.0.4"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_route_lists_counters() {
+        let resp = warp::test::request().path("/metrics").reply(&route()).await;
+        assert_eq!(resp.status(), 200);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("# TYPE gateway_retry_budget_exhausted_total counter"));
+        assert!(body.contains("\ngateway_upstream_retries_total "), "{body}");
+    }
+
+    #[test]
+    fn test_labeled_counter_renders_each_label_set() {
+        let counter = LabeledCounter::new("errs_total", "Errors.", &["type", "code"]);
+        counter.inc(&["rate_limit_error", ""]);
+        counter.inc(&["rate_limit_error", ""]);
+        counter.inc(&["server_error", "say \"hi\""]);
+        assert_eq!(
+            counter.render(),
+            "# HELP errs_total Errors.\n# TYPE errs_total counter\n\
+             errs_total{type=\"rate_limit_error\",code=\"\"} 2\n\
+             errs_total{type=\"server_error\",code=\"say \\\"hi\\\"\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn test_histogram_renders_cumulative_buckets() {
+        let histogram = LabeledHistogram::new("lat_seconds", "Latency.", &["route"], &[0.1, 1.0]);
+        histogram.observe(&["chat"], 0.05);
+        histogram.observe(&["chat"], 0.5);
+        histogram.observe(&["chat"], 3.0);
+        assert_eq!(
+            histogram.render(),
+            "# HELP lat_seconds Latency.\n# TYPE lat_seconds histogram\n\
+             lat_seconds_bucket{route=\"chat\",le=\"0.1\"} 1\n\
+             lat_seconds_bucket{route=\"chat\",le=\"1\"} 2\n\
+             lat_seconds_bucket{route=\"chat\",le=\"+Inf\"} 3\n\
+             lat_seconds_sum{route=\"chat\"} 3.55\n\
+             lat_seconds_count{route=\"chat\"} 3\n"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Per-model request rate limits (`GATEWAY_MODEL_RATE_LIMITS`), protecting scarce
+//! backends independently of the per-client concurrency limit.
+//!
+//! Each rule's pattern owns one token bucket, shared by every model it matches. The
+//! bucket holds up to a minute's allowance and refills continuously; a chat request
+//! for a model whose bucket is empty gets `429` with `Retry-After` set to the wait
+//! for the next token. Models without a rule are unlimited.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+use warp::http::StatusCode;
+
+use crate::{config, models, proxy};
+
+static MODEL_LIMITER: LazyLock<ModelLimiter> =
+    LazyLock::new(|| ModelLimiter::new(config().model_rate_limits.clone()));
+
+/// Take one request from `model`'s bucket; `Err` holds the wait before retrying.
+pub fn check(model: &str) -> Result<(), Duration> {
+    let result = MODEL_LIMITER.try_take(model, Instant::now());
+    if result.is_err() {
+        warn!("Rejected chat request: model is over its rate limit");
+    }
+    result
+}
+
+pub fn rate_limited_reply(model: &str, retry_after: Duration) -> warp::reply::Response {
+    too_many_requests(
+        format!("rate limit exceeded for model '{model}'"),
+        retry_after,
+    )
+}
+
+/// `429` with `Retry-After` set to `retry_after`.
+pub fn too_many_requests(error: String, retry_after: Duration) -> warp::reply::Response {
+    let reply = proxy::error_reply(error, StatusCode::TOO_MANY_REQUESTS);
+    proxy::with_retry_after(reply, retry_after)
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct ModelLimiter {
+    /// `(pattern, requests per minute)`, first match wins.
+    rules: Vec<(String, f64)>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl ModelLimiter {
+    pub fn new(rules: Vec<(String, f64)>) -> Self {
+        Self {
+            rules,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn try_take(&self, model: &str, now: Instant) -> Result<(), Duration> {
+        let Some((pattern, per_minute)) = self
+            .rules
+            .iter()
+            .find(|(pattern, _)| models::matches(pattern, model))
+        else {
+            return Ok(());
+        };
+        let per_sec = per_minute / 60.0;
+        let capacity = per_minute.max(1.0);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(pattern.clone()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limited_model_exhausted_while_other_model_available() {
+        let limiter = ModelLimiter::new(vec![
+            ("big-*".to_string(), 2.0),
+            ("cheap".to_string(), 600.0),
+        ]);
+        let now = Instant::now();
+        assert!(limiter.try_take("big-70b", now).is_ok());
+        assert!(limiter.try_take("big-405b", now).is_ok());
+        // The pattern's bucket is shared by every model it matches
+        let wait = limiter.try_take("big-70b", now).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(30));
+
+        for _ in 0..10 {
+            assert!(limiter.try_take("cheap", now).is_ok());
+        }
+        assert!(limiter.try_take("unlisted", now).is_ok());
+
+        // Half a minute refills one request at 2/min
+        let later = now + Duration::from_secs(30);
+        assert!(limiter.try_take("big-70b", later).is_ok());
+        assert!(limiter.try_take("big-70b", later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limited_reply_sets_retry_after() {
+        let resp = rate_limited_reply("big-70b", Duration::from_millis(1500));
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["retry-after"], "2");
+    }
+}
@@ -0,0 +1,61 @@
+//! Model name patterns, used by the allowlist (`GATEWAY_MODEL_ALLOWLIST`) and
+//! per-model rules. Per-backend rules match backend URLs the same way.
+//!
+//! Patterns are exact model names, or prefixes when they end in `*` (e.g. `qwen3-*`);
+//! a bare `*` matches every model.
+
+pub fn matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => model == pattern,
+    }
+}
+
+/// An empty allowlist permits every model.
+pub fn is_allowed(model: &str, allowlist: &[String]) -> bool {
+    allowlist.is_empty() || allowlist.iter().any(|pattern| matches(pattern, model))
+}
+
+/// The value of the first `(pattern, value)` rule matching `model`.
+pub fn lookup<'a>(rules: &'a [(String, String)], model: &str) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|(pattern, _)| matches(pattern, model))
+        .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_allowed_models() {
+        let allow = list(&["llama-3-8b", "qwen3-*"]);
+        assert!(is_allowed("llama-3-8b", &allow));
+        assert!(is_allowed("qwen3-7b-instruct", &allow));
+        assert!(is_allowed("anything", &[]));
+    }
+
+    #[test]
+    fn test_disallowed_models() {
+        let allow = list(&["llama-3-8b", "qwen3-*"]);
+        assert!(!is_allowed("llama-3-8b-chat", &allow));
+        assert!(!is_allowed("gpt-4", &allow));
+        assert!(!is_allowed("qwen2-7b", &allow));
+    }
+
+    #[test]
+    fn test_lookup_uses_first_matching_rule() {
+        let rules = vec![
+            ("qwen3-*".to_string(), "json".to_string()),
+            ("*".to_string(), "brief".to_string()),
+        ];
+        assert_eq!(lookup(&rules, "qwen3-7b"), Some("json"));
+        assert_eq!(lookup(&rules, "llama"), Some("brief"));
+        assert_eq!(lookup(&rules[..1], "llama"), None);
+    }
+}
@@ -0,0 +1,100 @@
+//! Optional rewrites applied to chat requests before they are forwarded upstream.
+
+use crate::ChatMessage;
+
+/// Collapse runs of messages sharing a role into one, joining content with newlines.
+///
+/// Some backends reject conversations with two consecutive messages from the same role.
+pub fn merge_consecutive_roles(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let mut merged: Vec<ChatMessage> = Vec::with_capacity(messages.len());
+    for message in messages {
+        match merged.last_mut() {
+            Some(prev) if prev.role == message.role => {
+                prev.content.push('\n');
+                prev.content.push_str(&message.content);
+            }
+            _ => merged.push(message),
+        }
+    }
+    merged
+}
+
+/// Append `suffix` to the last user message, or add it as a user message if there is none.
+///
+/// Only the forwarded copy is changed; the client never sees the suffix echoed back.
+pub fn append_suffix(messages: &mut Vec<ChatMessage>, suffix: &str) {
+    match messages.iter_mut().rev().find(|m| m.role == "user") {
+        Some(last_user) => {
+            last_user.content.push('\n');
+            last_user.content.push_str(suffix);
+        }
+        None => messages.push(ChatMessage {
+            role: "user".into(),
+            content: suffix.into(),
+        }),
+    }
+}
+
+/// Put `prompt` first in the conversation as the system message, ahead of any system
+/// message the client sent.
+pub fn prepend_system(messages: &mut Vec<ChatMessage>, prompt: String) {
+    match messages.first_mut() {
+        Some(first) if first.role == "system" => {
+            first.content = format!("{prompt}\n{}", first.content);
+        }
+        _ => messages.insert(
+            0,
+            ChatMessage {
+                role: "system".into(),
+                content: prompt,
+            },
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: content.into(),
+        }
+    }
+
+    #[test]
+    fn test_consecutive_user_messages_are_merged() {
+        let merged = merge_consecutive_roles(vec![
+            msg("system", "be brief"),
+            msg("user", "hello"),
+            msg("user", "are you there?"),
+            msg("assistant", "yes"),
+            msg("user", "ok"),
+        ]);
+
+        let roles: Vec<_> = merged.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert_eq!(merged[1].content, "hello\nare you there?");
+    }
+
+    #[test]
+    fn test_suffix_appended_to_last_user_message() {
+        let mut messages = vec![
+            msg("user", "first"),
+            msg("assistant", "ok"),
+            msg("user", "List three colours."),
+            msg("assistant", "(partial)"),
+        ];
+        append_suffix(&mut messages, "Respond in JSON.");
+
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].content, "first");
+        assert_eq!(messages[2].content, "List three colours.\nRespond in JSON.");
+
+        let mut system_only = vec![msg("system", "be brief")];
+        append_suffix(&mut system_only, "Respond in JSON.");
+        assert_eq!(system_only[1].role, "user");
+        assert_eq!(system_only[1].content, "Respond in JSON.");
+    }
+}
@@ -0,0 +1,308 @@
+//! Helpers for relaying upstream responses (or failures) back to the client.
+
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Serialize;
+use tracing::debug;
+use warp::Reply;
+use warp::http::StatusCode;
+use warp::reply::{WithHeader, WithStatus};
+
+use crate::ErrorResponse;
+use crate::context::RequestContext;
+
+/// Buffered reply shared by the proxying handlers.
+pub type ProxyReply = WithStatus<WithHeader<Vec<u8>>>;
+
+/// The shared upstream client. With `http2` (`GATEWAY_UPSTREAM_HTTP2`) plain-HTTP
+/// backends are spoken to in HTTP/2 from the first byte, multiplexing requests over
+/// one connection, so every backend must accept h2c; otherwise requests use HTTP/1.1.
+/// TLS backends negotiate the version through ALPN either way. `connect_timeout`
+/// bounds establishing each new connection.
+pub fn client(http2: bool, connect_timeout: Option<Duration>) -> reqwest::Result<Client> {
+    let mut builder = Client::builder();
+    if let Some(timeout) = connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if http2 {
+        builder.http2_prior_knowledge().build()
+    } else {
+        builder.build()
+    }
+}
+
+/// Log the HTTP version an upstream `route` call was answered with.
+pub fn log_version(route: &str, resp: &reqwest::Result<reqwest::Response>) {
+    if let Ok(resp) = resp {
+        debug!("{route} backend replied over {:?}", resp.version());
+    }
+}
+
+pub fn json_reply(body: Vec<u8>, status: StatusCode) -> ProxyReply {
+    warp::reply::with_status(
+        warp::reply::with_header(body, "Content-Type", "application/json"),
+        status,
+    )
+}
+
+pub fn error_reply(error: String, status: StatusCode) -> ProxyReply {
+    let pretty = crate::CONFIG
+        .get()
+        .is_some_and(|config| config.pretty_errors);
+    json_reply(error_body(&ErrorResponse { error }, pretty), status)
+}
+
+/// `reply` with `Retry-After` set to `retry_after`, in whole seconds rounded up so a
+/// retry at that time finds room.
+pub fn with_retry_after(reply: ProxyReply, retry_after: Duration) -> warp::reply::Response {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    warp::reply::with_header(reply, "retry-after", secs.max(1).to_string()).into_response()
+}
+
+/// OpenAI-shaped `{"error": {"message", "type", "param", "code"}}`, for errors SDKs
+/// branch on by `type` or `code`.
+pub fn typed_error_reply(message: &str, kind: &str, code: &str, status: StatusCode) -> ProxyReply {
+    let pretty = crate::CONFIG
+        .get()
+        .is_some_and(|config| config.pretty_errors);
+    let error = serde_json::json!({
+        "error": { "message": message, "type": kind, "param": null, "code": code }
+    });
+    json_reply(error_body(&error, pretty), status)
+}
+
+/// `504` for a backend call that ran past its connect or total timeout.
+pub fn timeout_reply(message: &str) -> ProxyReply {
+    typed_error_reply(
+        message,
+        "timeout_error",
+        "upstream_timeout",
+        StatusCode::GATEWAY_TIMEOUT,
+    )
+}
+
+/// Indented for reading in a terminal when `GATEWAY_PRETTY_ERRORS` is on.
+fn error_body(error: &impl Serialize, pretty: bool) -> Vec<u8> {
+    let body = if pretty {
+        serde_json::to_vec_pretty(error)
+    } else {
+        serde_json::to_vec(error)
+    };
+    body.unwrap_or_default()
+}
+
+/// Attach `body` as JSON, gzip-compressed when the backend accepts it.
+pub fn json_body(
+    req: reqwest::RequestBuilder,
+    body: &impl Serialize,
+    compress: bool,
+) -> reqwest::RequestBuilder {
+    if !compress {
+        return req.json(body);
+    }
+    let json = serde_json::to_vec(body).unwrap_or_default();
+    req.header("content-type", "application/json")
+        .header("content-encoding", "gzip")
+        .body(common::gzip::compress(&json))
+}
+
+/// Bound the whole upstream call, body included, when the endpoint has a timeout.
+pub fn with_timeout(
+    req: reqwest::RequestBuilder,
+    timeout: Option<Duration>,
+) -> reqwest::RequestBuilder {
+    match timeout {
+        Some(timeout) => req.timeout(timeout),
+        None => req,
+    }
+}
+
+/// Pass a `traceparent` to the backend: the gateway's own span when exporting traces
+/// (`otel`), else the client's header as received.
+pub fn with_traceparent(
+    req: reqwest::RequestBuilder,
+    ctx: &RequestContext,
+) -> reqwest::RequestBuilder {
+    #[cfg(feature = "otel")]
+    let own = common::otel::current_traceparent();
+    #[cfg(not(feature = "otel"))]
+    let own = None;
+    match own.or_else(|| ctx.traceparent.clone()) {
+        Some(traceparent) => req.header("traceparent", traceparent),
+        None => req,
+    }
+}
+
+/// Forward an upstream response's status, content type and body unchanged.
+/// A `forced_content_type` replaces whatever type the upstream sent.
+pub async fn relay(
+    resp: reqwest::Response,
+    default_content_type: &str,
+    forced_content_type: Option<&str>,
+) -> ProxyReply {
+    relay_with(resp, default_content_type, forced_content_type, |bytes| {
+        bytes
+    })
+    .await
+}
+
+/// The content type [`relay_with`] gives `resp`'s body: `forced_content_type`, else
+/// the upstream's, else `default_content_type`.
+pub fn content_type(
+    resp: &reqwest::Response,
+    default_content_type: &str,
+    forced_content_type: Option<&str>,
+) -> String {
+    forced_content_type
+        .or_else(|| {
+            resp.headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+        })
+        .unwrap_or(default_content_type)
+        .to_string()
+}
+
+/// [`relay`], passing the body through `transform` first.
+pub async fn relay_with(
+    resp: reqwest::Response,
+    default_content_type: &str,
+    forced_content_type: Option<&str>,
+    transform: impl FnOnce(Vec<u8>) -> Vec<u8>,
+) -> ProxyReply {
+    let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::OK);
+    let content_type = content_type(&resp, default_content_type, forced_content_type);
+    let bytes = resp.bytes().await.unwrap_or_default();
+    warp::reply::with_status(
+        warp::reply::with_header(transform(bytes.to_vec()), "Content-Type", content_type),
+        status,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::{Filter, Reply};
+
+    fn upstream(content_type: &str) -> reqwest::Response {
+        warp::http::Response::builder()
+            .header("content-type", content_type)
+            .body(r#"{"ok":true}"#)
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_upstream_http2_when_enabled() {
+        let backend = warp::path!("v1" / "chat" / "completions").map(|| "ok");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(backend).incoming(listener).run());
+        let url = format!("http://{addr}/v1/chat/completions");
+
+        let resp = client(true, None).unwrap().get(&url).send().await.unwrap();
+        assert_eq!(resp.version(), reqwest::Version::HTTP_2);
+        assert_eq!(resp.text().await.unwrap(), "ok");
+
+        let resp = client(false, None).unwrap().get(&url).send().await.unwrap();
+        assert_eq!(resp.version(), reqwest::Version::HTTP_11);
+    }
+
+    #[test]
+    fn test_pretty_errors_are_multiline() {
+        let error = ErrorResponse {
+            error: "no route".into(),
+        };
+        let compact = String::from_utf8(error_body(&error, false)).unwrap();
+        assert_eq!(compact, r#"{"error":"no route"}"#);
+        let pretty = String::from_utf8(error_body(&error, true)).unwrap();
+        assert_eq!(pretty, "{\n  \"error\": \"no route\"\n}");
+    }
+
+    #[tokio::test]
+    async fn test_forced_content_type_overrides_upstream() {
+        let resp = relay(upstream("text/plain"), "application/json", None)
+            .await
+            .into_response();
+        assert_eq!(resp.headers()["content-type"], "text/plain");
+
+        let resp = relay(
+            upstream("text/plain"),
+            "application/json",
+            Some("application/json"),
+        )
+        .await
+        .into_response();
+        assert_eq!(resp.headers()["content-type"], "application/json");
+    }
+
+    #[cfg(not(feature = "otel"))]
+    #[test]
+    fn test_client_traceparent_forwarded() {
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let mut headers = warp::http::HeaderMap::new();
+        headers.insert("traceparent", traceparent.parse().unwrap());
+        let ctx = RequestContext::from_headers(&headers, &[]);
+        let req = with_traceparent(Client::new().post("http://node/"), &ctx)
+            .build()
+            .unwrap();
+        assert_eq!(req.headers()["traceparent"], traceparent);
+
+        let ctx = RequestContext::from_headers(&warp::http::HeaderMap::new(), &[]);
+        let req = with_traceparent(Client::new().post("http://node/"), &ctx)
+            .build()
+            .unwrap();
+        assert!(req.headers().get("traceparent").is_none());
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn test_request_span_exported_and_sent_as_parent() {
+        use opentelemetry::trace::{SpanId, TraceId, TracerProvider};
+        use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("gateway")));
+        let mut headers = warp::http::HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+                .parse()
+                .unwrap(),
+        );
+        let ctx = RequestContext::from_headers(&headers, &[]);
+
+        let req = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("handle_chat", otel.kind = "server");
+            common::otel::set_parent(&span, ctx.traceparent.as_deref());
+            span.in_scope(|| with_traceparent(Client::new().post("http://node/"), &ctx))
+                .build()
+                .unwrap()
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans
+            .iter()
+            .find(|span| span.name == "handle_chat")
+            .expect("request span exported");
+        let context = &span.span_context;
+        assert_eq!(
+            context.trace_id(),
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert_eq!(
+            span.parent_span_id,
+            SpanId::from_hex("b7ad6b7169203331").unwrap()
+        );
+        assert_eq!(
+            req.headers()["traceparent"],
+            format!("00-{}-{}-01", context.trace_id(), context.span_id()).as_str()
+        );
+    }
+}
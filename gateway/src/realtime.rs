@@ -0,0 +1,186 @@
+//! Real-time TTS over WebSocket: each text message is synthesized by tts-node and
+//! the audio body is pushed back as binary frames while it is still arriving.
+
+use std::time::Duration;
+
+use futures_util::{Sink, SinkExt, StreamExt};
+use reqwest::Client;
+use tracing::info;
+use warp::Filter;
+use warp::ws::{Message, WebSocket, Ws};
+
+use crate::TtsRequest;
+use crate::auth::{self, ApiKey};
+use crate::context::RequestContext;
+use crate::proxy;
+
+/// `GET /v1/audio/realtime` WebSocket route proxying to the given tts-node URL, each
+/// utterance limited by `timeout`. Callers authenticate like the HTTP routes; `admit`
+/// then decides whether the connection may open, and what it returns (e.g. a client
+/// slot) is held until the session ends.
+pub fn route<H: Send + 'static>(
+    client: Client,
+    target: String,
+    keys: &'static [ApiKey],
+    timeout: Option<Duration>,
+    admit: impl Fn(&RequestContext) -> Result<H, Box<dyn warp::Reply>> + Clone + Send + Sync,
+) -> impl Filter<Extract = (Box<dyn warp::Reply>,), Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "audio" / "realtime")
+        .and(warp::get())
+        .and(auth::authorized(keys))
+        .and(warp::ws())
+        .map(move |ctx: RequestContext, ws: Ws| -> Box<dyn warp::Reply> {
+            let held = match admit(&ctx) {
+                Ok(held) => held,
+                Err(reply) => return reply,
+            };
+            let (client, target) = (client.clone(), target.clone());
+            Box::new(ws.on_upgrade(move |socket| async move {
+                let _held = held;
+                session(socket, &client, &target, timeout).await;
+            }))
+        })
+}
+
+/// Serve one client until it closes or disconnects. Pings and closes are answered
+/// by the WebSocket layer; binary messages are ignored.
+async fn session(socket: WebSocket, client: &Client, target: &str, timeout: Option<Duration>) {
+    let (mut tx, mut rx) = socket.split();
+    while let Some(Ok(message)) = rx.next().await {
+        if message.is_close() {
+            break;
+        }
+        let Ok(text) = message.to_str() else {
+            continue;
+        };
+        let text = text.to_string();
+        if stream_speech(client, target, timeout, text, &mut tx)
+            .await
+            .is_err()
+        {
+            // The client went away; dropping the upstream response stops synthesis.
+            info!("realtime TTS client disconnected");
+            break;
+        }
+    }
+}
+
+/// Synthesize one utterance and forward each upstream body chunk as a binary frame.
+///
+/// Text may be plain input or a JSON `TtsRequest`. Upstream failures are reported to
+/// the client as a JSON text frame; only socket write errors are returned.
+async fn stream_speech<W: Sink<Message, Error = warp::Error> + Unpin>(
+    client: &Client,
+    target: &str,
+    timeout: Option<Duration>,
+    text: String,
+    wr: &mut W,
+) -> Result<(), warp::Error> {
+    let body = serde_json::from_str::<TtsRequest>(&text).unwrap_or(TtsRequest {
+        input: text,
+        ..Default::default()
+    });
+
+    let upstream = proxy::with_timeout(client.post(target).json(&body), timeout);
+    let mut resp = match upstream.send().await {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => return send_error(wr, &format!("tts-node returned {}", r.status())).await,
+        Err(e) => return send_error(wr, &format!("TTS node unreachable: {e}")).await,
+    };
+
+    loop {
+        match resp.chunk().await {
+            Ok(Some(chunk)) => wr.send(Message::binary(chunk)).await?,
+            Ok(None) => return Ok(()),
+            Err(e) => return send_error(wr, &format!("TTS stream failed: {e}")).await,
+        }
+    }
+}
+
+async fn send_error<W: Sink<Message, Error = warp::Error> + Unpin>(
+    wr: &mut W,
+    error: &str,
+) -> Result<(), warp::Error> {
+    let body = serde_json::json!({ "error": error }).to_string();
+    wr.send(Message::text(body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::StatusCode;
+
+    async fn spawn<F>(filter: F) -> std::net::SocketAddr
+    where
+        F: Filter + Clone + Send + Sync + 'static,
+        F::Extract: warp::Reply,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(filter).incoming(listener).run());
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_text_message_streams_audio_frames() {
+        let audio = warp::path!("v1" / "audio" / "speech")
+            .and(warp::post())
+            .map(|| b"RIFF-fake-audio".to_vec());
+        let tts = spawn(audio).await;
+        let target = format!("http://{tts}/v1/audio/speech");
+        let realtime = route(
+            Client::new(),
+            target,
+            &[],
+            None,
+            |_: &RequestContext| Ok(()),
+        );
+
+        let mut client = warp::test::ws()
+            .path("/v1/audio/realtime")
+            .handshake(realtime)
+            .await
+            .expect("handshake");
+        client.send_text("hello").await;
+
+        let mut received = Vec::new();
+        while received.len() < b"RIFF-fake-audio".len() {
+            let message = client.recv().await.unwrap();
+            assert!(message.is_binary(), "unexpected message {message:?}");
+            received.extend_from_slice(message.as_bytes());
+        }
+        assert_eq!(received, b"RIFF-fake-audio");
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_upgrade_rejected() {
+        let keys = Box::leak(Box::new(vec![ApiKey {
+            name: "demo".into(),
+            key: "secret".into(),
+        }]));
+        let realtime = route(
+            Client::new(),
+            "http://127.0.0.1:9/v1/audio/speech".into(),
+            keys,
+            None,
+            |_: &RequestContext| Ok(()),
+        )
+        .recover(auth::recover_unauthorized);
+        let upgrade = || {
+            warp::test::request()
+                .path("/v1/audio/realtime")
+                .header("connection", "Upgrade")
+                .header("upgrade", "websocket")
+                .header("sec-websocket-version", "13")
+                .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        };
+
+        let resp = upgrade().reply(&realtime).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = upgrade()
+            .header("authorization", "Bearer wrong")
+            .reply(&realtime)
+            .await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,169 @@
+//! Opt-in re-ordering of streamed chunks that arrive out of sequence.
+//!
+//! Some parallel backends tag each chunk with its sequence number in a top-level
+//! `index` field but deliver them out of order. With `GATEWAY_REORDER_WINDOW` set, the
+//! streaming proxy holds early chunks until the gap before them fills. A gap is skipped
+//! when more than `window` chunks are waiting or nothing arrives for the timeout, so a
+//! lost chunk can only delay the stream, never stall it. Chunks without an index (such
+//! as `[DONE]`) release everything buffered and pass straight through.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use futures_util::{Stream, StreamExt, stream};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct IndexProbe {
+    index: Option<u64>,
+}
+
+fn chunk_index(chunk: &str) -> Option<u64> {
+    serde_json::from_str::<IndexProbe>(chunk)
+        .ok()
+        .and_then(|probe| probe.index)
+}
+
+/// Buffers early chunks and releases them in index order.
+pub struct Reorderer {
+    window: usize,
+    next: u64,
+    pending: BTreeMap<u64, String>,
+}
+
+impl Reorderer {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Whether chunks are held back waiting for a missing index.
+    pub fn is_waiting(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Accept one chunk, returning whatever is now ready to forward.
+    pub fn push(&mut self, chunk: String) -> Vec<String> {
+        let Some(index) = chunk_index(&chunk) else {
+            let mut ready = self.flush();
+            ready.push(chunk);
+            return ready;
+        };
+        if index < self.next {
+            // A straggler for an already-skipped gap; late is better than never
+            return vec![chunk];
+        }
+        self.pending.insert(index, chunk);
+        if self.pending.len() > self.window {
+            return self.skip_gap();
+        }
+        self.drain_ready()
+    }
+
+    /// Give up on the current gap and resume from the lowest buffered index.
+    pub fn skip_gap(&mut self) -> Vec<String> {
+        if let Some(&lowest) = self.pending.keys().next() {
+            self.next = lowest;
+        }
+        self.drain_ready()
+    }
+
+    /// Release every buffered chunk in index order.
+    pub fn flush(&mut self) -> Vec<String> {
+        if let Some(&last) = self.pending.keys().next_back() {
+            self.next = last + 1;
+        }
+        std::mem::take(&mut self.pending).into_values().collect()
+    }
+
+    fn drain_ready(&mut self) -> Vec<String> {
+        let mut ready = Vec::new();
+        while let Some(chunk) = self.pending.remove(&self.next) {
+            ready.push(chunk);
+            self.next += 1;
+        }
+        ready
+    }
+}
+
+/// Re-order `chunks`, waiting at most `timeout` for a missing one before skipping it.
+pub fn reorder<S>(chunks: S, window: usize, timeout: Duration) -> impl Stream<Item = String>
+where
+    S: Stream<Item = String> + Unpin,
+{
+    let state = (chunks, Reorderer::new(window), VecDeque::new(), false);
+    stream::unfold(
+        state,
+        move |(mut chunks, mut reorderer, mut ready, mut done)| async move {
+            loop {
+                if let Some(chunk) = ready.pop_front() {
+                    return Some((chunk, (chunks, reorderer, ready, done)));
+                }
+                if done {
+                    return None;
+                }
+                let next = if reorderer.is_waiting() {
+                    tokio::time::timeout(timeout, chunks.next()).await
+                } else {
+                    Ok(chunks.next().await)
+                };
+                match next {
+                    Ok(Some(chunk)) => ready.extend(reorderer.push(chunk)),
+                    Ok(None) => {
+                        ready.extend(reorderer.flush());
+                        done = true;
+                    }
+                    Err(_) => ready.extend(reorderer.skip_gap()),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(index: u64) -> String {
+        format!(r#"{{"index":{index},"choices":[]}}"#)
+    }
+
+    fn indices(chunks: &[String]) -> Vec<Option<u64>> {
+        chunks.iter().map(|c| chunk_index(c)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_chunks_emitted_in_index_order() {
+        let input = [2, 0, 3, 1, 5, 4].map(chunk).to_vec();
+        let input = stream::iter(input).chain(stream::iter(["[DONE]".to_string()]));
+        let output: Vec<String> = reorder(input, 8, Duration::from_secs(5)).collect().await;
+
+        let expected: Vec<_> = (0..6).map(Some).chain([None]).collect();
+        assert_eq!(indices(&output), expected);
+        assert_eq!(output.last().unwrap(), "[DONE]");
+    }
+
+    #[test]
+    fn test_full_window_skips_missing_chunk() {
+        let mut reorderer = Reorderer::new(2);
+        assert!(reorderer.push(chunk(1)).is_empty());
+        assert!(reorderer.push(chunk(2)).is_empty());
+        // Index 0 never arrives; a third waiting chunk overflows the window
+        let ready = reorderer.push(chunk(3));
+        assert_eq!(indices(&ready), [Some(1), Some(2), Some(3)]);
+        assert_eq!(indices(&reorderer.push(chunk(0))), [Some(0)]);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_releases_chunks_behind_a_gap() {
+        let input = stream::iter([chunk(1), chunk(2)]).chain(stream::pending());
+        let output: Vec<String> = reorder(input, 8, Duration::from_millis(20))
+            .take(2)
+            .collect()
+            .await;
+        assert_eq!(indices(&output), [Some(1), Some(2)]);
+    }
+}
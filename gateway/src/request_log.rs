@@ -0,0 +1,77 @@
+//! Per-request outcome logging with sampling of successes.
+//!
+//! `GATEWAY_LOG_SAMPLE_RATE` (0.0–1.0) controls the fraction of successful requests
+//! logged at `info`; failures are always logged at `warn`.
+
+use std::time::Duration;
+
+use tracing::{info, warn};
+use warp::http::StatusCode;
+
+/// Decide whether to log a successful request; `rate` is clamped to 0.0–1.0.
+pub fn should_sample(rate: f64) -> bool {
+    rate >= 1.0 || (rate > 0.0 && fastrand::f64() < rate)
+}
+
+/// Log a finished request. Returns whether a line was emitted.
+pub fn log_outcome(
+    route: &str,
+    label: &str,
+    status: StatusCode,
+    elapsed: Duration,
+    sample_rate: f64,
+) -> bool {
+    let duration_ms = elapsed.as_millis();
+    if status.is_client_error() || status.is_server_error() {
+        warn!("{route} request failed: {label}, status={status}, duration_ms={duration_ms}");
+        return true;
+    }
+    if !should_sample(sample_rate) {
+        return false;
+    }
+    info!("{route} request: {label}, status={status}, duration_ms={duration_ms}");
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::capture_logs;
+
+    #[test]
+    fn test_zero_rate_drops_successes_but_keeps_errors() {
+        let elapsed = Duration::from_millis(5);
+        let logs = capture_logs(|| {
+            for _ in 0..50 {
+                assert!(!log_outcome(
+                    "chat",
+                    "model=m",
+                    StatusCode::OK,
+                    elapsed,
+                    0.0
+                ));
+            }
+            assert!(log_outcome(
+                "chat",
+                "model=m",
+                StatusCode::BAD_GATEWAY,
+                elapsed,
+                0.0
+            ));
+        });
+
+        assert_eq!(logs.lines().count(), 1, "{logs}");
+        assert!(logs.contains("WARN"));
+        assert!(logs.contains("chat request failed: model=m, status=502 Bad Gateway"));
+    }
+
+    #[test]
+    fn test_full_rate_logs_every_success() {
+        let logs = capture_logs(|| {
+            for _ in 0..10 {
+                log_outcome("tts", "voice=v", StatusCode::OK, Duration::ZERO, 1.0);
+            }
+        });
+        assert_eq!(logs.matches("tts request: voice=v").count(), 10);
+    }
+}
@@ -0,0 +1,217 @@
+//! Exact-match cache of chat completions (`GATEWAY_CHAT_CACHE_SIZE`,
+//! `GATEWAY_CHAT_CACHE_TTL_SECS`), so repeated identical prompts skip the backend.
+//!
+//! A non-streamed request is keyed by a SHA-256 of its body as forwarded (model,
+//! messages after prompt rules, and the other options), its `json_schema` and the
+//! `Accept` header. A successful reply is served again for the TTL, marked with
+//! `X-Cache: hit`; at most `GATEWAY_CHAT_CACHE_SIZE` replies are kept, the least
+//! recently used evicted first. Replies are shared by every client sending the same
+//! request, so only deterministic ones are cached: the request must set
+//! `temperature: 0` (unset means the node's sampling default) and ask for at most one
+//! choice. Hits are counted in metrics and the request log under the `cache` target.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use ring::digest;
+use warp::Reply;
+
+use crate::{ChatCompletionRequest, config};
+
+pub const CACHE_HEADER: &str = "x-cache";
+/// Backend label for cache hits in metrics and the request log.
+pub const CACHE_TARGET: &str = "cache";
+
+pub static CACHE: LazyLock<ResponseCache> =
+    LazyLock::new(|| ResponseCache::new(config().chat_cache_ttl, config().chat_cache_size));
+
+pub type CacheKey = [u8; 32];
+
+/// The key for `body`, or `None` when the cache is off or the reply will be streamed
+/// or sampled.
+pub fn key(body: &ChatCompletionRequest, accept: Option<&str>) -> Option<CacheKey> {
+    if CACHE.capacity == 0 || body.stream == Some(true) || !deterministic(body) {
+        return None;
+    }
+    Some(hash(body, accept))
+}
+
+/// Greedy decoding of a single choice, so the same request gets the same reply.
+fn deterministic(body: &ChatCompletionRequest) -> bool {
+    body.temperature == Some(0.0) && body.n.unwrap_or(1) <= 1
+}
+
+fn hash(body: &ChatCompletionRequest, accept: Option<&str>) -> CacheKey {
+    let mut context = digest::Context::new(&digest::SHA256);
+    let schema = body.json_schema.as_ref().map(|schema| schema.to_string());
+    let parts = [
+        serde_json::to_string(body).unwrap_or_default(),
+        schema.unwrap_or_default(),
+        accept.unwrap_or_default().to_string(),
+    ];
+    for part in parts {
+        // Length-prefixed so fields can't run into each other
+        context.update(&(part.len() as u64).to_be_bytes());
+        context.update(part.as_bytes());
+    }
+    let mut key = [0; 32];
+    key.copy_from_slice(context.finish().as_ref());
+    key
+}
+
+/// The cached reply for `key`, ready to send.
+pub fn lookup(key: &CacheKey) -> Option<warp::reply::Response> {
+    let (content_type, body) = CACHE.get(key, Instant::now())?;
+    let reply = warp::reply::with_header(body, "Content-Type", content_type);
+    Some(warp::reply::with_header(reply, CACHE_HEADER, "hit").into_response())
+}
+
+pub fn store(key: CacheKey, content_type: &str, body: &[u8]) {
+    CACHE.insert(key, content_type, body, Instant::now());
+}
+
+struct Entry {
+    stored: Instant,
+    /// Position in [`CacheState::recency`].
+    used: u64,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, Entry>,
+    /// Keys by last use, oldest first, for evicting the least recently used.
+    recency: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: &CacheKey) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = self.clock;
+            self.recency.insert(self.clock, *key);
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+pub struct ResponseCache {
+    ttl: Duration,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ResponseCache {
+    /// A `capacity` of 0 disables caching.
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+
+    fn get(&self, key: &CacheKey, now: Instant) -> Option<(String, Vec<u8>)> {
+        let mut state = self.state.lock().unwrap();
+        let stored = state.entries.get(key)?.stored;
+        if now.saturating_duration_since(stored) >= self.ttl {
+            state.remove(key);
+            return None;
+        }
+        state.touch(key);
+        let entry = &state.entries[key];
+        Some((entry.content_type.clone(), entry.body.clone()))
+    }
+
+    fn insert(&self, key: CacheKey, content_type: &str, body: &[u8], now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.remove(&key);
+        let entry = Entry {
+            stored: now,
+            used: 0,
+            content_type: content_type.to_string(),
+            body: body.to_vec(),
+        };
+        state.entries.insert(key, entry);
+        state.touch(&key);
+        while state.entries.len() > self.capacity {
+            let Some((_, oldest)) = state.recency.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(content: &str) -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "messages": [{ "role": "user", "content": content }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_key_covers_request_and_accept() {
+        let hi = hash(&request("hi"), None);
+        assert_eq!(hi, hash(&request("hi"), None));
+        assert_ne!(hi, hash(&request("hello"), None));
+        assert_ne!(hi, hash(&request("hi"), Some("application/msgpack")));
+
+        let mut json_mode = request("hi");
+        json_mode.response_format = Some(serde_json::json!({ "type": "json_object" }));
+        assert_ne!(hi, hash(&json_mode, None));
+    }
+
+    #[test]
+    fn test_only_greedy_single_choice_requests_cached() {
+        let mut body = request("hi");
+        assert!(!deterministic(&body));
+        body.temperature = Some(0.7);
+        assert!(!deterministic(&body));
+        body.temperature = Some(0.0);
+        assert!(deterministic(&body));
+        body.n = Some(2);
+        assert!(!deterministic(&body));
+        body.n = Some(1);
+        assert!(deterministic(&body));
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted_and_entries_expire() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 2);
+        let now = Instant::now();
+        let (a, b, c) = ([1; 32], [2; 32], [3; 32]);
+        cache.insert(a, "application/json", b"a", now);
+        cache.insert(b, "application/json", b"b", now);
+        // Reading `a` makes `b` the least recently used
+        assert!(cache.get(&a, now).is_some());
+        cache.insert(c, "application/json", b"c", now);
+        assert!(cache.get(&b, now).is_none());
+        let (content_type, body) = cache.get(&a, now).unwrap();
+        assert_eq!(
+            (content_type.as_str(), body.as_slice()),
+            ("application/json", &b"a"[..])
+        );
+
+        let later = now + Duration::from_secs(60);
+        assert!(cache.get(&c, later).is_none());
+        assert_eq!(cache.state.lock().unwrap().entries.len(), 1);
+    }
+}
@@ -0,0 +1,343 @@
+//! Retries of failed chat requests, limited per request and by a shared budget.
+//!
+//! A non-streamed request whose upstream call fails to connect or returns `502` or
+//! `503` is re-sent up to `GATEWAY_MAX_RETRIES` times, each retry after a jittered
+//! exponential [`Backoff`]. Those failures mean the backend never started generating,
+//! so a retry can't duplicate work; streamed requests are never retried. On top of that, a token bucket limits
+//! retries to a fraction of traffic (`GATEWAY_RETRY_BUDGET`): every request earns that
+//! fraction of a token and every retry spends a whole one. When the backend is broadly
+//! unhealthy the bucket drains and retries stop instead of multiplying the load.
+//!
+//! `GATEWAY_MAX_TOTAL_ATTEMPTS` caps the backend attempts one request makes, first try
+//! included, counted by an [`Attempts`]. A request that uses them all and still fails,
+//! or whose retries (or retry budget) run out on a `502` or `503`, gets the gateway's
+//! own `502` (see [`gave_up`]) rather than the backend's reply.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tracing::warn;
+
+use crate::proxy::{self, ProxyReply};
+use crate::{config, metrics};
+
+/// The bucket holds at most the retries earned over this many requests.
+const BUDGET_WINDOW: f64 = 100.0;
+
+pub static BUDGET: LazyLock<RetryBudget> =
+    LazyLock::new(|| RetryBudget::new(config().retry_budget));
+
+pub struct RetryBudget {
+    ratio: f64,
+    cap: f64,
+    balance: Mutex<f64>,
+}
+
+impl RetryBudget {
+    /// Starts full, so a quiet gateway can still retry its first failures.
+    pub fn new(ratio: f64) -> Self {
+        let cap = (ratio * BUDGET_WINDOW).max(1.0);
+        Self {
+            ratio,
+            cap,
+            balance: Mutex::new(cap),
+        }
+    }
+
+    fn deposit(&self) {
+        let mut balance = self.balance.lock().unwrap();
+        *balance = (*balance + self.ratio).min(self.cap);
+    }
+
+    fn try_withdraw(&self) -> bool {
+        let mut balance = self.balance.lock().unwrap();
+        if *balance < 1.0 {
+            metrics::RETRY_BUDGET_EXHAUSTED.inc();
+            return false;
+        }
+        *balance -= 1.0;
+        true
+    }
+}
+
+/// Backend attempts left for one request.
+pub struct Attempts {
+    max: u32,
+    left: AtomicU32,
+}
+
+impl Attempts {
+    /// `None` leaves attempts unlimited.
+    pub fn new(max: Option<u32>) -> Self {
+        let max = max.unwrap_or(u32::MAX);
+        Self {
+            max,
+            left: AtomicU32::new(max),
+        }
+    }
+
+    /// True once the request has used all its attempts.
+    pub fn exhausted(&self) -> bool {
+        self.left.load(Ordering::SeqCst) == 0
+    }
+
+    pub fn used(&self) -> u32 {
+        self.max - self.left.load(Ordering::SeqCst)
+    }
+
+    fn try_take(&self) -> bool {
+        self.left
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
+
+/// Wait before each retry: `base` doubled per retry up to `max`, of which a random
+/// half is taken off so clients failing together don't retry in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Before the first retry (`GATEWAY_RETRY_BACKOFF_MS`).
+    pub base: Duration,
+    /// Longest wait (`GATEWAY_RETRY_BACKOFF_MAX_MS`).
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// The wait before retry number `retry` (1-based).
+    pub fn delay(&self, retry: u32) -> Duration {
+        let doubled = self
+            .base
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let full = doubled.min(self.max);
+        full / 2 + full.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(2),
+        }
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+fn is_retryable(resp: &reqwest::Result<Response>) -> bool {
+    match resp {
+        Ok(r) => is_retryable_status(r.status()),
+        Err(e) => e.is_connect(),
+    }
+}
+
+/// Whether the request has given up on the backend: `resp` is a failure and no
+/// attempts are left, or it is a retryable `502`/`503` still standing after the
+/// request was allowed `max_retries` retries. The client then gets [`gave_up_reply`]
+/// instead of `resp`.
+pub fn gave_up(resp: &Response, attempts: &Attempts, max_retries: u32) -> bool {
+    let status = resp.status();
+    let retries_spent = max_retries > 0 && is_retryable_status(status);
+    status.is_server_error() && (attempts.exhausted() || retries_spent)
+}
+
+/// `502` naming the backend's last `status` and the attempts spent on it.
+pub fn gave_up_reply(status: StatusCode, attempts: &Attempts) -> ProxyReply {
+    let used = attempts.used();
+    warn!("Chat request failed with {status} after {used} backend attempts");
+    let error = format!("llm-node returned {status} after {used} attempts");
+    proxy::error_reply(error, StatusCode::BAD_GATEWAY)
+}
+
+/// Send `request`, retrying failures after `backoff` while both the per-request count
+/// and the budget allow, and each attempt is charged to `attempts`. The first attempt
+/// goes out regardless; the last attempt's outcome is returned either way.
+pub async fn send(
+    request: RequestBuilder,
+    max_retries: u32,
+    backoff: Backoff,
+    budget: &RetryBudget,
+    attempts: &Attempts,
+) -> reqwest::Result<Response> {
+    budget.deposit();
+    attempts.try_take();
+    let mut retries = 0;
+    loop {
+        let resp = match request.try_clone() {
+            Some(attempt) => attempt.send().await,
+            // Streaming bodies cannot be replayed
+            None => return request.send().await,
+        };
+        if !is_retryable(&resp)
+            || retries >= max_retries
+            || !attempts.try_take()
+            || !budget.try_withdraw()
+        {
+            return resp;
+        }
+        retries += 1;
+        metrics::UPSTREAM_RETRIES.inc();
+        let delay = backoff.delay(retries);
+        warn!("Retrying chat request upstream in {delay:?} (retry {retries} of {max_retries})");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use warp::{Filter, Reply};
+
+    use super::*;
+
+    const NO_BACKOFF: Backoff = Backoff {
+        base: Duration::ZERO,
+        max: Duration::ZERO,
+    };
+
+    /// A backend that always answers `status`, counting the requests it receives.
+    async fn failing_backend(status: StatusCode) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let route = warp::any().map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            status
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        (url, hits)
+    }
+
+    /// The status the client sees for `resp`, as the chat handler decides it.
+    fn client_status(resp: &Response, attempts: &Attempts, max_retries: u32) -> StatusCode {
+        if gave_up(resp, attempts, max_retries) {
+            gave_up_reply(resp.status(), attempts)
+                .into_response()
+                .status()
+        } else {
+            resp.status()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_budget_skips_retries_within_count() {
+        let (url, hits) = failing_backend(StatusCode::SERVICE_UNAVAILABLE).await;
+        let client = reqwest::Client::new();
+        // 1% of traffic: the bucket starts with a single retry
+        let budget = RetryBudget::new(0.01);
+        let exhausted_before = metrics::RETRY_BUDGET_EXHAUSTED.get();
+
+        let unlimited = Attempts::new(None);
+        let resp = send(client.post(&url), 3, NO_BACKOFF, &budget, &unlimited)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 503);
+        assert_eq!(
+            hits.load(Ordering::SeqCst),
+            2,
+            "one retry, then out of budget"
+        );
+        assert_eq!(client_status(&resp, &unlimited, 3), StatusCode::BAD_GATEWAY);
+
+        let unlimited = Attempts::new(None);
+        let resp = send(client.post(&url), 3, NO_BACKOFF, &budget, &unlimited)
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 3, "no retry left");
+        assert!(metrics::RETRY_BUDGET_EXHAUSTED.get() >= exhausted_before + 2);
+        assert_eq!(client_status(&resp, &unlimited, 3), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_total_attempts_cap_retries_then_client_gets_502() {
+        let (url, hits) = failing_backend(StatusCode::SERVICE_UNAVAILABLE).await;
+        let client = reqwest::Client::new();
+        let budget = RetryBudget::new(1.0);
+        let attempts = Attempts::new(Some(3));
+
+        // Five retries allowed, but the request may only make three attempts in all
+        let resp = send(client.post(&url), 5, NO_BACKOFF, &budget, &attempts)
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(attempts.used(), 3);
+        assert_eq!(client_status(&resp, &attempts, 5), StatusCode::BAD_GATEWAY);
+
+        // Running out of retries before attempts also gives up
+        let attempts = Attempts::new(Some(4));
+        let resp = send(client.post(&url), 2, NO_BACKOFF, &budget, &attempts)
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 6);
+        assert_eq!(client_status(&resp, &attempts, 2), StatusCode::BAD_GATEWAY);
+
+        // A request that may not retry (a stream) relays the backend's own answer
+        let attempts = Attempts::new(Some(4));
+        let resp = send(client.post(&url), 0, NO_BACKOFF, &budget, &attempts)
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 7);
+        assert_eq!(
+            client_status(&resp, &attempts, 0),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn test_budget_refills_with_traffic() {
+        let budget = RetryBudget::new(0.5);
+        *budget.balance.lock().unwrap() = 0.0;
+        assert!(!budget.try_withdraw());
+        budget.deposit();
+        budget.deposit();
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+    }
+
+    #[test]
+    fn test_backoff_doubles_with_jitter_up_to_max() {
+        let backoff = Backoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(300),
+        };
+        for (retry, full) in [(1, 100), (2, 200), (3, 300), (10, 300)] {
+            let full = Duration::from_millis(full);
+            for _ in 0..20 {
+                let delay = backoff.delay(retry);
+                assert!(
+                    delay >= full / 2 && delay <= full,
+                    "retry {retry}: {delay:?}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_gateway_errors_retried() {
+        let (url, hits) = failing_backend(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let client = reqwest::Client::new();
+        let budget = RetryBudget::new(1.0);
+        let attempts = Attempts::new(None);
+        let resp = send(client.post(&url), 3, NO_BACKOFF, &budget, &attempts)
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        // Not retried, so the backend's own error reaches the client
+        assert_eq!(
+            client_status(&resp, &attempts, 3),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
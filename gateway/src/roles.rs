@@ -0,0 +1,137 @@
+//! Per-backend renaming of message roles (`GATEWAY_ROLE_MAPS`).
+//!
+//! Some backends expect non-standard role names such as `human`/`bot`. Outgoing
+//! messages are renamed for the backend, and the roles in its reply (`message.role`,
+//! or `delta.role` in streamed chunks) are renamed back before the client sees them.
+
+use serde_json::Value;
+
+use crate::{ChatMessage, models};
+
+/// `client role -> backend role` pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleMap(Vec<(String, String)>);
+
+impl RoleMap {
+    /// Parse `user:human,assistant:bot`.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let pairs = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (client, backend) = pair
+                    .split_once(':')
+                    .filter(|(c, b)| !c.trim().is_empty() && !b.trim().is_empty())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("invalid role mapping {pair:?}; expected from:to")
+                    })?;
+                Ok((client.trim().to_string(), backend.trim().to_string()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if pairs.is_empty() {
+            anyhow::bail!("empty role map");
+        }
+        Ok(Self(pairs))
+    }
+
+    pub fn forward(&self, messages: &mut [ChatMessage]) {
+        for message in messages {
+            if let Some((_, backend)) = self.0.iter().find(|(client, _)| *client == message.role) {
+                message.role = backend.clone();
+            }
+        }
+    }
+
+    fn reverse(&self, role: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, backend)| backend == role)
+            .map(|(client, _)| client.as_str())
+    }
+
+    /// Rename the roles in a reply or stream chunk; `false` if nothing changed.
+    fn restore_value(&self, body: &mut Value) -> bool {
+        let Some(choices) = body["choices"].as_array_mut() else {
+            return false;
+        };
+        let mut changed = false;
+        for choice in choices {
+            for key in ["message", "delta"] {
+                let role = &mut choice[key]["role"];
+                if let Some(client) = role.as_str().and_then(|r| self.reverse(r)) {
+                    *role = Value::String(client.to_string());
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Rename the roles in a JSON reply or stream payload, leaving anything else
+    /// (other formats, `[DONE]`) untouched.
+    pub fn restore(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut body: Value = serde_json::from_slice(payload).ok()?;
+        if !self.restore_value(&mut body) {
+            return None;
+        }
+        serde_json::to_vec(&body).ok()
+    }
+
+    /// [`restore`](Self::restore) for a stream payload.
+    pub fn restore_payload(&self, payload: String) -> String {
+        self.restore(payload.as_bytes())
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .unwrap_or(payload)
+    }
+}
+
+/// The role map of the first rule whose pattern matches the backend URL.
+pub fn for_backend<'a>(maps: &'a [(String, RoleMap)], target: &str) -> Option<&'a RoleMap> {
+    maps.iter()
+        .find(|(pattern, _)| models::matches(pattern, target))
+        .map(|(_, map)| map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: "hi".into(),
+        }
+    }
+
+    #[test]
+    fn test_roles_mapped_forward_and_back() {
+        let map = RoleMap::parse("user:human, assistant:bot").unwrap();
+        let mut messages = vec![message("system"), message("user"), message("assistant")];
+        map.forward(&mut messages);
+        let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "human", "bot"]);
+
+        let reply = br#"{"choices":[{"index":0,"message":{"role":"bot","content":"hello"}}]}"#;
+        let restored: Value = serde_json::from_slice(&map.restore(reply).unwrap()).unwrap();
+        assert_eq!(restored["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(restored["choices"][0]["message"]["content"], "hello");
+
+        let chunk = br#"{"choices":[{"index":0,"delta":{"role":"bot"}}]}"#;
+        let restored: Value = serde_json::from_slice(&map.restore(chunk).unwrap()).unwrap();
+        assert_eq!(restored["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(map.restore(b"[DONE]"), None);
+    }
+
+    #[test]
+    fn test_map_selected_by_backend_pattern() {
+        let maps = vec![(
+            "http://legacy*".to_string(),
+            RoleMap::parse("user:human").unwrap(),
+        )];
+        assert!(for_backend(&maps, "http://legacy:9000/v1/chat/completions").is_some());
+        assert!(for_backend(&maps, "http://localhost:9000/v1/chat/completions").is_none());
+        assert!(RoleMap::parse("user").is_err());
+        assert!(RoleMap::parse("").is_err());
+    }
+}
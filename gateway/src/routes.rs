@@ -0,0 +1,342 @@
+//! Model-based routing of chat requests, from `GATEWAY_ROUTES` or the `[routes]`
+//! table of the TOML file named by `GATEWAY_CONFIG_FILE`.
+//!
+//! `GATEWAY_ROUTES="qwen3-*=http://localhost:9000,llama-3-*=http://localhost:9001"` maps
+//! model patterns (see [`crate::models`]) to llm-node base URLs. The most specific
+//! matching pattern wins: an exact name beats any prefix, and a longer prefix beats a
+//! shorter one, so `*=<url>` acts as the default. A route may list several replicas,
+//! separated by `|` (`qwen3-*=http://gpu0:9000|http://gpu1:9000`); requests are spread
+//! across them by `GATEWAY_BACKEND_SELECTION` (see [`crate::backends`]). The same
+//! table in a config file, where a list of URLs does the same:
+//!
+//! ```toml
+//! [routes]
+//! "qwen3-*" = ["http://gpu0:9000", "http://gpu1:9000"]
+//! "llama-3-*" = "http://localhost:9001"
+//! ```
+//!
+//! `GATEWAY_ROUTES` wins when both are set. The table is parsed once at startup; a
+//! malformed entry stops the gateway from starting.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, bail};
+
+use crate::backends::{BackendPool, BreakerPolicy};
+use crate::config::{BackendSelection, env_opt};
+use crate::{language, models};
+
+const CHAT_PATH: &str = "/v1/chat/completions";
+/// Latency smoothing for tables parsed without a configuration, as in tests.
+const DEFAULT_ALPHA: f64 = 0.2;
+
+#[derive(Debug)]
+pub struct RoutingTable {
+    /// `(pattern, replicas)` in configuration order.
+    routes: Vec<(String, BackendPool)>,
+}
+
+impl RoutingTable {
+    /// The replicas serving `model`, or `None` when no pattern matches.
+    pub fn target(&self, model: &str) -> Option<&BackendPool> {
+        self.routes
+            .iter()
+            .filter(|(pattern, _)| models::matches(pattern, model))
+            .max_by_key(|(pattern, _)| specificity(pattern))
+            .map(|(_, pool)| pool)
+    }
+
+    /// Each model pattern with its replicas.
+    pub fn routes(&self) -> impl Iterator<Item = (&str, &BackendPool)> {
+        self.routes
+            .iter()
+            .map(|(pattern, pool)| (pattern.as_str(), pool))
+    }
+
+    /// Open each route's backend circuits by `breaker`.
+    pub fn set_breaker(&mut self, breaker: Option<BreakerPolicy>) {
+        for (_, pool) in &mut self.routes {
+            pool.set_breaker(breaker);
+        }
+    }
+
+    /// Spread requests within each route by `selection`, smoothing latency by `alpha`.
+    fn configure(mut self, selection: BackendSelection, alpha: f64) -> Self {
+        for (_, pool) in &mut self.routes {
+            pool.configure(selection, alpha);
+        }
+        self
+    }
+}
+
+/// Exact names rank above every prefix; longer prefixes rank above shorter ones.
+fn specificity(pattern: &str) -> usize {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => prefix.len(),
+        None => usize::MAX,
+    }
+}
+
+/// A validated chat completions URL; base URLs get the chat path appended.
+fn chat_url(pattern: &str, url: &str) -> anyhow::Result<String> {
+    let url = url.trim().trim_end_matches('/');
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        bail!("route {pattern:?} needs an http:// or https:// URL, got {url:?}");
+    }
+    Ok(if url.ends_with(CHAT_PATH) {
+        url.to_string()
+    } else {
+        format!("{url}{CHAT_PATH}")
+    })
+}
+
+impl RoutingTable {
+    /// Validate `(pattern, urls)` entries in configuration order.
+    fn from_pairs<'a>(
+        pairs: impl IntoIterator<Item = (&'a str, Vec<&'a str>)>,
+    ) -> anyhow::Result<Self> {
+        let mut routes: Vec<(String, BackendPool)> = Vec::new();
+        for (pattern, urls) in pairs {
+            let pattern = pattern.trim();
+            if pattern.is_empty() {
+                bail!("route to {urls:?} has an empty model pattern");
+            }
+            if routes.iter().any(|(existing, _)| existing == pattern) {
+                bail!("model pattern {pattern:?} is routed twice");
+            }
+            let urls = urls
+                .into_iter()
+                .map(|url| chat_url(pattern, url))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if urls.is_empty() {
+                bail!("route {pattern:?} has no backend URLs");
+            }
+            let pool = BackendPool::new(urls, BackendSelection::RoundRobin, DEFAULT_ALPHA);
+            routes.push((pattern.to_string(), pool));
+        }
+        if routes.is_empty() {
+            bail!("no routes given");
+        }
+        Ok(Self { routes })
+    }
+
+    /// The `[routes]` table of a TOML document: `pattern = "url"` or
+    /// `pattern = ["url", ...]` per entry.
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let doc: toml_edit::Document = text.parse()?;
+        let routes = doc
+            .get("routes")
+            .and_then(toml_edit::Item::as_table_like)
+            .context("no [routes] table")?;
+        let pairs = routes
+            .iter()
+            .map(|(pattern, item)| {
+                let invalid = || format!("route {pattern:?} must be a URL or a list of URLs");
+                let urls = match item.as_array() {
+                    Some(urls) => urls
+                        .iter()
+                        .map(|url| url.as_str().with_context(invalid))
+                        .collect::<anyhow::Result<Vec<_>>>()?,
+                    None => vec![item.as_str().with_context(invalid)?],
+                };
+                Ok((pattern, urls))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Self::from_pairs(pairs)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_toml(&text).with_context(|| format!("invalid routes in {}", path.display()))
+    }
+}
+
+impl FromStr for RoutingTable {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let pairs = s
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(|entry| {
+                let (pattern, urls) = entry
+                    .split_once('=')
+                    .with_context(|| format!("route {entry:?} is not pattern=url"))?;
+                Ok((pattern, urls.split('|').collect()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Self::from_pairs(pairs)
+    }
+}
+
+/// The routing table from `GATEWAY_ROUTES`, or else from `GATEWAY_CONFIG_FILE`, if set,
+/// balancing each route with `selection`.
+pub fn from_env(selection: BackendSelection, alpha: f64) -> anyhow::Result<Option<RoutingTable>> {
+    let table = match env_opt("GATEWAY_ROUTES")? {
+        Some(table) => Some(table),
+        None => {
+            let path: Option<String> = env_opt("GATEWAY_CONFIG_FILE")?;
+            path.map(|path| RoutingTable::from_file(Path::new(&path)))
+                .transpose()?
+        }
+    };
+    Ok(table.map(|table| table.configure(selection, alpha)))
+}
+
+/// Backends by prompt language from `GATEWAY_LANGUAGE_ROUTES` (e.g.
+/// `fr=http://localhost:9002`), keyed by the ISO 639-1 codes [`language::detect`] reports.
+pub fn language_from_env(
+    selection: BackendSelection,
+    alpha: f64,
+) -> anyhow::Result<Option<RoutingTable>> {
+    let table: Option<RoutingTable> = env_opt("GATEWAY_LANGUAGE_ROUTES")?;
+    for (code, _) in table.iter().flat_map(|t| &t.routes) {
+        if !language::SUPPORTED.contains(&code.as_str()) {
+            bail!(
+                "GATEWAY_LANGUAGE_ROUTES: unsupported language {code:?}; expected one of {:?}",
+                language::SUPPORTED
+            );
+        }
+    }
+    Ok(table.map(|table| table.configure(selection, alpha)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url<'a>(table: &'a RoutingTable, model: &str) -> Option<&'a str> {
+        table.target(model).map(|pool| pool.select().url())
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let table: RoutingTable =
+            "qwen3-*=http://gpu0:9000, qwen3-coder-*=http://gpu1:9000/, qwen3-8b=http://gpu2:9000"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            url(&table, "qwen3-coder-30b"),
+            Some("http://gpu1:9000/v1/chat/completions")
+        );
+        assert_eq!(
+            url(&table, "qwen3-14b"),
+            Some("http://gpu0:9000/v1/chat/completions")
+        );
+        assert_eq!(
+            url(&table, "qwen3-8b"),
+            Some("http://gpu2:9000/v1/chat/completions")
+        );
+        assert_eq!(url(&table, "llama-3-8b"), None);
+    }
+
+    #[test]
+    fn test_default_route_catches_unmatched_models() {
+        let table: RoutingTable =
+            "*=http://localhost:9000/v1/chat/completions,llama-3-*=http://localhost:9001"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            url(&table, "mistral-7b"),
+            Some("http://localhost:9000/v1/chat/completions")
+        );
+        assert_eq!(
+            url(&table, "llama-3-8b"),
+            Some("http://localhost:9001/v1/chat/completions")
+        );
+    }
+
+    #[test]
+    fn test_route_spreads_across_replicas() {
+        let table: RoutingTable = "qwen3-*=http://gpu0:9000|http://gpu1:9000, *=http://cpu:9000"
+            .parse()
+            .unwrap();
+        let mut seen: Vec<&str> = (0..4).map(|_| url(&table, "qwen3-8b").unwrap()).collect();
+        seen.sort();
+        assert_eq!(
+            seen,
+            [
+                "http://gpu0:9000/v1/chat/completions",
+                "http://gpu0:9000/v1/chat/completions",
+                "http://gpu1:9000/v1/chat/completions",
+                "http://gpu1:9000/v1/chat/completions"
+            ]
+        );
+        assert_eq!(
+            url(&table, "mistral-7b"),
+            Some("http://cpu:9000/v1/chat/completions")
+        );
+        assert!(
+            "qwen3-*=http://gpu0:9000|gpu1:9000"
+                .parse::<RoutingTable>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_routes_from_config_file() {
+        let path = std::env::temp_dir().join(format!("gateway-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+# Model routing for the lab cluster
+[routes]
+"qwen3-*" = ["http://gpu0:9000", "http://gpu1:9000/"]
+"llama-3-*" = "http://gpu2:9000/"
+"*" = "http://localhost:9000/v1/chat/completions"
+"#,
+        )
+        .unwrap();
+        let table = RoutingTable::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut qwen3: Vec<&str> = (0..2).map(|_| url(&table, "qwen3-8b").unwrap()).collect();
+        qwen3.sort();
+        assert_eq!(
+            qwen3,
+            [
+                "http://gpu0:9000/v1/chat/completions",
+                "http://gpu1:9000/v1/chat/completions"
+            ]
+        );
+        assert_eq!(
+            url(&table, "llama-3-70b"),
+            Some("http://gpu2:9000/v1/chat/completions")
+        );
+        assert_eq!(
+            url(&table, "mistral-7b"),
+            Some("http://localhost:9000/v1/chat/completions")
+        );
+
+        for toml in [
+            "[other]\na = \"http://x\"",
+            "[routes]\n\"qwen3-*\" = 9000",
+            "[routes]\n\"qwen3-*\" = []",
+            "[routes]\n\"qwen3-*\" = [\"http://x\", 1]",
+            "[routes]\n\"qwen3-*\" = \"gpu0:9000\"",
+            "[routes]",
+            "[routes\n",
+        ] {
+            assert!(
+                RoutingTable::from_toml(toml).is_err(),
+                "{toml:?} was accepted"
+            );
+        }
+        assert!(RoutingTable::from_file(Path::new("/nonexistent/gateway.toml")).is_err());
+    }
+
+    #[test]
+    fn test_malformed_routes_rejected() {
+        for raw in [
+            "qwen3-*",
+            "=http://localhost:9000",
+            "qwen3-*=localhost:9000",
+            "a=http://x,a=http://y",
+            " , ",
+        ] {
+            assert!(raw.parse::<RoutingTable>().is_err(), "{raw:?} was accepted");
+        }
+    }
+}
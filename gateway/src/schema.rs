@@ -0,0 +1,174 @@
+//! Enforcement of a client-supplied `json_schema` on structured chat replies.
+//!
+//! A chat request may carry a `json_schema` (alongside `response_format`) that the
+//! gateway keeps to itself. Each choice of a successful, non-streamed reply must then
+//! hold content that parses as JSON and conforms to the schema; otherwise the client
+//! gets a `502` listing what failed instead of malformed output.
+//!
+//! Schemas are compiled with the `jsonschema` crate when the request arrives, so the
+//! full draft (`$ref`/`$defs`, `anyOf`/`oneOf`/`allOf`/`not`, `pattern`, ...) is
+//! enforced. A schema that doesn't compile, including one whose `$ref` points outside
+//! the document, is answered `400` before any backend is called.
+
+use jsonschema::Validator;
+use serde_json::Value;
+use warp::http::StatusCode;
+
+use crate::proxy::{self, ProxyReply};
+
+/// Compile a request's `json_schema`, or say why it can't be used.
+pub fn compile(schema: &Value) -> Result<Validator, String> {
+    jsonschema::validator_for(schema).map_err(|e| format!("invalid json_schema: {e}"))
+}
+
+/// Every way `value` breaks the schema, each prefixed with its JSON pointer.
+pub fn validate(value: &Value, validator: &Validator) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .map(|error| {
+            let path = error.instance_path().to_string();
+            format!("{}: {error}", if path.is_empty() { "/" } else { &path })
+        })
+        .collect()
+}
+
+/// Why a completion's choices don't hold JSON matching `schema`, if they don't.
+pub fn check_completion(body: &[u8], validator: &Validator) -> Result<(), String> {
+    let completion: Value =
+        serde_json::from_slice(body).map_err(|e| format!("reply is not JSON: {e}"))?;
+    let choices = completion["choices"]
+        .as_array()
+        .ok_or("reply has no choices")?;
+    let mut problems = Vec::new();
+    for (i, choice) in choices.iter().enumerate() {
+        let content = choice["message"]["content"].as_str().unwrap_or_default();
+        match serde_json::from_str::<Value>(content) {
+            Ok(content) => problems.extend(
+                validate(&content, validator)
+                    .into_iter()
+                    .map(|e| format!("choice {i} at {e}")),
+            ),
+            Err(e) => problems.push(format!("choice {i} content is not JSON: {e}")),
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+/// Relay a successful completion only if it conforms to the schema; `restore` is
+/// applied to the body as in [`proxy::relay_with`].
+pub async fn relay_conforming(
+    resp: reqwest::Response,
+    validator: &Validator,
+    restore: impl FnOnce(Vec<u8>) -> Vec<u8>,
+) -> ProxyReply {
+    let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::OK);
+    let bytes = resp.bytes().await.unwrap_or_default();
+    match check_completion(&bytes, validator) {
+        Ok(()) => proxy::json_reply(restore(bytes.to_vec()), status),
+        Err(e) => {
+            tracing::warn!("Chat reply failed json_schema validation: {e}");
+            let error = format!("response does not match json_schema: {e}");
+            proxy::error_reply(error, StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn completion(content: &str) -> Vec<u8> {
+        json!({"choices": [{"index": 0, "message": {"role": "assistant", "content": content}}]})
+            .to_string()
+            .into_bytes()
+    }
+
+    fn upstream(body: Vec<u8>) -> reqwest::Response {
+        warp::http::Response::builder()
+            .header("content-type", "application/json")
+            .body(body)
+            .unwrap()
+            .into()
+    }
+
+    fn schema() -> Validator {
+        compile(&json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "age": {"type": "integer", "minimum": 0},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}}
+            },
+            "required": ["name", "age"],
+            "additionalProperties": false
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_conforming_reply_relayed() {
+        let body = completion(r#"{"name": "Ada", "age": 36, "tags": ["a"]}"#);
+        let reply = relay_conforming(upstream(body.clone()), &schema(), |b| b).await;
+        let resp = warp::Reply::into_response(reply);
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_nonconforming_reply_is_502() {
+        let body = completion(r#"{"name": "", "tags": ["c"], "extra": 1}"#);
+        let reply = relay_conforming(upstream(body), &schema(), |b| b).await;
+        let resp = warp::Reply::into_response(reply);
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+
+        let errors = check_completion(
+            &completion(r#"{"name": "", "tags": ["c"], "extra": 1}"#),
+            &schema(),
+        )
+        .unwrap_err();
+        for expected in [r#"/: "age" is a required property"#, "/name: ", "/tags/0: "] {
+            assert!(errors.contains(expected), "{expected} not in {errors}");
+        }
+        assert!(errors.contains("'extra' was unexpected"), "{errors}");
+
+        let prose = check_completion(&completion("Sure! Here is the JSON"), &schema());
+        assert!(prose.unwrap_err().contains("choice 0 content is not JSON"));
+    }
+
+    #[test]
+    fn test_composite_keywords_enforced() {
+        let schema = compile(&json!({
+            "$defs": {"code": {"type": "string", "pattern": "^[A-Z]{3}$"}},
+            "type": "object",
+            "properties": {
+                "currency": {"$ref": "#/$defs/code"},
+                "amount": {"anyOf": [{"type": "integer"}, {"type": "string", "format": "date"}]},
+                "note": {"not": {"const": "TODO"}}
+            }
+        }))
+        .unwrap();
+        let ok = json!({"currency": "EUR", "amount": 5, "note": "paid"});
+        assert!(validate(&ok, &schema).is_empty());
+
+        let bad = json!({"currency": "euro", "amount": 1.5, "note": "TODO"});
+        let errors = validate(&bad, &schema);
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        for path in ["/currency: ", "/amount: ", "/note: "] {
+            assert!(errors.iter().any(|e| e.starts_with(path)), "{errors:?}");
+        }
+    }
+
+    #[test]
+    fn test_unusable_schema_rejected() {
+        assert!(compile(&json!({"type": "object"})).is_ok());
+        let err = compile(&json!({"type": 5})).err().unwrap();
+        assert!(err.starts_with("invalid json_schema"), "{err}");
+        let remote = json!({"$ref": "https://example.com/schema.json"});
+        assert!(compile(&remote).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! The gateway's HTTP server: warp's routes served over hyper directly, so a reply
+//! can stream a body warp has no constructor for. warp streams only server-sent events
+//! and files; a reply built by [`streamed`] carries its body as a [`Streamed`]
+//! extension, which the server sends in place of the (empty) warp body. Used for
+//! NDJSON chat streams.
+//!
+//! Every request carries the connection's [`PeerAddr`] as an extension.
+//!
+//! Shutdown works as with `warp::serve(..).graceful(..)`: once the signal resolves no
+//! new connections are accepted and open ones are closed as their requests finish.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::{Stream, StreamExt};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use tokio::net::TcpListener;
+use tower_service::Service;
+use tracing::debug;
+use warp::Filter;
+use warp::http::{HeaderValue, Request, Response, header};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The remote address of the connection a request came in on.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr(pub SocketAddr);
+
+/// A reply body streamed by the server rather than by warp.
+#[derive(Clone)]
+pub struct Streamed(Arc<Mutex<Option<BoxStream<'static, Bytes>>>>);
+
+/// A `200` reply of `content_type` whose body is `chunks`, each sent as it comes.
+pub fn streamed(
+    content_type: &'static str,
+    chunks: impl Stream<Item = Bytes> + Send + 'static,
+) -> warp::reply::Response {
+    let mut reply = warp::reply::Response::default();
+    let headers = reply.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    let body = Streamed(Arc::new(Mutex::new(Some(chunks.boxed()))));
+    reply.extensions_mut().insert(body);
+    reply
+}
+
+/// A warp reply as hyper sends it: its own body, or the [`Streamed`] one.
+fn into_hyper(reply: warp::reply::Response) -> Response<UnsyncBoxBody<Bytes, BoxError>> {
+    let streamed = reply
+        .extensions()
+        .get::<Streamed>()
+        .and_then(|streamed| streamed.0.lock().unwrap().take());
+    reply.map(|body| match streamed {
+        Some(chunks) => {
+            let frames = chunks.map(|chunk| Ok::<_, BoxError>(Frame::data(chunk)));
+            StreamBody::new(frames).boxed_unsync()
+        }
+        None => body.map_err(BoxError::from).boxed_unsync(),
+    })
+}
+
+/// Serve `routes` on `listener` until `shutdown` resolves, then wait for open
+/// connections to finish.
+pub async fn run<F>(listener: TcpListener, routes: F, shutdown: impl Future<Output = ()>)
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: warp::Reply,
+{
+    let graceful = GracefulShutdown::new();
+    let mut shutdown = std::pin::pin!(shutdown);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    debug!("accept error: {e}");
+                    continue;
+                }
+            },
+            () = &mut shutdown => break,
+        };
+        let routes = routes.clone();
+        let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+            req.extensions_mut().insert(PeerAddr(peer));
+            let reply = warp::service(routes.clone()).call(req);
+            async move { reply.await.map(into_hyper) }
+        });
+        let connection = auto::Builder::new(TokioExecutor::new())
+            .serve_connection_with_upgrades(TokioIo::new(stream), service)
+            .into_owned();
+        let connection = graceful.watch(connection);
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                debug!("connection from {peer} failed: {e}");
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_streamed_body_and_warp_replies_served() {
+        let chunks = warp::path("stream").map(|| {
+            let chunks = futures_util::stream::iter(["a\n", "b\n"]).then(|chunk| async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Bytes::from(chunk)
+            });
+            streamed("application/x-ndjson", chunks)
+        });
+        let plain = warp::path("plain").map(|| "ok");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(run(listener, chunks.or(plain), async {
+            let _ = stopped.await;
+        }));
+
+        let resp = reqwest::get(format!("{url}/stream")).await.unwrap();
+        assert_eq!(resp.headers()["content-type"], "application/x-ndjson");
+        assert_eq!(resp.text().await.unwrap(), "a\nb\n");
+        let resp = reqwest::get(format!("{url}/plain")).await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "ok");
+
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), server)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! Mirroring of chat traffic to a shadow backend (`GATEWAY_SHADOW_BACKEND`).
+//!
+//! Each chat request is also sent to the shadow from a spawned task, so a slow or
+//! failing shadow can never delay or fail the client's request. The shadow's response
+//! is read and discarded; only its latency and whether its status matched the
+//! primary's are logged and counted in `/metrics`.
+
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::{ChatCompletionRequest, context, metrics};
+
+/// Marks mirrored requests so the shadow can tell them from real traffic.
+pub const SHADOW_HEADER: &str = "x-shadow";
+
+/// How a mirrored request compared with the primary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Matched {
+        latency: Duration,
+    },
+    StatusMismatch {
+        primary: u16,
+        shadow: u16,
+    },
+    /// The shadow was unreachable, or the primary never reported back.
+    Failed,
+}
+
+/// A mirrored request in flight; report the primary's status when it is known.
+pub struct Mirror {
+    primary_status: oneshot::Sender<u16>,
+    task: JoinHandle<Outcome>,
+}
+
+impl Mirror {
+    /// Hand over the primary's status and let the shadow finish on its own.
+    pub fn primary_done(self, status: u16) -> JoinHandle<Outcome> {
+        let _ = self.primary_status.send(status);
+        self.task
+    }
+}
+
+/// Send a copy of `body` to `target` in the background.
+pub fn mirror(
+    client: &Client,
+    target: &str,
+    body: &ChatCompletionRequest,
+    request_id: &str,
+) -> Mirror {
+    let request = client
+        .post(target)
+        .header(context::request_id_header(), request_id)
+        .header(SHADOW_HEADER, "1")
+        .json(body);
+    let (primary_status, primary) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        metrics::SHADOW_REQUESTS.inc();
+        let sent = Instant::now();
+        let shadow = match request.send().await {
+            Ok(resp) => {
+                let status = resp.status().as_u16();
+                // Drain the body so latency covers the whole response, then drop it
+                let _ = resp.bytes().await;
+                Some(status)
+            }
+            Err(e) => {
+                warn!("Shadow backend request failed: {e}");
+                None
+            }
+        };
+        let latency = sent.elapsed();
+        let outcome = match (primary.await, shadow) {
+            (Ok(primary), Some(shadow)) if primary == shadow => Outcome::Matched { latency },
+            (Ok(primary), Some(shadow)) => {
+                metrics::SHADOW_MISMATCHES.inc();
+                warn!("Shadow backend returned {shadow} where the primary returned {primary}");
+                Outcome::StatusMismatch { primary, shadow }
+            }
+            _ => {
+                metrics::SHADOW_FAILURES.inc();
+                Outcome::Failed
+            }
+        };
+        debug!("Shadow request finished in {latency:?}: {outcome:?}");
+        outcome
+    });
+    Mirror {
+        primary_status,
+        task,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use warp::Filter;
+
+    use super::*;
+    use crate::ChatMessage;
+
+    /// A backend answering every request with `reply`, counting requests that carry
+    /// the shadow header.
+    async fn backend(reply: &'static str) -> (String, Arc<AtomicUsize>) {
+        let shadowed = Arc::new(AtomicUsize::new(0));
+        let counter = shadowed.clone();
+        let route =
+            warp::header::optional::<String>(SHADOW_HEADER).map(move |h: Option<String>| {
+                if h.is_some() {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+                reply
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        (url, shadowed)
+    }
+
+    fn request() -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "m".into(),
+            messages: vec![ChatMessage {
+                role: "user".into(),
+                content: "hi".into(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// The primary call as `handle_chat` makes it, with a shadow mirrored alongside.
+    async fn proxied(primary: &str, shadow: &str) -> (String, JoinHandle<Outcome>) {
+        let client = Client::new();
+        let mirror = mirror(&client, shadow, &request(), "req-1");
+        let resp = client.post(primary).json(&request()).send().await.unwrap();
+        let task = mirror.primary_done(resp.status().as_u16());
+        (resp.text().await.unwrap(), task)
+    }
+
+    #[tokio::test]
+    async fn test_shadow_called_but_response_ignored() {
+        let (primary, _) = backend("from primary").await;
+        let (shadow, shadowed) = backend("from shadow").await;
+
+        let (reply, task) = proxied(&primary, &shadow).await;
+        assert_eq!(reply, "from primary");
+        assert!(matches!(task.await.unwrap(), Outcome::Matched { .. }));
+        assert_eq!(shadowed.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_failure_does_not_fail_request() {
+        let (primary, _) = backend("from primary").await;
+        // Nothing listens here once the listener is dropped
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let shadow = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let failures = metrics::SHADOW_FAILURES.get();
+
+        let (reply, task) = proxied(&primary, &shadow).await;
+        assert_eq!(reply, "from primary");
+        assert_eq!(task.await.unwrap(), Outcome::Failed);
+        assert!(metrics::SHADOW_FAILURES.get() > failures);
+    }
+}
@@ -0,0 +1,221 @@
+//! Graceful shutdown: a drain flag flipped by SIGTERM/SIGINT, an in-flight request
+//! counter, and a bounded wait for in-flight work before the process exits.
+//!
+//! `GET /ready` answers `200` until draining starts and then the configured draining
+//! status. With `GATEWAY_DRAIN_GRACE_SECS` the listener keeps accepting for that long
+//! after the signal, so a load balancer polling `/ready` sees the change and stops
+//! routing here before connections are refused.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+use warp::Filter;
+use warp::http::StatusCode;
+
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Requests currently being handled by the gateway.
+pub static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a request as in flight until dropped.
+pub struct RequestGuard<'a>(&'a AtomicUsize);
+
+impl<'a> RequestGuard<'a> {
+    pub fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Track a request against the global in-flight counter.
+pub fn track() -> RequestGuard<'static> {
+    RequestGuard::new(&IN_FLIGHT)
+}
+
+/// Set from the signal handler; polled by the listener task.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Spawn a listener that flips the returned flag to `true` on SIGTERM or SIGINT.
+pub fn listen_for_signals() -> watch::Receiver<bool> {
+    install_handlers();
+    let (tx, rx) = watch::channel(false);
+    tokio::spawn(async move {
+        let mut poll = tokio::time::interval(SIGNAL_POLL_INTERVAL);
+        while !SIGNALLED.load(Ordering::SeqCst) {
+            poll.tick().await;
+        }
+        info!("shutdown signal received; draining");
+        let _ = tx.send(true);
+    });
+    rx
+}
+
+// Handlers go through libc rather than tokio's `signal` feature to avoid pulling in
+// signal-hook; the handler only stores an atomic, which is async-signal-safe.
+#[cfg(unix)]
+fn install_handlers() {
+    extern "C" fn on_signal(_: libc::c_int) {
+        SIGNALLED.store(true, Ordering::SeqCst);
+    }
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: `on_signal` is async-signal-safe and lives for the whole program.
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_handlers() {}
+
+/// Resolve once draining has started (or the signal listener is gone).
+pub async fn wait_draining(mut draining: watch::Receiver<bool>) {
+    let _ = draining.wait_for(|d| *d).await;
+}
+
+/// Resolve once draining has started and `grace` has passed; the listener stops
+/// accepting new connections at that point.
+pub async fn stop_accepting(draining: watch::Receiver<bool>, grace: Duration) {
+    wait_draining(draining).await;
+    tokio::time::sleep(grace).await;
+}
+
+/// `GET /ready`: `200 ready`, or `status` with `body` once draining has started.
+pub fn ready_route(
+    draining: watch::Receiver<bool>,
+    status: StatusCode,
+    body: String,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("ready")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || {
+            if *draining.borrow() {
+                warp::reply::with_status(body.clone(), status)
+            } else {
+                warp::reply::with_status("ready".to_string(), StatusCode::OK)
+            }
+        })
+}
+
+/// Drive `server` to completion, but once draining starts give in-flight requests
+/// at most `timeout` to finish. Returns `false` if requests had to be abandoned.
+pub async fn serve_with_drain_timeout<F>(
+    server: F,
+    draining: watch::Receiver<bool>,
+    timeout: Duration,
+    in_flight: &AtomicUsize,
+) -> bool
+where
+    F: Future<Output = ()>,
+{
+    tokio::pin!(server);
+    tokio::select! {
+        _ = &mut server => return true,
+        _ = wait_draining(draining) => {}
+    }
+
+    match tokio::time::timeout(timeout, server).await {
+        Ok(()) => true,
+        Err(_) => {
+            warn!(
+                "drain timeout of {}s elapsed; abandoning {} in-flight requests",
+                timeout.as_secs_f32(),
+                in_flight.load(Ordering::SeqCst)
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hung_request_is_abandoned_after_drain_timeout() {
+        let counter = AtomicUsize::new(0);
+        let _hung = RequestGuard::new(&counter);
+        let (tx, rx) = watch::channel(false);
+        tx.send(true).unwrap();
+
+        let drained = serve_with_drain_timeout(
+            std::future::pending::<()>(),
+            rx,
+            Duration::from_millis(20),
+            &counter,
+        )
+        .await;
+
+        assert!(!drained);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_server_finishing_within_timeout_drains_cleanly() {
+        let (tx, rx) = watch::channel(false);
+        tx.send(true).unwrap();
+        let server = tokio::time::sleep(Duration::from_millis(5));
+
+        let drained =
+            serve_with_drain_timeout(server, rx, Duration::from_secs(5), &AtomicUsize::new(0))
+                .await;
+        assert!(drained);
+    }
+
+    #[tokio::test]
+    async fn test_ready_reports_draining_while_in_flight_requests_complete() {
+        let (tx, rx) = watch::channel(false);
+        let slow = warp::path("slow").then(|| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "done"
+        });
+        let routes = ready_route(
+            rx.clone(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "draining".into(),
+        )
+        .or(slow);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = warp::serve(routes)
+            .incoming(listener)
+            .graceful(stop_accepting(rx, Duration::from_secs(5)))
+            .run();
+        tokio::spawn(server);
+
+        let client = reqwest::Client::new();
+        let ready = client.get(format!("{base}/ready")).send().await.unwrap();
+        assert_eq!(ready.status(), 200);
+
+        let in_flight = tokio::spawn(client.get(format!("{base}/slow")).send());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tx.send(true).unwrap();
+
+        let ready = client.get(format!("{base}/ready")).send().await.unwrap();
+        assert_eq!(ready.status(), 503);
+        assert_eq!(ready.text().await.unwrap(), "draining");
+        let finished = in_flight.await.unwrap().unwrap();
+        assert_eq!(finished.text().await.unwrap(), "done");
+    }
+
+    #[test]
+    fn test_request_guard_decrements_on_drop() {
+        let counter = AtomicUsize::new(0);
+        {
+            let _a = RequestGuard::new(&counter);
+            let _b = RequestGuard::new(&counter);
+            assert_eq!(counter.load(Ordering::SeqCst), 2);
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,536 @@
+//! Streaming relay for `stream: true` chat completions.
+//!
+//! An upstream `text/event-stream` body is forwarded event by event instead of being
+//! buffered, so clients see deltas as llm-node produces them, `data: [DONE]` included.
+//! With a session id, every event is also published to that session's subscribers.
+//! With `X-Emit-Timing: 1` a final `timing` event reports the gaps between tokens.
+//! Deltas can optionally be re-ordered, capped and coalesced (see [`StreamOptions`]).
+//! Idle streams get a heartbeat, an SSE comment or an empty `data: {}` event, so
+//! proxies don't time them out.
+//!
+//! Clients sending `Accept: application/x-ndjson` get the same chunks as newline-
+//! delimited JSON instead: one chunk object per line, no `[DONE]` sentinel and no
+//! `timing` event, with a blank line (or `{}`) as the heartbeat. The upstream may
+//! answer in either framing.
+
+use std::convert::Infallible;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, future, stream};
+use tokio::sync::mpsc;
+use warp::Reply;
+use warp::sse::Event;
+
+use crate::config::HeartbeatStyle;
+use crate::fanout::{self, Publisher};
+use crate::key_limits::StreamCharge;
+use crate::metrics::ByteCounter;
+use crate::roles::RoleMap;
+use crate::timing::TokenTimer;
+use crate::{coalesce, config, reorder, serve, token_limit};
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// How streamed chunks are framed for the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Server-sent events, `data: [DONE]` last.
+    #[default]
+    Sse,
+    /// One JSON chunk per line.
+    Ndjson,
+}
+
+impl Framing {
+    /// NDJSON when the `Accept` header asks for it, else server-sent events.
+    pub fn for_accept(accept: Option<&str>) -> Self {
+        let ndjson = accept.is_some_and(|accept| {
+            accept.split(',').any(|part| {
+                let media = part.split(';').next().unwrap_or_default().trim();
+                media.eq_ignore_ascii_case(NDJSON_CONTENT_TYPE)
+            })
+        });
+        if ndjson { Self::Ndjson } else { Self::Sse }
+    }
+}
+
+/// How upstream deltas are rearranged before they reach the client.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamOptions {
+    /// Re-order out-of-sequence chunks within this many (`GATEWAY_REORDER_WINDOW`).
+    pub reorder_window: Option<usize>,
+    pub reorder_timeout: Duration,
+    /// Merge deltas arriving within this long of each other (`GATEWAY_COALESCE_MS`).
+    pub coalesce_window: Option<Duration>,
+    /// Stop forwarding after this many tokens (`GATEWAY_MAX_STREAM_TOKENS`).
+    pub max_tokens: Option<usize>,
+    /// Send a heartbeat after this long without an event (`GATEWAY_HEARTBEAT_SECS`).
+    pub heartbeat_interval: Option<Duration>,
+    pub heartbeat_style: HeartbeatStyle,
+    /// Rename the backend's roles in each delta back to the client's (`GATEWAY_ROLE_MAPS`).
+    pub role_map: Option<&'static RoleMap>,
+    pub framing: Framing,
+}
+
+impl StreamOptions {
+    pub fn from_config() -> Self {
+        let config = config();
+        Self {
+            reorder_window: config.reorder_window,
+            reorder_timeout: config.reorder_timeout,
+            coalesce_window: config.coalesce_window,
+            max_tokens: config.max_stream_tokens,
+            heartbeat_interval: config.heartbeat_interval,
+            heartbeat_style: config.heartbeat_style,
+            role_map: None,
+            framing: Framing::Sse,
+        }
+    }
+}
+
+fn content_type(resp: &reqwest::Response) -> Option<&str> {
+    resp.headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+}
+
+/// True for a streamed chat reply, as server-sent events or NDJSON.
+pub fn is_stream(resp: &reqwest::Response) -> bool {
+    content_type(resp).is_some_and(|ct| {
+        ct.starts_with("text/event-stream") || ct.starts_with(NDJSON_CONTENT_TYPE)
+    })
+}
+
+/// Incrementally splits raw SSE bytes into the `data:` payload of each event, or
+/// NDJSON bytes into lines.
+#[derive(Default)]
+struct EventParser {
+    buf: Vec<u8>,
+    ndjson: bool,
+}
+
+impl EventParser {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(bytes);
+        let mut payloads = Vec::new();
+        if self.ndjson {
+            while let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..end + 1).collect();
+                let line = String::from_utf8_lossy(&line);
+                if !line.trim().is_empty() {
+                    payloads.push(line.trim().to_string());
+                }
+            }
+            return payloads;
+        }
+        while let Some(end) = self.buf.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buf.drain(..end + 2).collect();
+            let event = String::from_utf8_lossy(&event);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                payloads.push(data.join("\n"));
+            }
+        }
+        payloads
+    }
+}
+
+/// The `data:` payloads of an upstream event stream (or the lines of an NDJSON one),
+/// as they arrive.
+fn data_payloads(upstream: reqwest::Response) -> impl Stream<Item = String> + Send {
+    let ndjson = content_type(&upstream).is_some_and(|ct| ct.starts_with(NDJSON_CONTENT_TYPE));
+    let parser = EventParser {
+        ndjson,
+        ..EventParser::default()
+    };
+    let state = (upstream, parser);
+    stream::unfold(state, |(mut upstream, mut parser)| async move {
+        let bytes = upstream.chunk().await.ok().flatten()?;
+        Some((stream::iter(parser.feed(&bytes)), (upstream, parser)))
+    })
+    .flatten()
+}
+
+/// Forward upstream payloads to `tx` and the session publisher, re-ordering, capping
+/// and coalescing them first when configured. Stops reading the upstream once nobody is
+/// listening, then charges the client for what was relayed.
+async fn pump(
+    upstream: reqwest::Response,
+    options: StreamOptions,
+    tx: mpsc::Sender<Frame>,
+    publisher: Option<Publisher>,
+    mut timer: Option<TokenTimer>,
+    bytes: Option<ByteCounter>,
+    mut charge: Option<StreamCharge>,
+) {
+    let payloads = data_payloads(upstream);
+    let payloads = match options.role_map {
+        Some(map) => payloads.map(|payload| map.restore_payload(payload)).boxed(),
+        None => payloads.boxed(),
+    };
+    let payloads = match options.reorder_window {
+        Some(window) => reorder::reorder(payloads, window, options.reorder_timeout).boxed(),
+        None => payloads,
+    };
+    let payloads = match options.max_tokens {
+        Some(max_tokens) => token_limit::limit(payloads, max_tokens).boxed(),
+        None => payloads,
+    };
+    let mut payloads = match options.coalesce_window {
+        Some(window) => coalesce::coalesce(payloads, window).boxed(),
+        None => payloads,
+    };
+    let mut client = Some(tx);
+    while let Some(payload) = payloads.next().await {
+        if let Some(timer) = &mut timer {
+            timer.observe(&payload);
+        }
+        if let Some(bytes) = &bytes {
+            bytes.add(payload.len());
+        }
+        if let Some(charge) = &mut charge {
+            charge.observe(&payload);
+        }
+        if let Some(publisher) = &publisher {
+            publisher.send(&payload);
+        }
+        let delivered = match &client {
+            Some(tx) => tx.send(Frame::Payload(payload)).await.is_ok(),
+            None => false,
+        };
+        if !delivered {
+            client = None;
+        }
+        // Subscribers keep the generation going after the primary client leaves
+        if client.is_none() && !publisher.as_ref().is_some_and(Publisher::has_subscribers) {
+            break;
+        }
+    }
+    if let Some(charge) = charge {
+        charge.finish();
+    }
+    if let (Some(tx), Some(timer)) = (client, timer) {
+        let _ = tx.send(Frame::Timing(timer.event())).await;
+    }
+}
+
+/// What [`pump`] hands the client side of the relay, framed there.
+enum Frame {
+    Payload(String),
+    Timing(Event),
+    Heartbeat,
+}
+
+impl Frame {
+    fn event(self, style: HeartbeatStyle) -> Event {
+        match self {
+            Frame::Payload(payload) => Event::default().data(payload),
+            Frame::Timing(event) => event,
+            Frame::Heartbeat => match style {
+                HeartbeatStyle::Comment => Event::default().comment(""),
+                HeartbeatStyle::Data => Event::default().data("{}"),
+            },
+        }
+    }
+
+    /// The NDJSON line for this frame; `[DONE]` and the timing event have none.
+    fn line(self, style: HeartbeatStyle) -> Option<Bytes> {
+        match self {
+            Frame::Payload(payload) if payload == "[DONE]" => None,
+            Frame::Payload(payload) => Some(Bytes::from(payload + "\n")),
+            Frame::Timing(_) => None,
+            Frame::Heartbeat => match style {
+                HeartbeatStyle::Comment => Some(Bytes::from_static(b"\n")),
+                HeartbeatStyle::Data => Some(Bytes::from_static(b"{}\n")),
+            },
+        }
+    }
+}
+
+/// Relay an upstream event stream to the client as it arrives, framed as
+/// `options.framing`, with a heartbeat whenever it has been idle for the configured
+/// interval. `bytes` counts the event data relayed and `charge` bills it to the client;
+/// `held` (e.g. the backend's outstanding-request count) lives until the upstream
+/// stream is done.
+pub fn relay_stream(
+    upstream: reqwest::Response,
+    options: StreamOptions,
+    session: Option<&str>,
+    emit_timing: bool,
+    bytes: Option<ByteCounter>,
+    charge: Option<StreamCharge>,
+    held: impl Send + 'static,
+) -> warp::reply::Response {
+    let (tx, rx) = mpsc::channel(32);
+    let publisher = session.map(|id| fanout::SESSIONS.publish(id));
+    let timer = (emit_timing && options.framing == Framing::Sse).then(TokenTimer::default);
+    tokio::spawn(async move {
+        pump(upstream, options, tx, publisher, timer, bytes, charge).await;
+        drop(held);
+    });
+    let frames = stream::unfold(rx, move |mut rx| async move {
+        let frame = match options.heartbeat_interval {
+            Some(interval) => match tokio::time::timeout(interval, rx.recv()).await {
+                Ok(frame) => frame?,
+                Err(_) => Frame::Heartbeat,
+            },
+            None => rx.recv().await?,
+        };
+        Some((frame, rx))
+    });
+    let style = options.heartbeat_style;
+    match options.framing {
+        Framing::Sse => {
+            let events = frames.map(move |frame| Ok::<_, Infallible>(frame.event(style)));
+            warp::sse::reply(events).into_response()
+        }
+        Framing::Ndjson => {
+            let lines = frames.filter_map(move |frame| future::ready(frame.line(style)));
+            serve::streamed(NDJSON_CONTENT_TYPE, lines)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    const UPSTREAM_BODY: &str = "data: {\"choices\":[{\"delta\":{\"content\":\"Echo \"}}]}\n\n\
+        data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n\
+        data: [DONE]\n\n";
+
+    fn upstream() -> reqwest::Response {
+        warp::http::Response::builder()
+            .header("content-type", "text/event-stream")
+            .body(UPSTREAM_BODY)
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_relay_stream_forwards_each_event_and_done() {
+        let route = warp::any().map(|| {
+            relay_stream(
+                upstream(),
+                StreamOptions::default(),
+                None,
+                false,
+                None,
+                None,
+                (),
+            )
+        });
+        let resp = warp::test::request().reply(&route).await;
+        assert_eq!(resp.headers()["content-type"], "text/event-stream");
+
+        let body = String::from_utf8_lossy(resp.body()).into_owned();
+        let events: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert!(events[0].contains("Echo "), "{body}");
+        assert_eq!(events[2], "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn test_timing_event_appended_when_requested() {
+        let route = warp::any().map(|| {
+            relay_stream(
+                upstream(),
+                StreamOptions::default(),
+                None,
+                true,
+                None,
+                None,
+                (),
+            )
+        });
+        let resp = warp::test::request().reply(&route).await;
+        let body = String::from_utf8_lossy(resp.body()).into_owned();
+
+        let (before, timing) = body.rsplit_once("event:timing\n").expect(&body);
+        assert!(before.ends_with("data:[DONE]\n\n"), "{body}");
+        let data = timing.strip_prefix("data:").unwrap().trim_end();
+        let timing: serde_json::Value = serde_json::from_str(data).unwrap();
+        assert_eq!(timing["tokens"], 2);
+        let gaps = timing["inter_token_ms"].as_array().unwrap();
+        assert_eq!(gaps.len(), 1);
+        assert!(gaps[0].as_f64().unwrap() >= 0.0);
+    }
+
+    /// An upstream that goes quiet for 300ms between its two events.
+    async fn idle_upstream() -> reqwest::Response {
+        let route = warp::any().map(|| {
+            let events = stream::iter(["{\"n\":1}", "[DONE]"]).then(|data| async move {
+                if data == "[DONE]" {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                }
+                Ok::<_, Infallible>(Event::default().data(data))
+            });
+            warp::sse::reply(events)
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        reqwest::get(url).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_style_sent_while_idle() {
+        for (style, beat) in [
+            (HeartbeatStyle::Comment, ":\n\n"),
+            (HeartbeatStyle::Data, "data:{}\n\n"),
+        ] {
+            let options = StreamOptions {
+                heartbeat_interval: Some(Duration::from_millis(50)),
+                heartbeat_style: style,
+                ..StreamOptions::default()
+            };
+            // The route closure must be reusable, so the response is handed over once
+            let upstream = std::sync::Arc::new(std::sync::Mutex::new(Some(idle_upstream().await)));
+            let route = warp::any().map(move || {
+                let upstream = upstream.lock().unwrap().take().unwrap();
+                relay_stream(upstream, options, None, false, None, None, ())
+            });
+            let resp = warp::test::request().reply(&route).await;
+            let body = String::from_utf8_lossy(resp.body()).into_owned();
+            let (first, rest) = body.split_once("data:{\"n\":1}\n\n").expect(&body);
+            assert!(first.is_empty(), "{body}");
+            assert!(rest.starts_with(beat), "{style:?}: {body}");
+            assert!(rest.ends_with("data:[DONE]\n\n"), "{body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_stops_pump() {
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+        // Returns quietly instead of panicking once the client side is gone
+        let pumped = pump(
+            upstream(),
+            StreamOptions::default(),
+            tx,
+            None,
+            None,
+            None,
+            None,
+        );
+        tokio::time::timeout(Duration::from_secs(1), pumped)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_fans_out_to_every_subscriber() {
+        let sessions: &'static fanout::Sessions = Box::leak(Box::default());
+        let mut viewers = [sessions.subscribe("demo"), sessions.subscribe("demo")];
+        let (tx, mut rx) = mpsc::channel(8);
+        let publisher = Some(sessions.publish("demo"));
+        pump(
+            upstream(),
+            StreamOptions::default(),
+            tx,
+            publisher,
+            None,
+            None,
+            None,
+        )
+        .await;
+
+        let mut primary = Vec::new();
+        while let Some(frame) = rx.recv().await {
+            primary.push(frame.event(HeartbeatStyle::Comment).to_string());
+        }
+        assert_eq!(primary.len(), 3);
+        for viewer in &mut viewers {
+            let mut received = Vec::new();
+            while let Some(payload) = viewer.next().await {
+                received.push(Event::default().data(payload).to_string());
+            }
+            assert_eq!(received, primary);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_lines_arrive_incrementally() {
+        // The upstream holds its second event back until the first has reached the client
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let released = std::sync::Arc::new(std::sync::Mutex::new(Some(released)));
+        let backend = warp::any().map(move || {
+            let released = released.lock().unwrap().take().unwrap();
+            let second = stream::once(async move {
+                let _ = released.await;
+                Event::default().data("{\"n\":2}")
+            });
+            let events = stream::once(async { Event::default().data("{\"n\":1}") })
+                .chain(second)
+                .chain(stream::once(async { Event::default().data("[DONE]") }))
+                .map(Ok::<_, Infallible>);
+            warp::sse::reply(events)
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(warp::serve(backend).incoming(listener).run());
+
+        let options = StreamOptions {
+            framing: Framing::for_accept(Some("application/x-ndjson")),
+            ..StreamOptions::default()
+        };
+        let route = warp::any().then(move || {
+            let backend_url = backend_url.clone();
+            async move {
+                let upstream = reqwest::get(backend_url).await.unwrap();
+                relay_stream(upstream, options, None, true, None, None, ())
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve::run(listener, route, std::future::pending()));
+
+        let mut resp = reqwest::get(url).await.unwrap();
+        assert_eq!(resp.headers()["content-type"], NDJSON_CONTENT_TYPE);
+        let first = resp.chunk().await.unwrap().unwrap();
+        assert_eq!(first, "{\"n\":1}\n");
+        release.send(()).unwrap();
+        let second = resp.chunk().await.unwrap().unwrap();
+        assert_eq!(second, "{\"n\":2}\n");
+        // `[DONE]` and the timing event have no NDJSON line
+        assert!(resp.chunk().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parser_splits_ndjson_lines_across_reads() {
+        let mut parser = EventParser {
+            ndjson: true,
+            ..EventParser::default()
+        };
+        assert!(parser.feed(b"{\"a\":").is_empty());
+        let payloads = parser.feed(b"1}\n\n{\"b\":2}\n");
+        assert_eq!(payloads, [r#"{"a":1}"#, r#"{"b":2}"#]);
+        assert!(parser.buf.is_empty());
+    }
+
+    #[test]
+    fn test_ndjson_framing_only_when_accepted() {
+        assert_eq!(Framing::for_accept(None), Framing::Sse);
+        assert_eq!(Framing::for_accept(Some("text/event-stream")), Framing::Sse);
+        let accept = "application/json, application/x-ndjson;q=0.9";
+        assert_eq!(Framing::for_accept(Some(accept)), Framing::Ndjson);
+    }
+
+    #[test]
+    fn test_parser_reassembles_events_split_across_reads() {
+        let mut parser = EventParser::default();
+        assert!(parser.feed(b"data: {\"a\":").is_empty());
+        let payloads = parser.feed(b"1}\n\ndata: [DONE]\n\n: comment\n\n");
+        assert_eq!(payloads, [r#"{"a":1}"#, "[DONE]"]);
+        assert!(parser.buf.is_empty());
+    }
+}
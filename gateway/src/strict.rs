@@ -0,0 +1,286 @@
+//! Chat request body parsing and validation, with an optional strict mode.
+//!
+//! By default fields the gateway doesn't use are passed through to the backend as sent.
+//! With `GATEWAY_STRICT_FIELDS=1` the body is parsed into mirror structs listing
+//! OpenAI's chat fields and marked `deny_unknown_fields`, so client typos surface as a
+//! `400` naming the offending field instead of reaching the backend.
+
+use serde::Deserialize;
+use serde_json::Value;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::{ChatCompletionRequest, ChatMessage, limits, proxy};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictChatCompletionRequest {
+    model: String,
+    messages: Vec<StrictChatMessage>,
+    #[serde(default)]
+    stream: Option<bool>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    response_format: Option<Value>,
+    #[serde(default)]
+    json_schema: Option<Value>,
+    #[serde(default)]
+    temperature: Option<f64>,
+    #[serde(default)]
+    n: Option<u32>,
+    // The rest of OpenAI's chat fields, passed through untouched
+    #[serde(default)]
+    audio: Option<Value>,
+    #[serde(default)]
+    frequency_penalty: Option<Value>,
+    #[serde(default)]
+    function_call: Option<Value>,
+    #[serde(default)]
+    functions: Option<Value>,
+    #[serde(default)]
+    logit_bias: Option<Value>,
+    #[serde(default)]
+    logprobs: Option<Value>,
+    #[serde(default)]
+    max_completion_tokens: Option<Value>,
+    #[serde(default)]
+    max_tokens: Option<Value>,
+    #[serde(default)]
+    metadata: Option<Value>,
+    #[serde(default)]
+    modalities: Option<Value>,
+    #[serde(default)]
+    parallel_tool_calls: Option<Value>,
+    #[serde(default)]
+    prediction: Option<Value>,
+    #[serde(default)]
+    presence_penalty: Option<Value>,
+    #[serde(default)]
+    reasoning_effort: Option<Value>,
+    #[serde(default)]
+    seed: Option<Value>,
+    #[serde(default)]
+    service_tier: Option<Value>,
+    #[serde(default)]
+    stop: Option<Value>,
+    #[serde(default)]
+    store: Option<Value>,
+    #[serde(default)]
+    stream_options: Option<Value>,
+    #[serde(default)]
+    tool_choice: Option<Value>,
+    #[serde(default)]
+    tools: Option<Value>,
+    #[serde(default)]
+    top_logprobs: Option<Value>,
+    #[serde(default)]
+    top_p: Option<Value>,
+    #[serde(default)]
+    web_search_options: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictChatMessage {
+    role: String,
+    content: String,
+}
+
+impl From<StrictChatCompletionRequest> for ChatCompletionRequest {
+    fn from(req: StrictChatCompletionRequest) -> Self {
+        Self {
+            model: req.model,
+            messages: req
+                .messages
+                .into_iter()
+                .map(|m| ChatMessage {
+                    role: m.role,
+                    content: m.content,
+                })
+                .collect(),
+            stream: req.stream,
+            user: req.user,
+            response_format: req.response_format,
+            json_schema: req.json_schema,
+            temperature: req.temperature,
+            n: req.n,
+            extra: [
+                ("audio", req.audio),
+                ("frequency_penalty", req.frequency_penalty),
+                ("function_call", req.function_call),
+                ("functions", req.functions),
+                ("logit_bias", req.logit_bias),
+                ("logprobs", req.logprobs),
+                ("max_completion_tokens", req.max_completion_tokens),
+                ("max_tokens", req.max_tokens),
+                ("metadata", req.metadata),
+                ("modalities", req.modalities),
+                ("parallel_tool_calls", req.parallel_tool_calls),
+                ("prediction", req.prediction),
+                ("presence_penalty", req.presence_penalty),
+                ("reasoning_effort", req.reasoning_effort),
+                ("seed", req.seed),
+                ("service_tier", req.service_tier),
+                ("stop", req.stop),
+                ("store", req.store),
+                ("stream_options", req.stream_options),
+                ("tool_choice", req.tool_choice),
+                ("tools", req.tools),
+                ("top_logprobs", req.top_logprobs),
+                ("top_p", req.top_p),
+                ("web_search_options", req.web_search_options),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .collect(),
+        }
+    }
+}
+
+/// The request body could not be parsed or failed validation; carries the reason.
+#[derive(Debug)]
+struct InvalidBody(String);
+
+impl warp::reject::Reject for InvalidBody {}
+
+pub fn parse_chat_request(bytes: &[u8], strict: bool) -> Result<ChatCompletionRequest, String> {
+    let parsed = if strict {
+        serde_json::from_slice::<StrictChatCompletionRequest>(bytes).map(Into::into)
+    } else {
+        serde_json::from_slice::<ChatCompletionRequest>(bytes)
+    };
+    parsed.map_err(|e| {
+        if limits::has_content_parts(bytes) {
+            "invalid request body: array `content` (content parts) is not supported yet; \
+             send each message's content as a string"
+                .to_string()
+        } else {
+            format!("invalid request body: {e}")
+        }
+    })
+}
+
+/// Extract a `ChatCompletionRequest`, rejecting with [`InvalidBody`] when it fails to
+/// parse or breaks a size limit.
+pub fn chat_body(
+    strict: bool,
+    max_message_chars: Option<usize>,
+    max_attachments: Option<usize>,
+) -> impl Filter<Extract = (ChatCompletionRequest,), Error = Rejection> + Clone {
+    warp::body::bytes().and_then(move |bytes: warp::hyper::body::Bytes| async move {
+        limits::check_attachments(&bytes, max_attachments)
+            .and_then(|()| parse_chat_request(&bytes, strict))
+            .and_then(|req| {
+                limits::check_message_chars(&req.messages, max_message_chars).map(|()| req)
+            })
+            .map_err(|e| warp::reject::custom(InvalidBody(e)))
+    })
+}
+
+/// Turn an [`InvalidBody`] rejection into a `400` JSON error; other rejections pass on.
+pub async fn recover_invalid_body(err: Rejection) -> Result<impl Reply, Rejection> {
+    let Some(InvalidBody(message)) = err.find::<InvalidBody>() else {
+        return Err(err);
+    };
+    Ok(proxy::error_reply(message.clone(), StatusCode::BAD_REQUEST))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WITH_EXTRA_FIELD: &[u8] =
+        br#"{"model":"m","messages":[{"role":"user","content":"hi"}],"temprature":0.2}"#;
+
+    /// The JSON body the gateway would send the backend for `req`.
+    fn forwarded(req: &ChatCompletionRequest) -> serde_json::Value {
+        let upstream = proxy::json_body(reqwest::Client::new().post("http://node/"), req, false)
+            .build()
+            .unwrap();
+        serde_json::from_slice(upstream.body().unwrap().as_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_unknown_field_accepted_when_lenient() {
+        let req = parse_chat_request(WITH_EXTRA_FIELD, false).unwrap();
+        assert_eq!(req.model, "m");
+        assert_eq!(req.messages.len(), 1);
+        assert_eq!(forwarded(&req)["temprature"], 0.2);
+    }
+
+    #[test]
+    fn test_unknown_field_rejected_when_strict() {
+        let err = parse_chat_request(WITH_EXTRA_FIELD, true).unwrap_err();
+        assert!(err.contains("unknown field `temprature`"), "{err}");
+
+        let nested = br#"{"model":"m","messages":[{"role":"user","content":"hi","name":"x"}]}"#;
+        let err = parse_chat_request(nested, true).unwrap_err();
+        assert!(err.contains("unknown field `name`"), "{err}");
+    }
+
+    #[test]
+    fn test_openai_fields_forwarded_in_both_modes() {
+        let body = br#"{"model":"m","messages":[{"role":"user","content":"hi"}],
+            "max_tokens":64,"top_p":0.9,"seed":7,"stop":["\n"],
+            "tools":[{"type":"function","function":{"name":"f"}}]}"#;
+        for strict in [false, true] {
+            let req = parse_chat_request(body, strict).unwrap();
+            let sent = forwarded(&req);
+            assert_eq!(sent["max_tokens"], 64, "strict={strict}");
+            assert_eq!(sent["top_p"], 0.9);
+            assert_eq!(sent["seed"], 7);
+            assert_eq!(sent["stop"][0], "\n");
+            assert_eq!(sent["tools"][0]["function"]["name"], "f");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_filter_replies_400_naming_field() {
+        let filter = chat_body(true, None, None)
+            .map(|req: ChatCompletionRequest| req.model)
+            .recover(recover_invalid_body);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .body(WITH_EXTRA_FIELD)
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("temprature"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn test_attachment_limit_through_chat_filter() {
+        let filter = chat_body(false, None, Some(1))
+            .map(|req: ChatCompletionRequest| req.model)
+            .recover(recover_invalid_body);
+        let send = |content: serde_json::Value| {
+            let body = serde_json::json!({
+                "model": "m",
+                "messages": [{ "role": "user", "content": content }]
+            });
+            warp::test::request()
+                .method("POST")
+                .body(body.to_string())
+                .reply(&filter)
+        };
+        let image = serde_json::json!({ "type": "image_url", "image_url": { "url": "data:," } });
+
+        let resp = send(serde_json::json!([image, image])).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("2 attachments; the limit is 1"), "{body}");
+
+        // Within the limit, content parts still can't be parsed, and say so
+        let resp = send(serde_json::json!([image])).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("not supported yet"), "{body}");
+
+        let resp = send(serde_json::json!("hi")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), "m");
+    }
+}
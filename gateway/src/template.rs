@@ -0,0 +1,128 @@
+//! Per-model system prompt templates (`GATEWAY_SYSTEM_PROMPTS`).
+//!
+//! Templates may reference `{date}` (today in UTC, `YYYY-MM-DD`), `{user}` (the request's
+//! `user` field, empty when absent) and `{model}`; `{{` and `}}` produce literal braces.
+//! Templates are validated at startup, so an unknown variable stops the gateway from
+//! starting rather than leaking `{typo}` into prompts.
+
+use anyhow::bail;
+use time::OffsetDateTime;
+
+use crate::ChatCompletionRequest;
+
+pub const VARIABLES: &[&str] = &["date", "user", "model"];
+
+enum Segment<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+fn parse(template: &str) -> anyhow::Result<Vec<Segment<'_>>> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        segments.push(Segment::Text(&rest[..pos]));
+        let tail = &rest[pos..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            segments.push(Segment::Text(&tail[..1]));
+            rest = &tail[2..];
+            continue;
+        }
+        if tail.starts_with('}') {
+            bail!("unmatched '}}' in template {template:?}");
+        }
+        let Some(end) = tail.find('}') else {
+            bail!("unclosed '{{' in template {template:?}");
+        };
+        let name = &tail[1..end];
+        if !VARIABLES.contains(&name) {
+            bail!(
+                "unknown variable {{{name}}} in template {template:?}; expected one of {VARIABLES:?}"
+            );
+        }
+        segments.push(Segment::Variable(name));
+        rest = &tail[end + 1..];
+    }
+    segments.push(Segment::Text(rest));
+    Ok(segments)
+}
+
+/// Check that `template` is well-formed and only uses known variables.
+pub fn validate(template: &str) -> anyhow::Result<()> {
+    parse(template).map(|_| ())
+}
+
+/// Render a validated `template` for `req`.
+pub fn render(template: &str, req: &ChatCompletionRequest) -> String {
+    let Ok(segments) = parse(template) else {
+        return template.to_string();
+    };
+    let mut rendered = String::with_capacity(template.len());
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => rendered.push_str(text),
+            Segment::Variable("date") => rendered.push_str(&today()),
+            Segment::Variable("user") => rendered.push_str(req.user.as_deref().unwrap_or("")),
+            Segment::Variable(_) => rendered.push_str(&req.model),
+        }
+    }
+    rendered
+}
+
+/// Today's UTC date as `YYYY-MM-DD`.
+pub fn today() -> String {
+    OffsetDateTime::now_utc().date().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatMessage, normalize};
+
+    fn request(user: Option<&str>) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "qwen3-8b".into(),
+            messages: vec![ChatMessage {
+                role: "user".into(),
+                content: "hi".into(),
+            }],
+            user: user.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_date_substituted_in_forwarded_system_message() {
+        let mut req = request(Some("ada"));
+        let prompt = render("Today is {date}. You are helping {user} via {model}.", &req);
+        normalize::prepend_system(&mut req.messages, prompt);
+
+        assert_eq!(req.messages[0].role, "system");
+        assert_eq!(
+            req.messages[0].content,
+            format!("Today is {}. You are helping ada via qwen3-8b.", today())
+        );
+        assert_eq!(req.messages[1].content, "hi");
+    }
+
+    #[test]
+    fn test_unknown_or_malformed_variables_rejected() {
+        assert!(validate("Today is {date}, {{literal}}").is_ok());
+        let err = validate("Hello {name}").unwrap_err().to_string();
+        assert!(err.contains("{name}"), "{err}");
+        assert!(validate("Hello {user").is_err());
+        assert!(validate("Hello }").is_err());
+    }
+
+    #[test]
+    fn test_date_is_iso_utc() {
+        let date = time::Date::from_calendar_date(2000, time::Month::February, 29).unwrap();
+        assert_eq!(date.to_string(), "2000-02-29");
+        let fields: Vec<usize> = today().split('-').map(str::len).collect();
+        assert_eq!(fields, [4, 2, 2]);
+        assert_eq!(
+            render("{{{date}}}", &request(None)),
+            format!("{{{}}}", today())
+        );
+    }
+}
@@ -0,0 +1,56 @@
+//! Inter-token timing for streamed chat replies, requested with `X-Emit-Timing: 1`.
+//!
+//! Every upstream event other than `[DONE]` counts as one token. Once the stream ends,
+//! the gateway sends a final `event: timing` whose data is
+//! `{"tokens": N, "inter_token_ms": [...]}`, the gaps between consecutive tokens as
+//! seen by the gateway.
+
+use std::time::Instant;
+
+use serde::Serialize;
+use warp::sse::Event;
+
+#[derive(Debug, Default, Serialize)]
+pub struct TokenTimer {
+    tokens: usize,
+    inter_token_ms: Vec<f64>,
+    #[serde(skip)]
+    last: Option<Instant>,
+}
+
+impl TokenTimer {
+    pub fn observe(&mut self, payload: &str) {
+        if payload == "[DONE]" {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            self.inter_token_ms
+                .push(now.duration_since(last).as_secs_f64() * 1000.0);
+        }
+        self.last = Some(now);
+        self.tokens += 1;
+    }
+
+    pub fn event(&self) -> Event {
+        Event::default()
+            .event("timing")
+            .json_data(self)
+            .expect("timing serializes to JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_done_is_not_a_token() {
+        let mut timer = TokenTimer::default();
+        for payload in ["a", "b", "c", "[DONE]"] {
+            timer.observe(payload);
+        }
+        assert_eq!(timer.tokens, 3);
+        assert_eq!(timer.inter_token_ms.len(), 2);
+    }
+}
@@ -0,0 +1,104 @@
+//! Opt-in cap on the tokens forwarded per streamed reply (`GATEWAY_MAX_STREAM_TOKENS`).
+//!
+//! Every upstream chunk whose delta carries content counts as one token. Once the cap
+//! is reached the gateway stops reading, sends its own final chunk with
+//! `finish_reason: "length"` followed by `[DONE]`, and drops the upstream stream,
+//! which closes the backend connection. Streams that finish on their own under the
+//! cap pass through unchanged.
+
+use futures_util::{Stream, StreamExt, stream};
+use serde_json::{Value, json};
+use tracing::info;
+
+/// Fields copied from the last forwarded chunk so the final one looks like the rest.
+const CHUNK_IDENTITY: &[&str] = &["id", "object", "created", "model"];
+
+fn is_token(chunk: &Value) -> bool {
+    chunk["choices"]
+        .as_array()
+        .is_some_and(|choices| choices.iter().any(|c| c["delta"]["content"].is_string()))
+}
+
+/// The chunk ending a stream cut at the cap, modelled on `last`.
+fn length_chunk(last: Option<&Value>) -> String {
+    let mut chunk = json!({
+        "choices": [{"index": 0, "delta": {}, "finish_reason": "length"}],
+    });
+    for key in CHUNK_IDENTITY {
+        if let Some(value) = last.map(|last| &last[*key]).filter(|v| !v.is_null()) {
+            chunk[*key] = value.clone();
+        }
+    }
+    chunk.to_string()
+}
+
+struct Limiter<S> {
+    upstream: S,
+    max_tokens: usize,
+    tokens: usize,
+    last: Option<Value>,
+}
+
+/// Forward `payloads` until `max_tokens` tokens have been sent, then end the stream.
+pub fn limit(
+    payloads: impl Stream<Item = String> + Send + Unpin,
+    max_tokens: usize,
+) -> impl Stream<Item = String> + Send {
+    let limiter = Limiter {
+        upstream: payloads,
+        max_tokens,
+        tokens: 0,
+        last: None,
+    };
+    stream::unfold(Some(limiter), |limiter| async move {
+        let mut limiter = limiter?;
+        if limiter.tokens >= limiter.max_tokens {
+            info!("Cut streamed reply at {} tokens", limiter.tokens);
+            let end = vec![length_chunk(limiter.last.as_ref()), "[DONE]".to_string()];
+            // Dropping the limiter drops the upstream response
+            return Some((stream::iter(end), None));
+        }
+        let payload = limiter.upstream.next().await?;
+        if let Ok(chunk) = serde_json::from_str::<Value>(&payload) {
+            if is_token(&chunk) {
+                limiter.tokens += 1;
+            }
+            limiter.last = Some(chunk);
+        }
+        Some((stream::iter(vec![payload]), Some(limiter)))
+    })
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(content: &str) -> String {
+        json!({"id": "c1", "model": "m", "choices": [{"index": 0, "delta": {"content": content}}]})
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_stream_cut_at_token_limit() {
+        let role = json!({"id": "c1", "choices": [{"index": 0, "delta": {"role": "assistant"}}]});
+        let upstream: Vec<String> = std::iter::once(role.to_string())
+            .chain((0..10).map(|i| delta(&format!("t{i} "))))
+            .chain(["[DONE]".to_string()])
+            .collect();
+
+        let forwarded: Vec<String> = limit(stream::iter(upstream.clone()), 3).collect().await;
+        // The role chunk, three tokens, the length chunk and [DONE]
+        assert_eq!(forwarded.len(), 6);
+        assert_eq!(forwarded[..4], upstream[..4]);
+        let last: Value = serde_json::from_str(&forwarded[4]).unwrap();
+        assert_eq!(last["choices"][0]["finish_reason"], "length");
+        assert_eq!(last["id"], "c1");
+        assert_eq!(last["model"], "m");
+        assert_eq!(forwarded[5], "[DONE]");
+
+        // Under the cap the stream is untouched
+        let forwarded: Vec<String> = limit(stream::iter(upstream.clone()), 20).collect().await;
+        assert_eq!(forwarded, upstream);
+    }
+}
@@ -0,0 +1,75 @@
+//! `X-Trace-Url` on chat and TTS responses, for pasting into the tracing UI.
+//!
+//! With `GATEWAY_TRACE_URL_BASE` set, each reply carries that base followed by the
+//! request id, which every request span records as `request_id`. Nothing is added
+//! when no base is configured.
+
+use tracing::warn;
+use warp::http::HeaderValue;
+
+pub const TRACE_URL_HEADER: &str = "x-trace-url";
+
+/// Percent-encode everything but RFC 3986 unreserved characters, since request ids
+/// may be client-supplied.
+fn encode(id: &str) -> String {
+    id.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+pub fn trace_url(base: &str, request_id: &str) -> String {
+    format!("{base}{}", encode(request_id))
+}
+
+/// Add the header to `reply` when a base URL is configured.
+pub fn attach(
+    mut reply: warp::reply::Response,
+    base: Option<&str>,
+    request_id: &str,
+) -> warp::reply::Response {
+    let Some(base) = base else {
+        return reply;
+    };
+    match HeaderValue::from_str(&trace_url(base, request_id)) {
+        Ok(value) => {
+            reply.headers_mut().insert(TRACE_URL_HEADER, value);
+        }
+        Err(e) => warn!("Skipping {TRACE_URL_HEADER}: {e}"),
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::Reply;
+
+    use super::*;
+
+    #[test]
+    fn test_header_set_only_when_configured() {
+        let base = Some("https://traces.example.com/search?request_id=");
+        let reply = attach("ok".into_response(), base, "req 1/2");
+        let url = reply.headers()[TRACE_URL_HEADER].to_str().unwrap();
+        assert_eq!(
+            url,
+            "https://traces.example.com/search?request_id=req%201%2F2"
+        );
+
+        let uuid = "0b6f8a2e-5d4c-4c1e-9a51-3f1f0f7a9b10";
+        let reply = attach("ok".into_response(), base, uuid);
+        assert!(
+            reply.headers()[TRACE_URL_HEADER]
+                .to_str()
+                .unwrap()
+                .ends_with(uuid)
+        );
+
+        let reply = attach("ok".into_response(), None, uuid);
+        assert!(!reply.headers().contains_key(TRACE_URL_HEADER));
+    }
+}
@@ -0,0 +1,79 @@
+//! Classification of chat backend error replies for metrics
+//! (`GATEWAY_CLASSIFY_UPSTREAM_ERRORS`).
+//!
+//! OpenAI-compatible backends answer failures with `{"error": {"type", "code", ...}}`.
+//! When enabled, each non-2xx chat reply is parsed for that shape and counted in
+//! `gateway_upstream_errors_total` by type and code; bodies in another shape count as
+//! type `unknown`. The client still receives the body exactly as the backend sent it.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::metrics;
+
+#[derive(Debug, Deserialize)]
+struct ErrorEnvelope {
+    error: OpenAiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiError {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// A string for most backends, though some send numbers.
+    code: Option<Value>,
+}
+
+/// The `(type, code)` of an OpenAI error body; either may be empty.
+pub fn classify(body: &[u8]) -> Option<(String, String)> {
+    let ErrorEnvelope { error } = serde_json::from_slice(body).ok()?;
+    let code = match error.code {
+        Some(Value::String(code)) => code,
+        Some(Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    };
+    Some((error.kind.unwrap_or_default(), code))
+}
+
+/// Count an error reply in `gateway_upstream_errors_total`.
+pub fn record(body: &[u8]) {
+    let (kind, code) = classify(body).unwrap_or_else(|| ("unknown".into(), String::new()));
+    metrics::UPSTREAM_ERRORS.inc(&[&kind, &code]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upstream_error_type_counted_in_metrics() {
+        let body = br#"{"error": {"message": "too long", "type": "test_invalid_request_error",
+            "param": "messages", "code": "context_length_exceeded"}}"#;
+        assert_eq!(
+            classify(body),
+            Some((
+                "test_invalid_request_error".into(),
+                "context_length_exceeded".into()
+            ))
+        );
+        record(body);
+        assert_eq!(
+            classify(br#"{"error": {"type": "server_error", "code": 500}}"#),
+            Some(("server_error".into(), "500".into()))
+        );
+        assert_eq!(classify(b"Bad Gateway"), None);
+
+        let resp = warp::test::request()
+            .path("/metrics")
+            .reply(&metrics::route())
+            .await;
+        let text = String::from_utf8_lossy(resp.body());
+        assert!(
+            text.contains(
+                "gateway_upstream_errors_total{type=\"test_invalid_request_error\",\
+                 code=\"context_length_exceeded\"} 1"
+            ),
+            "{text}"
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Build metadata served at `GET /version`, for matching running binaries to deploys.
+
+use warp::Filter;
+
+/// `GET /version`.
+pub fn route() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("version")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::json(&common::version_info!()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_version_route_reports_crate_version() {
+        let resp = warp::test::request().path("/version").reply(&route()).await;
+        assert_eq!(resp.status(), 200);
+
+        let info: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["name"], "gateway");
+        assert!(info["git_sha"].is_string());
+    }
+}
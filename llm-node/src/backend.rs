@@ -0,0 +1,86 @@
+//! The inference backend that writes chat replies, chosen at startup from
+//! `LLM_NODE_BACKEND`.
+//!
+//! Only `echo` exists today: it repeats the last user message, which keeps the whole
+//! stack testable without a model. Real engines (e.g. llama.cpp) plug in as further
+//! [`InferenceBackend`] impls and [`BackendKind`] names.
+
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::bail;
+
+use crate::{ChatMessage, find_last_user_message};
+
+pub trait InferenceBackend: Debug + Send + Sync {
+    /// The name `LLM_NODE_BACKEND` selects this backend by.
+    fn name(&self) -> &'static str;
+
+    /// The assistant reply to `messages`.
+    fn generate(&self, model: &str, messages: &[ChatMessage]) -> String;
+}
+
+#[derive(Debug)]
+pub struct Echo;
+
+impl InferenceBackend for Echo {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn generate(&self, model: &str, messages: &[ChatMessage]) -> String {
+        let user_message = find_last_user_message(messages);
+        format!(
+            "Echo from llm-node (model={model}): {}",
+            user_message.content
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    #[default]
+    Echo,
+}
+
+impl FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "echo" => Ok(Self::Echo),
+            other => bail!("unknown inference backend {other:?}; expected 'echo'"),
+        }
+    }
+}
+
+impl BackendKind {
+    /// Construct the backend, loading whatever it needs.
+    pub fn build(self) -> Arc<dyn InferenceBackend> {
+        match self {
+            Self::Echo => Arc::new(Echo),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_echo_default_and_unknown_backend_rejected() {
+        assert_eq!(Config::default().backend.name(), "echo");
+        assert_eq!(
+            "echo".parse::<BackendKind>().unwrap().build().name(),
+            "echo"
+        );
+
+        let err = "llamacpp".parse::<BackendKind>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown inference backend \"llamacpp\"; expected 'echo'"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Runtime configuration for llm-node, read once from the environment at startup.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{Context, bail};
+
+use crate::backend::{BackendKind, InferenceBackend};
+
+/// What to do when a request asks for more choices than `max_n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NPolicy {
+    /// Fail the request with `400`.
+    Reject,
+    /// Serve `max_n` choices instead.
+    Clamp,
+}
+
+impl FromStr for NPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "clamp" => Ok(Self::Clamp),
+            other => bail!("unknown n policy {other:?}; expected 'reject' or 'clamp'"),
+        }
+    }
+}
+
+/// How streamed echo text is split into deltas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkMode {
+    /// One delta per whitespace-separated word (trailing space attached).
+    Word,
+    /// One delta per `n` characters; `char` is `Chars(1)`.
+    Chars(usize),
+}
+
+impl FromStr for ChunkMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "word" => Ok(Self::Word),
+            "char" => Ok(Self::Chars(1)),
+            other => match other.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Self::Chars(n)),
+                _ => bail!("unknown chunk mode {other:?}; expected 'word', 'char' or a count"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Upper bound on `n` (choices per request), from `LLM_NODE_MAX_N`.
+    pub max_n: usize,
+    /// Over-limit handling, from `LLM_NODE_N_POLICY`.
+    pub n_policy: NPolicy,
+    /// Delta size for `stream: true` responses, from `LLM_NODE_STREAM_CHUNK`.
+    pub stream_chunk: ChunkMode,
+    /// `temperature` when a request omits it, from `LLM_NODE_DEFAULT_TEMPERATURE`.
+    pub default_temperature: f64,
+    /// Default and upper bound for `max_tokens`, from `LLM_NODE_MAX_TOKENS`.
+    pub max_tokens: u32,
+    /// What writes replies, built from `LLM_NODE_BACKEND` at startup.
+    pub backend: Arc<dyn InferenceBackend>,
+    /// Add a `timing` object to chat replies, from `LLM_NODE_REPORT_TIMING`.
+    pub report_timing: bool,
+    /// Bearer token for `/admin/drain`, from `NODE_ADMIN_TOKEN`; unset refuses admin calls.
+    pub admin_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_n: 8,
+            n_policy: NPolicy::Reject,
+            stream_chunk: ChunkMode::Word,
+            default_temperature: 0.7,
+            max_tokens: 2048,
+            backend: BackendKind::default().build(),
+            report_timing: false,
+            admin_token: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
+        let max_tokens = env_or("LLM_NODE_MAX_TOKENS", defaults.max_tokens)?;
+        if max_tokens == 0 {
+            bail!("LLM_NODE_MAX_TOKENS must be at least 1");
+        }
+        Ok(Self {
+            max_n: env_or("LLM_NODE_MAX_N", defaults.max_n)?,
+            n_policy: env_or("LLM_NODE_N_POLICY", defaults.n_policy)?,
+            stream_chunk: env_or("LLM_NODE_STREAM_CHUNK", defaults.stream_chunk)?,
+            default_temperature: env_or(
+                "LLM_NODE_DEFAULT_TEMPERATURE",
+                defaults.default_temperature,
+            )?,
+            max_tokens,
+            backend: env_or("LLM_NODE_BACKEND", BackendKind::default())?.build(),
+            report_timing: env_flag("LLM_NODE_REPORT_TIMING", defaults.report_timing)?,
+            admin_token: std::env::var("NODE_ADMIN_TOKEN").ok(),
+        })
+    }
+}
+
+fn env_or<T>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    match std::env::var(key) {
+        Ok(raw) => raw
+            .trim()
+            .parse::<T>()
+            .map_err(Into::<anyhow::Error>::into)
+            .with_context(|| format!("invalid {key}={raw:?}")),
+        Err(_) => Ok(default),
+    }
+}
+
+fn env_flag(key: &str, default: bool) -> anyhow::Result<bool> {
+    match std::env::var(key).as_deref().map(str::trim) {
+        Ok("1") => Ok(true),
+        Ok("0") => Ok(false),
+        _ => env_or(key, default),
+    }
+}
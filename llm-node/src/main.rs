@@ -1,15 +1,49 @@
 //! LLM inference service stub exposing OpenAI-compatible chat completions API.
 //! This is a placeholder that echoes input; swap in mistral.rs or llama.cpp later.
 
-use axum::{Json, Router, routing::post};
+mod backend;
+mod config;
+mod msgpack;
+mod sampling;
+mod stream;
+#[cfg(test)]
+mod test_support;
+mod timing;
+mod tokens;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    Extension, Json, Router,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
-use tracing::{Level, info};
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::util::SubscriberInitExt;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+use crate::config::{Config, NPolicy};
+use crate::sampling::SamplingParams;
+use crate::timing::{Received, Timing};
+use crate::tokens::Usage;
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 struct ChatCompletionRequest {
     model: String,
     messages: Vec<ChatMessage>,
+    /// Number of choices to generate (OpenAI `n`); defaults to 1.
+    n: Option<usize>,
+    temperature: Option<f64>,
+    max_tokens: Option<u32>,
+    top_p: Option<f64>,
+    seed: Option<u64>,
+    /// Reply with server-sent `chat.completion.chunk` events instead of one body.
+    stream: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -22,6 +56,10 @@ struct ChatMessage {
 struct ChatCompletionResponse {
     id: String,
     choices: Vec<ChatChoice>,
+    usage: Usage,
+    /// Only with `LLM_NODE_REPORT_TIMING`; not part of the OpenAI schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timing: Option<Timing>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -30,6 +68,30 @@ struct ChatChoice {
     message: ChatMessage,
 }
 
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+fn bad_request(error: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response()
+}
+
+/// Resolve the requested number of choices against the configured cap.
+fn resolve_n(requested: Option<usize>, config: &Config) -> Result<usize, String> {
+    let n = requested.unwrap_or(1);
+    if n == 0 {
+        return Err("n must be at least 1".into());
+    }
+    if n <= config.max_n {
+        return Ok(n);
+    }
+    match config.n_policy {
+        NPolicy::Reject => Err(format!("n={n} exceeds the maximum of {}", config.max_n)),
+        NPolicy::Clamp => Ok(config.max_n),
+    }
+}
+
 fn find_last_user_message(messages: &[ChatMessage]) -> ChatMessage {
     messages
         .iter()
@@ -42,45 +104,132 @@ fn find_last_user_message(messages: &[ChatMessage]) -> ChatMessage {
         })
 }
 
-fn create_echo_response(model: &str, user_message: &ChatMessage) -> ChatCompletionResponse {
-    let reply_text = format!(
-        "Echo from llm-node (model={model}): {}",
-        user_message.content
-    );
+fn create_response(reply_text: String, n: usize, prompt_tokens: usize) -> ChatCompletionResponse {
+    let usage = Usage::new(prompt_tokens, n * tokens::count(&reply_text));
 
     ChatCompletionResponse {
         id: uuid::Uuid::new_v4().to_string(),
-        choices: vec![ChatChoice {
-            index: 0,
-            message: ChatMessage {
-                role: "assistant".into(),
-                content: reply_text,
-            },
-        }],
+        choices: (0..n)
+            .map(|index| ChatChoice {
+                index,
+                message: ChatMessage {
+                    role: "assistant".into(),
+                    content: reply_text.clone(),
+                },
+            })
+            .collect(),
+        usage,
+        timing: None,
     }
 }
 
-async fn chat_handler(Json(req): Json<ChatCompletionRequest>) -> Json<ChatCompletionResponse> {
+/// Serialize as MessagePack when the client's `Accept` asks for it, JSON otherwise.
+fn encode_response(response: &ChatCompletionResponse, headers: &HeaderMap) -> Response {
+    let wants_msgpack = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(msgpack::accepts);
+    if !wants_msgpack {
+        return Json(response).into_response();
+    }
+
+    match rmp_serde::to_vec_named(response) {
+        Ok(bytes) => ([(header::CONTENT_TYPE, msgpack::CONTENT_TYPE)], bytes).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: format!("failed to encode response: {e}"),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+async fn chat_handler(
+    State(config): State<Arc<Config>>,
+    received: Option<Extension<Received>>,
+    headers: HeaderMap,
+    Json(req): Json<ChatCompletionRequest>,
+) -> Response {
     info!(
         "Chat request: model={}, messages={}",
         req.model,
         req.messages.len()
     );
 
-    let last_user = find_last_user_message(&req.messages);
-    let response = create_echo_response(&req.model, &last_user);
+    let n = match resolve_n(req.n, &config) {
+        Ok(n) => n,
+        Err(e) => return bad_request(e),
+    };
+    SamplingParams::resolve(
+        req.temperature,
+        req.max_tokens,
+        req.top_p,
+        n,
+        req.seed,
+        &config,
+    )
+    .log();
+
+    let started = Instant::now();
+    let reply_text = config.backend.generate(&req.model, &req.messages);
+    let prompt_tokens = tokens::prompt_tokens(&req.messages);
+    let mut response = create_response(reply_text, n, prompt_tokens);
+    if config.report_timing {
+        let received = received.map_or(started, |Extension(Received(at))| at);
+        response.timing = Some(Timing {
+            queue_ms: timing::millis_between(received, started),
+            generation_ms: timing::millis_between(started, Instant::now()),
+        });
+    }
 
-    Json(response)
+    if req.stream == Some(true) {
+        let wants_ndjson = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(stream::accepts_ndjson);
+        return if wants_ndjson {
+            stream::ndjson_response(&response, config.stream_chunk)
+        } else {
+            stream::sse_response(&response, config.stream_chunk)
+        };
+    }
+    encode_response(&response, &headers)
+}
+
+async fn version_handler() -> Json<common::version::VersionInfo> {
+    Json(common::version_info!())
+}
+
+fn app(config: Arc<Config>) -> Router {
+    let router = Router::new()
+        .route("/v1/chat/completions", post(chat_handler))
+        .route("/v1/tokenize", post(tokens::tokenize_handler))
+        .route("/version", get(version_handler))
+        .layer(axum::middleware::from_fn(common::gzip::decompress_request))
+        .layer(axum::middleware::from_fn(timing::stamp_received));
+    #[cfg(feature = "otel")]
+    let router = router.layer(axum::middleware::from_fn(common::otel::trace_request));
+    let drain = common::drain::routes(config.admin_token.clone());
+    router.with_state(config).merge(drain)
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .with_env_filter("llm_node=info,axum=info")
-        .init();
+    // RUST_LOG=llm_node=debug adds per-request detail such as resolved sampling parameters
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("llm_node=info,axum=info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter).finish();
+    #[cfg(feature = "otel")]
+    let subscriber = tracing_subscriber::layer::SubscriberExt::with(
+        subscriber,
+        common::otel::layer_from_env("llm-node"),
+    );
+    subscriber.init();
 
-    let app = Router::new().route("/v1/chat/completions", post(chat_handler));
+    let config = Config::from_env()?;
+    info!("Inference backend: {}", config.backend.name());
+    let app = app(Arc::new(config));
 
     let listener = TcpListener::bind("0.0.0.0:9000").await?;
     info!("llm-node listening on {}", listener.local_addr()?);
@@ -92,6 +241,8 @@ async fn main() -> anyhow::Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::{Echo, InferenceBackend};
+    use crate::config::ChunkMode;
 
     #[test]
     fn test_find_last_user_message_found() {
@@ -138,7 +289,8 @@ mod tests {
             content: "Test message".into(),
         };
 
-        let response = create_echo_response("test-model", &user_msg);
+        let reply_text = Echo.generate("test-model", &[user_msg]);
+        let response = create_response(reply_text, 1, 0);
 
         assert!(!response.id.is_empty());
         assert_eq!(response.choices.len(), 1);
@@ -147,4 +299,303 @@ mod tests {
         assert!(response.choices[0].message.content.contains("test-model"));
         assert!(response.choices[0].message.content.contains("Test message"));
     }
+
+    fn config(max_n: usize, n_policy: NPolicy) -> Config {
+        Config {
+            max_n,
+            n_policy,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_n_boundary() {
+        let cfg = config(8, NPolicy::Reject);
+        assert_eq!(resolve_n(None, &cfg), Ok(1));
+        assert_eq!(resolve_n(Some(8), &cfg), Ok(8));
+        assert!(resolve_n(Some(9), &cfg).is_err());
+        assert!(resolve_n(Some(0), &cfg).is_err());
+    }
+
+    #[test]
+    fn test_resolve_n_clamps_when_configured() {
+        let cfg = config(8, NPolicy::Clamp);
+        assert_eq!(resolve_n(Some(1000), &cfg), Ok(8));
+    }
+
+    #[tokio::test]
+    async fn test_chat_handler_rejects_excess_n() {
+        let req = ChatCompletionRequest {
+            model: "test".into(),
+            messages: vec![],
+            n: Some(9),
+            ..Default::default()
+        };
+        let resp = chat_handler(
+            State(Arc::new(Config::default())),
+            None,
+            HeaderMap::new(),
+            Json(req),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_resolved_sampling_defaults_are_logged() {
+        let req = ChatCompletionRequest {
+            model: "test".into(),
+            top_p: Some(0.5),
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let logs = test_support::capture_logs(|| {
+            let handled = chat_handler(
+                State(Arc::new(Config::default())),
+                None,
+                HeaderMap::new(),
+                Json(req),
+            );
+            runtime.block_on(handled);
+        });
+
+        let line = logs
+            .lines()
+            .find(|l| l.contains("Resolved sampling parameters"))
+            .expect(&logs);
+        assert!(line.contains("DEBUG"), "{line}");
+        for field in [
+            "temperature=0.7",
+            "max_tokens=2048",
+            "top_p=0.5",
+            "n=1",
+            "seed=None",
+        ] {
+            assert!(line.contains(field), "missing {field}: {line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_msgpack_response_round_trips() {
+        let req = ChatCompletionRequest {
+            model: "test".into(),
+            messages: vec![ChatMessage {
+                role: "user".into(),
+                content: "hi".into(),
+            }],
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, msgpack::CONTENT_TYPE.parse().unwrap());
+
+        let resp = chat_handler(State(Arc::new(Config::default())), None, headers, Json(req)).await;
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], msgpack::CONTENT_TYPE);
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let decoded: serde_json::Value = rmp_serde::from_slice(&body).expect("valid msgpack");
+        assert_eq!(decoded["choices"][0]["message"]["role"], "assistant");
+        assert!(
+            decoded["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap()
+                .contains("hi")
+        );
+        assert!(!decoded["id"].as_str().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tokenize_matches_reported_usage() {
+        let messages = vec![
+            ChatMessage {
+                role: "system".into(),
+                content: "Be brief.".into(),
+            },
+            ChatMessage {
+                role: "user".into(),
+                content: "How many tokens is this, roughly?".into(),
+            },
+        ];
+        let req = ChatCompletionRequest {
+            model: "m".into(),
+            messages: messages.clone(),
+            ..Default::default()
+        };
+        let resp = chat_handler(
+            State(Arc::new(Config::default())),
+            None,
+            HeaderMap::new(),
+            Json(req),
+        )
+        .await;
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let reply: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let tokenize = serde_json::from_value(serde_json::json!({ "messages": messages })).unwrap();
+        let Json(counted) = tokens::tokenize_handler(Json(tokenize)).await;
+        assert_eq!(counted.prompt_tokens, 17);
+        assert_eq!(reply["usage"]["prompt_tokens"], counted.prompt_tokens);
+        let usage = &reply["usage"];
+        assert_eq!(
+            usage["total_tokens"],
+            usage["prompt_tokens"].as_u64().unwrap() + usage["completion_tokens"].as_u64().unwrap()
+        );
+    }
+
+    /// Post a streaming request and return the `data:` payloads of the SSE body.
+    async fn stream_events(content: &str, stream_chunk: ChunkMode) -> Vec<String> {
+        let req = ChatCompletionRequest {
+            model: "m".into(),
+            messages: vec![ChatMessage {
+                role: "user".into(),
+                content: content.into(),
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+        let config = Config {
+            stream_chunk,
+            ..Config::default()
+        };
+        let resp = chat_handler(State(Arc::new(config)), None, HeaderMap::new(), Json(req)).await;
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_per_character_chunks() {
+        let events = stream_events("hi", ChunkMode::Chars(1)).await;
+        let echo = create_response(
+            Echo.generate(
+                "m",
+                &[ChatMessage {
+                    role: "user".into(),
+                    content: "hi".into(),
+                }],
+            ),
+            1,
+            0,
+        );
+        let chars = echo.choices[0].message.content.chars().count();
+
+        // One event per character, a stop chunk, and the [DONE] sentinel
+        assert_eq!(events.len(), chars + 2);
+        assert_eq!(events.last().unwrap(), "[DONE]");
+        let first: serde_json::Value = serde_json::from_str(&events[0]).unwrap();
+        assert_eq!(first["object"], "chat.completion.chunk");
+        assert_eq!(first["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(first["choices"][0]["delta"]["content"], "E");
+    }
+
+    #[tokio::test]
+    async fn test_stream_default_splits_reply_into_chunks() {
+        let events = stream_events("hello there", Config::default().stream_chunk).await;
+        // At least two content deltas, then the stop chunk and [DONE]
+        assert!(events.len() >= 4, "{events:?}");
+        assert_eq!(events.last().unwrap(), "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn test_stream_per_n_character_chunks() {
+        let events = stream_events("abc", ChunkMode::Chars(10)).await;
+        let echo_len = "Echo from llm-node (model=m): abc".len();
+        assert_eq!(events.len(), echo_len.div_ceil(10) + 2);
+
+        let text: String = events[..events.len() - 2]
+            .iter()
+            .map(|e| serde_json::from_str::<serde_json::Value>(e).unwrap())
+            .map(|v| {
+                v["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(text, "Echo from llm-node (model=m): abc");
+    }
+
+    #[tokio::test]
+    async fn test_stream_ndjson_when_accepted() {
+        let req = ChatCompletionRequest {
+            model: "m".into(),
+            messages: vec![ChatMessage {
+                role: "user".into(),
+                content: "two words".into(),
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, "application/x-ndjson".parse().unwrap());
+
+        let resp = chat_handler(State(Arc::new(Config::default())), None, headers, Json(req)).await;
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            stream::NDJSON_CONTENT_TYPE
+        );
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.ends_with('\n'));
+        let chunks: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("each line is a JSON object"))
+            .collect();
+        // "Echo from llm-node (model=m): two words" is 6 words, plus the stop chunk
+        assert_eq!(chunks.len(), 7);
+        assert!(
+            chunks
+                .iter()
+                .all(|c| c["object"] == "chat.completion.chunk")
+        );
+        assert_eq!(chunks[6]["choices"][0]["finish_reason"], "stop");
+    }
+
+    #[tokio::test]
+    async fn test_gzipped_request_round_trips_through_router() {
+        use tower::ServiceExt;
+
+        let body = serde_json::json!({
+            "model": "m",
+            "messages": [{ "role": "user", "content": "the quick brown fox ".repeat(20) }],
+        });
+        let compressed = common::gzip::compress(body.to_string().as_bytes());
+        let req = axum::http::Request::post("/v1/chat/completions")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(axum::body::Body::from(compressed))
+            .unwrap();
+        let resp = app(Arc::new(Config::default())).oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("quick brown fox"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn test_version_reports_crate_version() {
+        let Json(info) = version_handler().await;
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.name, "llm-node");
+        assert!(!info.git_sha.is_empty());
+    }
 }
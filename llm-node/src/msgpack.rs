@@ -0,0 +1,25 @@
+//! MessagePack content negotiation. Responses are encoded with `rmp_serde` as maps
+//! keyed by field name, so they decode to the same shape as the JSON form.
+
+pub const CONTENT_TYPE: &str = "application/msgpack";
+
+/// True when an `Accept` header asks for MessagePack.
+pub fn accepts(accept: &str) -> bool {
+    accept.split(',').any(|part| {
+        let media = part.split(';').next().unwrap_or_default().trim();
+        media.eq_ignore_ascii_case(CONTENT_TYPE)
+            || media.eq_ignore_ascii_case("application/x-msgpack")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_parses_media_ranges() {
+        assert!(accepts("application/msgpack"));
+        assert!(accepts("text/html, application/x-msgpack;q=0.9"));
+        assert!(!accepts("application/json"));
+    }
+}
@@ -0,0 +1,69 @@
+//! Sampling parameters as llm-node resolves them: request values with defaults
+//! filled in and out-of-range values clamped.
+
+use crate::config::Config;
+
+/// Values the OpenAI API accepts for `temperature`.
+const TEMPERATURE_RANGE: (f64, f64) = (0.0, 2.0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingParams {
+    pub temperature: f64,
+    pub max_tokens: u32,
+    pub top_p: f64,
+    pub n: usize,
+    pub seed: Option<u64>,
+}
+
+impl SamplingParams {
+    /// Apply `config`'s defaults and limits to the requested values; `n` has already
+    /// been checked against its own policy.
+    pub fn resolve(
+        temperature: Option<f64>,
+        max_tokens: Option<u32>,
+        top_p: Option<f64>,
+        n: usize,
+        seed: Option<u64>,
+        config: &Config,
+    ) -> Self {
+        let (min, max) = TEMPERATURE_RANGE;
+        Self {
+            temperature: temperature
+                .unwrap_or(config.default_temperature)
+                .clamp(min, max),
+            max_tokens: max_tokens
+                .unwrap_or(config.max_tokens)
+                .clamp(1, config.max_tokens),
+            top_p: top_p.unwrap_or(1.0).clamp(0.0, 1.0),
+            n,
+            seed,
+        }
+    }
+
+    pub fn log(&self) {
+        tracing::debug!(
+            temperature = self.temperature,
+            max_tokens = self.max_tokens,
+            top_p = self.top_p,
+            n = self.n,
+            seed = ?self.seed,
+            "Resolved sampling parameters"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_out_of_range_values_are_clamped() {
+        let config = Config::default();
+        let params =
+            SamplingParams::resolve(Some(5.0), Some(1_000_000), Some(-1.0), 2, Some(7), &config);
+        assert_eq!(params.temperature, 2.0);
+        assert_eq!(params.max_tokens, config.max_tokens);
+        assert_eq!(params.top_p, 0.0);
+        assert_eq!(params.seed, Some(7));
+    }
+}
@@ -0,0 +1,133 @@
+//! OpenAI-style `stream: true` responses: the echo is split into deltas and sent as
+//! `chat.completion.chunk` server-sent events, terminated by `data: [DONE]`.
+//! Clients sending `Accept: application/x-ndjson` get one chunk object per line instead.
+
+use std::convert::Infallible;
+
+use axum::{
+    body::Body,
+    http::header,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, Sse},
+    },
+};
+use serde::Serialize;
+
+use crate::ChatCompletionResponse;
+use crate::config::ChunkMode;
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// True when an `Accept` header asks for newline-delimited JSON.
+pub fn accepts_ndjson(accept: &str) -> bool {
+    accept.split(',').any(|part| {
+        let media = part.split(';').next().unwrap_or_default().trim();
+        media.eq_ignore_ascii_case(NDJSON_CONTENT_TYPE)
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: &'static str,
+    pub choices: Vec<ChunkChoice>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkChoice {
+    pub index: usize,
+    pub delta: Delta,
+    pub finish_reason: Option<&'static str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Delta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+/// Split `text` into deltas; concatenating the pieces reproduces the input.
+pub fn split_chunks(text: &str, mode: ChunkMode) -> Vec<String> {
+    match mode {
+        ChunkMode::Word => text.split_inclusive(' ').map(str::to_string).collect(),
+        ChunkMode::Chars(n) => {
+            let chars: Vec<char> = text.chars().collect();
+            chars.chunks(n).map(|c| c.iter().collect()).collect()
+        }
+    }
+}
+
+/// One content chunk per delta for each choice, then a `finish_reason: "stop"` chunk.
+pub fn build_chunks(
+    response: &ChatCompletionResponse,
+    mode: ChunkMode,
+) -> Vec<ChatCompletionChunk> {
+    let chunk = |index, delta, finish_reason| ChatCompletionChunk {
+        id: response.id.clone(),
+        object: "chat.completion.chunk",
+        choices: vec![ChunkChoice {
+            index,
+            delta,
+            finish_reason,
+        }],
+    };
+
+    let mut chunks = Vec::new();
+    for choice in &response.choices {
+        let pieces = split_chunks(&choice.message.content, mode);
+        for (i, piece) in pieces.into_iter().enumerate() {
+            let delta = Delta {
+                role: (i == 0).then(|| choice.message.role.clone()),
+                content: Some(piece),
+            };
+            chunks.push(chunk(choice.index, delta, None));
+        }
+        chunks.push(chunk(choice.index, Delta::default(), Some("stop")));
+    }
+    chunks
+}
+
+pub fn sse_response(response: &ChatCompletionResponse, mode: ChunkMode) -> Response {
+    let events: Vec<Result<Event, axum::Error>> = build_chunks(response, mode)
+        .iter()
+        .map(|chunk| Event::default().json_data(chunk))
+        .chain(std::iter::once(Ok(Event::default().data("[DONE]"))))
+        .collect();
+    Sse::new(futures_util::stream::iter(events)).into_response()
+}
+
+/// The same chunks as [`sse_response`], one JSON object per line and no sentinel.
+pub fn ndjson_response(response: &ChatCompletionResponse, mode: ChunkMode) -> Response {
+    let lines: Vec<Result<String, Infallible>> = build_chunks(response, mode)
+        .iter()
+        .filter_map(|chunk| serde_json::to_string(chunk).ok())
+        .map(|json| Ok(json + "\n"))
+        .collect();
+    (
+        [(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        Body::from_stream(futures_util::stream::iter(lines)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_chunks_modes() {
+        let text = "hello big world";
+        assert_eq!(
+            split_chunks(text, ChunkMode::Word),
+            ["hello ", "big ", "world"]
+        );
+        assert_eq!(split_chunks(text, ChunkMode::Chars(1)).len(), 15);
+        assert_eq!(
+            split_chunks(text, ChunkMode::Chars(4)),
+            ["hell", "o bi", "g wo", "rld"]
+        );
+    }
+}
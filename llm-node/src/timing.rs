@@ -0,0 +1,73 @@
+//! Non-standard `timing` object on chat replies (`LLM_NODE_REPORT_TIMING`).
+//!
+//! `queue_ms` runs from the moment the request reaches the node (before body
+//! decompression and parsing) until generation starts; `generation_ms` covers the
+//! backend call and building the reply. Both are fractional milliseconds.
+
+use std::time::Instant;
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use serde::Serialize;
+
+/// When the request arrived, stamped into its extensions by [`stamp_received`].
+#[derive(Debug, Clone, Copy)]
+pub struct Received(pub Instant);
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct Timing {
+    pub queue_ms: f64,
+    pub generation_ms: f64,
+}
+
+/// Outermost middleware recording the arrival time for [`Timing::queue_ms`].
+pub async fn stamp_received(mut req: Request, next: Next) -> Response {
+    req.extensions_mut().insert(Received(Instant::now()));
+    next.run(req).await
+}
+
+pub fn millis_between(from: Instant, to: Instant) -> f64 {
+    to.saturating_duration_since(from).as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{StatusCode, header};
+    use tower::ServiceExt;
+
+    use crate::config::Config;
+
+    async fn chat_reply(report_timing: bool) -> serde_json::Value {
+        let config = Config {
+            report_timing,
+            ..Config::default()
+        };
+        let req = axum::http::Request::post("/v1/chat/completions")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"model": "m", "messages": [{"role": "user", "content": "hi"}]}"#,
+            ))
+            .unwrap();
+        let resp = crate::app(Arc::new(config)).oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_timing_reported_only_when_enabled() {
+        let reply = chat_reply(true).await;
+        let timing = &reply["timing"];
+        for field in ["queue_ms", "generation_ms"] {
+            let ms = timing[field].as_f64().unwrap_or_else(|| panic!("{reply}"));
+            assert!(ms >= 0.0, "{field} = {ms}");
+        }
+
+        let reply = chat_reply(false).await;
+        assert!(reply.get("timing").is_none(), "{reply}");
+    }
+}
@@ -0,0 +1,78 @@
+//! Stub tokenizer behind `usage` in chat replies and `POST /v1/tokenize`.
+//!
+//! Words and individual punctuation marks count as one token each, plus a fixed
+//! overhead per message for the role and separators. A real engine would use the
+//! model's own tokenizer; both endpoints only need to agree with each other.
+
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::ChatMessage;
+
+/// Tokens each message costs beyond its content (role and delimiters).
+const MESSAGE_OVERHEAD: usize = 3;
+
+pub fn count(text: &str) -> usize {
+    text.split_whitespace()
+        .map(|word| {
+            let punctuation = word.chars().filter(|c| c.is_ascii_punctuation()).count();
+            let has_word = word.chars().any(|c| !c.is_ascii_punctuation());
+            punctuation + usize::from(has_word)
+        })
+        .sum()
+}
+
+pub fn prompt_tokens(messages: &[ChatMessage]) -> usize {
+    messages
+        .iter()
+        .map(|m| MESSAGE_OVERHEAD + count(&m.content))
+        .sum()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub total_tokens: usize,
+}
+
+impl Usage {
+    pub fn new(prompt_tokens: usize, completion_tokens: usize) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenizeRequest {
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenizeResponse {
+    pub prompt_tokens: usize,
+}
+
+/// `POST /v1/tokenize`: the prompt tokens a chat request would be charged, without
+/// generating.
+pub async fn tokenize_handler(Json(req): Json<TokenizeRequest>) -> Json<TokenizeResponse> {
+    Json(TokenizeResponse {
+        prompt_tokens: prompt_tokens(&req.messages),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_and_punctuation_counted() {
+        assert_eq!(count("Hello, world!"), 4);
+        assert_eq!(count("  spaced   out "), 2);
+        assert_eq!(count("..."), 3);
+        assert_eq!(count(""), 0);
+    }
+}
@@ -0,0 +1,5 @@
+//! Embed the git commit and build time for the `/version` endpoint.
+
+fn main() {
+    common::version::emit_build_env();
+}
@@ -0,0 +1,139 @@
+//! Runtime configuration for tts-node, read once from the environment at startup.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+
+use crate::pitch::VoicePitches;
+use crate::resample::Resampler;
+
+/// What to do with a synthesis request when all slots are busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Wait for a slot to free up.
+    Queue,
+    /// Fail the request with `503`.
+    Reject,
+}
+
+impl FromStr for OverloadPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "queue" => Ok(Self::Queue),
+            "reject" => Ok(Self::Reject),
+            other => bail!("unknown overload policy {other:?}; expected 'queue' or 'reject'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Syntheses allowed to run at once, from `TTS_NODE_MAX_CONCURRENCY`.
+    pub max_concurrency: usize,
+    /// Handling of requests beyond the limit, from `TTS_NODE_OVERLOAD_POLICY`.
+    pub overload_policy: OverloadPolicy,
+    /// Strip surrounding whitespace from `input` before synthesis, from `TTS_NODE_TRIM_INPUT`.
+    pub trim_input: bool,
+    /// Silence appended after synthesized audio when a request doesn't set
+    /// `trailing_silence_ms`, from `TTS_NODE_TRAILING_SILENCE_MS`.
+    pub trailing_silence_ms: u32,
+    /// Interpolation for requests whose `sample_rate` differs from the synthesis rate,
+    /// from `TTS_NODE_RESAMPLER`.
+    pub resampler: Resampler,
+    /// Default pitch shift per voice, from `TTS_NODE_VOICE_PITCH`.
+    pub voice_pitch: VoicePitches,
+    /// Longest a buffered synthesis may run before the request fails with `504`,
+    /// from `TTS_SYNTHESIS_TIMEOUT_MS`.
+    pub synthesis_timeout: Duration,
+    /// Run one throwaway synthesis before serving, from `TTS_WARMUP`.
+    pub warmup: bool,
+    /// Idle sample buffers kept for reuse across requests, from `TTS_NODE_BUFFER_POOL`;
+    /// `0` allocates per request.
+    pub buffer_pool: usize,
+    /// Add an approximate `X-Audio-Lufs` header to buffered audio, from
+    /// `TTS_NODE_REPORT_LUFS`.
+    pub report_loudness: bool,
+    /// Bearer token for `/admin/drain`, from `NODE_ADMIN_TOKEN`; unset refuses admin calls.
+    pub admin_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 4,
+            overload_policy: OverloadPolicy::Queue,
+            trim_input: true,
+            trailing_silence_ms: 0,
+            resampler: Resampler::Sinc,
+            voice_pitch: VoicePitches::default(),
+            synthesis_timeout: Duration::from_secs(30),
+            warmup: false,
+            buffer_pool: 0,
+            report_loudness: true,
+            admin_token: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
+        let config = Self {
+            max_concurrency: env_or("TTS_NODE_MAX_CONCURRENCY", defaults.max_concurrency)?,
+            overload_policy: env_or("TTS_NODE_OVERLOAD_POLICY", defaults.overload_policy)?,
+            trim_input: env_or("TTS_NODE_TRIM_INPUT", defaults.trim_input)?,
+            trailing_silence_ms: env_or(
+                "TTS_NODE_TRAILING_SILENCE_MS",
+                defaults.trailing_silence_ms,
+            )?,
+            resampler: env_or("TTS_NODE_RESAMPLER", defaults.resampler)?,
+            voice_pitch: env_or("TTS_NODE_VOICE_PITCH", defaults.voice_pitch)?,
+            synthesis_timeout: env_or(
+                "TTS_SYNTHESIS_TIMEOUT_MS",
+                defaults.synthesis_timeout.as_millis() as u64,
+            )
+            .map(Duration::from_millis)?,
+            warmup: env_flag("TTS_WARMUP", defaults.warmup)?,
+            buffer_pool: env_or("TTS_NODE_BUFFER_POOL", defaults.buffer_pool)?,
+            report_loudness: env_flag("TTS_NODE_REPORT_LUFS", defaults.report_loudness)?,
+            admin_token: std::env::var("NODE_ADMIN_TOKEN").ok(),
+        };
+        if config.max_concurrency == 0 {
+            bail!("TTS_NODE_MAX_CONCURRENCY must be at least 1");
+        }
+        if config.trailing_silence_ms > crate::MAX_TRAILING_SILENCE_MS {
+            bail!(
+                "TTS_NODE_TRAILING_SILENCE_MS must be at most {}",
+                crate::MAX_TRAILING_SILENCE_MS
+            );
+        }
+        Ok(config)
+    }
+}
+
+fn env_or<T>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    match std::env::var(key) {
+        Ok(raw) => raw
+            .trim()
+            .parse::<T>()
+            .map_err(Into::<anyhow::Error>::into)
+            .with_context(|| format!("invalid {key}={raw:?}")),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Like [`env_or`] for booleans, also accepting `1`/`0`.
+fn env_flag(key: &str, default: bool) -> anyhow::Result<bool> {
+    match std::env::var(key).as_deref().map(str::trim) {
+        Ok("1") => Ok(true),
+        Ok("0") => Ok(false),
+        _ => env_or(key, default),
+    }
+}
@@ -0,0 +1,65 @@
+//! Triangular (TPDF) dither for reducing 16-bit samples to a lower bit depth.
+//!
+//! Noise spanning ±1 step of the target depth is added before the encoder rounds, which
+//! decorrelates quantization error from the signal: a low, even hiss instead of the
+//! harmonic distortion plain rounding gives quiet passages at 8 bits.
+
+/// Seed used for rendered audio without a request `seed`, so identical requests
+/// produce identical bytes.
+pub const SEED: u64 = 0x5EED_D17E;
+
+/// xorshift64; plenty for noise shaping and seedable for reproducible output.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // The all-zero state is a fixed point
+        Self(seed.max(1))
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        (x >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Add TPDF noise sized for quantizing `samples` down to `target_bits`.
+pub fn apply_tpdf(samples: &mut [i16], target_bits: u16, seed: u64) {
+    let step = f32::from(1u16 << (16 - target_bits.clamp(1, 16)).min(15));
+    let mut rng = XorShift::new(seed);
+    for sample in samples {
+        let noise = (rng.unit() - rng.unit()) * step;
+        *sample = (f32::from(*sample) + noise)
+            .round()
+            .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dither_is_deterministic_per_seed() {
+        let input: Vec<i16> = (0..1000).map(|i| (i * 37 % 2000) as i16 - 1000).collect();
+        let dithered = |seed| {
+            let mut samples = input.clone();
+            apply_tpdf(&mut samples, 8, seed);
+            samples
+        };
+
+        let first = dithered(7);
+        assert_eq!(first, dithered(7));
+        assert_ne!(first, dithered(8));
+
+        let changed = first.iter().zip(&input).filter(|(a, b)| a != b).count();
+        assert!(changed > input.len() / 2, "only {changed} samples changed");
+        // Noise stays within one 8-bit step (256 in 16-bit units)
+        assert!(first.iter().zip(&input).all(|(a, b)| a.abs_diff(*b) < 256));
+    }
+}
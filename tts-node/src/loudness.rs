@@ -0,0 +1,76 @@
+//! Approximate integrated loudness of rendered audio, reported as `X-Audio-Lufs`.
+//!
+//! Follows the BS.1770 block structure without its K-weighting filter: mean square
+//! over 400 ms blocks overlapping by 75%, an absolute gate at -70 LUFS, then a relative
+//! gate 10 LU below the mean of what passed. Unfiltered, speech reads within a couple
+//! of LU of a true meter, which is enough for clients deciding whether to apply gain.
+
+const BLOCK_SECS: f64 = 0.4;
+const BLOCK_STEP: f64 = 0.25;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = 10.0;
+
+fn to_lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// Integrated loudness of mono `samples`, or `None` when everything is below the
+/// absolute gate (e.g. silence).
+pub fn integrated_lufs(samples: &[i16], sample_rate: u32) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    let block = ((sample_rate as f64 * BLOCK_SECS) as usize).clamp(1, samples.len());
+    let step = ((block as f64 * BLOCK_STEP) as usize).max(1);
+    let blocks: Vec<f64> = (0..=samples.len() - block)
+        .step_by(step)
+        .map(|start| {
+            let sum: f64 = samples[start..start + block]
+                .iter()
+                .map(|&s| (f64::from(s) / 32768.0).powi(2))
+                .sum();
+            sum / block as f64
+        })
+        .filter(|&ms| ms > 0.0 && to_lufs(ms) > ABSOLUTE_GATE_LUFS)
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+    let ungated = blocks.iter().sum::<f64>() / blocks.len() as f64;
+    let threshold = to_lufs(ungated) - RELATIVE_GATE_LU;
+    let gated: Vec<f64> = blocks
+        .into_iter()
+        .filter(|&ms| to_lufs(ms) > threshold)
+        .collect();
+    Some(to_lufs(gated.iter().sum::<f64>() / gated.len() as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f64, secs: f64) -> Vec<i16> {
+        (0..(44100.0 * secs) as usize)
+            .map(|n| {
+                let phase = 2.0 * std::f64::consts::PI * 440.0 * n as f64 / 44100.0;
+                (phase.sin() * amplitude * 32767.0) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_louder_clip_reports_higher_lufs() {
+        let loud = integrated_lufs(&sine(1.0, 1.0), 44100).unwrap();
+        let quiet = integrated_lufs(&sine(0.1, 1.0), 44100).unwrap();
+        // A full-scale sine has a mean square of 0.5: about -3.7 LUFS unweighted
+        assert!((loud + 3.7).abs() < 0.1, "{loud}");
+        assert!((loud - quiet - 20.0).abs() < 0.1, "{loud} vs {quiet}");
+
+        assert_eq!(integrated_lufs(&[0; 44100], 44100), None);
+        // Trailing silence is gated out rather than dragging the figure down
+        let mut padded = sine(1.0, 1.0);
+        padded.extend([0; 44100]);
+        let padded = integrated_lufs(&padded, 44100).unwrap();
+        assert!((padded - loud).abs() < 1.0, "{padded} vs {loud}");
+    }
+}
@@ -1,40 +1,423 @@
-//! Minimal TTS stub that returns a 1-second 440Hz tone as WAV.
+//! Minimal TTS stub that returns a 1-second 440Hz tone as WAV or raw PCM,
+//! or streams a short tone per sentence.
 //! This is just a placeholder to prove the wiring; swap in Piper/Kokoro later.
 
+mod config;
+mod dither;
+mod loudness;
+mod phonemes;
+mod pitch;
+mod pool;
+mod preamble;
+mod resample;
+mod stream;
+mod wav;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use axum::{
     Json, Router,
     body::Body,
-    http::{StatusCode, header},
+    extract::State,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
 };
 use serde::Deserialize;
 use tokio::net::TcpListener;
-use tracing::{Level, info};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{Level, info, warn};
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::config::{Config, OverloadPolicy};
+use crate::phonemes::{InputType, MAX_PHONEMES};
+use crate::pool::SamplePool;
+use crate::preamble::Preamble;
+use crate::resample::{Resampler, resample};
+use crate::wav::{ByteOrder, WavSpec, downmix, encode_raw, encode_wav};
 
-#[derive(Debug, Deserialize)]
+/// Native synthesis rate; other requested rates are resampled from it.
+const SAMPLE_RATE: u32 = 44100;
+/// Channels the synthesizer produces; more than the output's are downmixed to mono.
+const SYNTH_CHANNELS: u16 = 1;
+/// The stub voice's tone before any pitch shift.
+const BASE_FREQ_HZ: f32 = 440.0;
+/// Stub audio length for text input, whatever its length.
+const TEXT_SECS: f32 = 1.0;
+/// Range of `sample_rate` a request may ask for.
+const MIN_SAMPLE_RATE: u32 = 8000;
+const MAX_SAMPLE_RATE: u32 = 192_000;
+/// Most `trailing_silence_ms` a request (or `TTS_NODE_TRAILING_SILENCE_MS`) may ask for.
+const MAX_TRAILING_SILENCE_MS: u32 = 10_000;
+/// Approximate integrated loudness of buffered audio; see [`loudness`].
+const LUFS_HEADER: &str = "x-audio-lufs";
+
+#[derive(Debug, Default, Deserialize)]
 struct TtsRequest {
     input: String,
+    /// `"text"` (default) or `"phonemes"`, which takes `input` as a whitespace-separated
+    /// ARPAbet or IPA string and skips text preparation.
+    #[serde(default)]
+    input_type: InputType,
     voice: Option<String>,
     format: Option<String>,
+    /// Tags embedded in a WAV `LIST/INFO` chunk; see `wav::INFO_TAGS` for supported keys.
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    /// Output amplitude scale, clamped to 0.0–1.0 (default full scale).
+    gain: Option<f32>,
+    /// Stream raw PCM one sentence at a time instead of a single buffered file.
+    stream: Option<bool>,
+    /// Write a `WAVE_FORMAT_EXTENSIBLE` header even for mono output.
+    extensible: Option<bool>,
+    /// Milliseconds of silence appended so players don't clip the final phoneme
+    /// (defaults to `TTS_NODE_TRAILING_SILENCE_MS`).
+    trailing_silence_ms: Option<u32>,
+    /// Output bit depth: 16 (default), 8, or `"f32"` for 32-bit float WAV.
+    bit_depth: Option<BitDepth>,
+    /// Dither before reducing the bit depth (default true; no effect at 16 bits).
+    dither: Option<bool>,
+    /// Seeds every random source in synthesis (currently only dither), so the same
+    /// request and seed give identical bytes. Output without randomness ignores it.
+    seed: Option<u64>,
+    /// Byte order of 16-bit raw PCM: `"le"` (default) or `"be"`. WAV is always
+    /// little-endian, so `"be"` is rejected for it.
+    #[serde(default)]
+    byte_order: ByteOrder,
+    /// Output rate in Hz, resampled from the synthesis rate when different.
+    sample_rate: Option<u32>,
+    /// Shift in semitones, clamped to ±24 (defaults to the voice's `TTS_NODE_VOICE_PITCH`).
+    pitch: Option<f32>,
+    /// `"beep"` plays a short alert tone before the speech (default `"none"`).
+    #[serde(default)]
+    preamble: Preamble,
+}
+
+/// What the stub synthesizes: `duration_secs` of sine at `freq_hz` scaled by `gain`,
+/// after the `preamble` if any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tone {
+    freq_hz: f32,
+    gain: f32,
+    duration_secs: f32,
+    preamble: Preamble,
 }
 
-async fn tts_handler(Json(req): Json<TtsRequest>) -> Response {
+impl Tone {
+    fn for_request(req: &TtsRequest, config: &Config) -> Self {
+        let voice = req.voice.as_deref().unwrap_or("default");
+        let semitones = req.pitch.unwrap_or_else(|| config.voice_pitch.get(voice));
+        let duration_secs = match req.input_type {
+            InputType::Text => TEXT_SECS,
+            InputType::Phonemes => phonemes::duration_secs(phonemes::split(&req.input).len()),
+        };
+        Self {
+            freq_hz: pitch::shift(BASE_FREQ_HZ, semitones),
+            gain: req.gain.unwrap_or(1.0),
+            duration_secs,
+            preamble: req.preamble,
+        }
+    }
+}
+
+/// `bit_depth` as sent: a number, or a name such as `"f32"`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum BitDepth {
+    Bits(u16),
+    Named(String),
+}
+
+impl BitDepth {
+    /// The WAV bits per sample, where 32 means IEEE float; `None` if unsupported.
+    fn bits_per_sample(&self) -> Option<u16> {
+        match self {
+            Self::Bits(bits @ (8 | 16)) => Some(*bits),
+            Self::Named(name) if name == "f32" => Some(32),
+            Self::Named(name) => name.parse().ok().filter(|bits| [8, 16].contains(bits)),
+            Self::Bits(_) => None,
+        }
+    }
+}
+
+/// How buffered audio is produced and finished after synthesis.
+#[derive(Clone)]
+struct RenderOptions {
+    trailing_silence_ms: u32,
+    dither: bool,
+    /// Seed for the dither noise.
+    seed: u64,
+    /// Report the rendered audio's loudness in `X-Audio-Lufs`.
+    report_loudness: bool,
+    /// Byte order of raw PCM output.
+    byte_order: ByteOrder,
+    resampler: Resampler,
+    /// Recycle sample buffers across requests instead of allocating each time.
+    pool: Option<Arc<SamplePool>>,
+}
+
+impl RenderOptions {
+    fn take_buffer(&self) -> Vec<i16> {
+        self.pool.as_ref().map_or_else(Vec::new, |pool| pool.take())
+    }
+
+    fn recycle(&self, buffer: Vec<i16>) {
+        if let Some(pool) = &self.pool {
+            pool.give(buffer);
+        }
+    }
+}
+
+/// Shared handler state: config, one permit per allowed concurrent synthesis, and the
+/// sample buffer pool when enabled.
+struct AppState {
+    config: Config,
+    synth_slots: Arc<Semaphore>,
+    pool: Option<Arc<SamplePool>>,
+}
+
+impl AppState {
+    fn new(config: Config) -> Self {
+        let synth_slots = Arc::new(Semaphore::new(config.max_concurrency));
+        let pool = (config.buffer_pool > 0).then(|| Arc::new(SamplePool::new(config.buffer_pool)));
+        Self {
+            config,
+            synth_slots,
+            pool,
+        }
+    }
+
+    /// Take a synthesis slot, waiting or giving up according to the overload policy.
+    async fn acquire_slot(&self) -> Option<OwnedSemaphorePermit> {
+        let slots = self.synth_slots.clone();
+        match self.config.overload_policy {
+            OverloadPolicy::Queue => slots.acquire_owned().await.ok(),
+            OverloadPolicy::Reject => slots.try_acquire_owned().ok(),
+        }
+    }
+}
+
+async fn tts_handler(State(state): State<Arc<AppState>>, Json(req): Json<TtsRequest>) -> Response {
+    let Some(slot) = state.acquire_slot().await else {
+        warn!("TTS request rejected: all synthesis slots busy");
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "TTS node at capacity; retry later",
+        )
+            .into_response();
+    };
+
     let format = req.format.as_deref().unwrap_or("wav");
     let voice = req.voice.as_deref().unwrap_or("default");
+    let input = synthesis_input(&req, state.config.trim_input);
 
     info!(
-        "TTS request: {} chars, voice={}, format={}",
-        req.input.len(),
+        "TTS request: {} chars ({:?}), voice={}, format={}",
+        input.len(),
+        req.input_type,
         voice,
         format
     );
 
+    if req.input_type == InputType::Phonemes && phonemes::split(&req.input).len() > MAX_PHONEMES {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("input has more than {MAX_PHONEMES} phonemes"),
+        )
+            .into_response();
+    }
+    let tone = Tone::for_request(&req, &state.config);
+    if req.byte_order == ByteOrder::Be && format == "wav" && req.stream != Some(true) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "byte_order \"be\" is only available for pcm; WAV data is little-endian",
+        )
+            .into_response();
+    }
+    if req.stream == Some(true) && req.input_type == InputType::Phonemes {
+        return (
+            StatusCode::BAD_REQUEST,
+            "stream is only available for input_type \"text\"; phonemes have no sentences",
+        )
+            .into_response();
+    }
+    if req.stream == Some(true) {
+        let buffered_only = [
+            ("sample_rate", req.sample_rate.is_some()),
+            ("bit_depth", req.bit_depth.is_some()),
+            ("trailing_silence_ms", req.trailing_silence_ms.is_some()),
+            ("dither", req.dither.is_some()),
+        ];
+        if let Some((field, _)) = buffered_only.iter().find(|(_, set)| *set) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{field} is not available with stream; streamed audio is 16-bit PCM at {SAMPLE_RATE} Hz"),
+            )
+                .into_response();
+        }
+        return stream::sentence_response(input, tone, req.byte_order, slot);
+    }
+    let Some(bits_per_sample) = req
+        .bit_depth
+        .as_ref()
+        .map_or(Some(16), BitDepth::bits_per_sample)
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Unsupported bit_depth; expected 8, 16 or \"f32\"",
+        )
+            .into_response();
+    };
+    let sample_rate = req.sample_rate.unwrap_or(SAMPLE_RATE);
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported sample_rate; expected {MIN_SAMPLE_RATE}-{MAX_SAMPLE_RATE} Hz"),
+        )
+            .into_response();
+    }
+    let trailing_silence_ms = req
+        .trailing_silence_ms
+        .unwrap_or(state.config.trailing_silence_ms);
+    if trailing_silence_ms > MAX_TRAILING_SILENCE_MS {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("trailing_silence_ms must be at most {MAX_TRAILING_SILENCE_MS}"),
+        )
+            .into_response();
+    }
+    let spec = WavSpec {
+        bits_per_sample,
+        extensible: req.extensible.unwrap_or(false),
+        ..WavSpec::mono(sample_rate)
+    };
+    let options = RenderOptions {
+        trailing_silence_ms,
+        dither: req.dither.unwrap_or(true),
+        seed: req.seed.unwrap_or(dither::SEED),
+        report_loudness: state.config.report_loudness,
+        byte_order: req.byte_order,
+        resampler: state.config.resampler,
+        pool: state.pool.clone(),
+    };
+    let format = format.to_string();
+    let metadata = req.metadata;
+    run_synthesis(state.config.synthesis_timeout, move || {
+        let _slot = slot;
+        render_audio(&format, tone, &options, &spec, &metadata)
+    })
+    .await
+}
+
+/// Run `synthesize` on the blocking pool, answering `504` if it outlasts `budget`.
+///
+/// A timed-out synthesis can't be interrupted; it finishes in the background, still
+/// holding its slot, and its output is dropped.
+async fn run_synthesis<F>(budget: Duration, synthesize: F) -> Response
+where
+    F: FnOnce() -> Response + Send + 'static,
+{
+    let error = |status: StatusCode, error: String| {
+        (status, Json(serde_json::json!({ "error": error }))).into_response()
+    };
+    match tokio::time::timeout(budget, tokio::task::spawn_blocking(synthesize)).await {
+        Ok(Ok(resp)) => resp,
+        Ok(Err(e)) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("synthesis failed: {e}"),
+        ),
+        Err(_) => {
+            let millis = budget.as_millis();
+            warn!("TTS synthesis exceeded {millis}ms");
+            error(
+                StatusCode::GATEWAY_TIMEOUT,
+                format!("synthesis exceeded the {millis}ms budget"),
+            )
+        }
+    }
+}
+
+/// The input handed to synthesis: prepared text, or phonemes exactly as sent.
+fn synthesis_input(req: &TtsRequest, trim: bool) -> &str {
+    match req.input_type {
+        InputType::Text => prepare_input(&req.input, trim),
+        InputType::Phonemes => &req.input,
+    }
+}
+
+/// Strip leading/trailing whitespace that would otherwise be spoken as pauses.
+fn prepare_input(input: &str, trim: bool) -> &str {
+    if !trim {
+        return input;
+    }
+    let trimmed = input.trim();
+    if trimmed.len() != input.len() {
+        info!(
+            "Trimmed TTS input from {} to {} chars",
+            input.len(),
+            trimmed.len()
+        );
+    }
+    trimmed
+}
+
+fn render_audio(
+    format: &str,
+    tone: Tone,
+    options: &RenderOptions,
+    spec: &WavSpec,
+    metadata: &HashMap<String, String>,
+) -> Response {
+    // Stub: generate tone regardless of input text
+    // Real implementation would synthesize the input with the voice
+    let mut native = options.take_buffer();
+    native.extend(tone.preamble.samples(SAMPLE_RATE));
+    synthesize_sine_into(
+        &mut native,
+        tone.freq_hz,
+        tone.duration_secs,
+        tone.gain,
+        SAMPLE_RATE,
+    );
+    if spec.channels == 1 {
+        native = downmix(native, SYNTH_CHANNELS);
+    }
+    let mut samples = if spec.sample_rate == SAMPLE_RATE {
+        native
+    } else {
+        let resampled = resample(&native, SAMPLE_RATE, spec.sample_rate, options.resampler);
+        options.recycle(native);
+        resampled
+    };
+    append_silence(&mut samples, options.trailing_silence_ms, spec);
+    if options.dither && spec.bits_per_sample < 16 {
+        dither::apply_tpdf(&mut samples, spec.bits_per_sample, options.seed);
+    }
+    let mut resp = encode_audio(format, &samples, spec, options.byte_order, metadata);
+    let lufs = options
+        .report_loudness
+        .then(|| loudness::integrated_lufs(&samples, spec.sample_rate))
+        .flatten();
+    if let Some(lufs) = lufs.filter(|_| resp.status().is_success()) {
+        let value = HeaderValue::from_str(&format!("{lufs:.1}")).expect("numeric header");
+        resp.headers_mut().insert(LUFS_HEADER, value);
+    }
+    options.recycle(samples);
+    resp
+}
+
+fn encode_audio(
+    format: &str,
+    samples: &[i16],
+    spec: &WavSpec,
+    byte_order: ByteOrder,
+    metadata: &HashMap<String, String>,
+) -> Response {
     match format {
         "wav" => {
-            // Stub: generate tone regardless of input text
-            // Real implementation would synthesize req.input with req.voice
-            let bytes = generate_sine_wav(440.0, 1.0);
+            let bytes = encode_wav(samples, spec, metadata);
             (
                 StatusCode::OK,
                 [(header::CONTENT_TYPE, "audio/wav")],
@@ -42,66 +425,131 @@ async fn tts_handler(Json(req): Json<TtsRequest>) -> Response {
             )
                 .into_response()
         }
+        "pcm" if spec.is_float() => (
+            StatusCode::BAD_REQUEST,
+            "bit_depth \"f32\" is only available as wav",
+        )
+            .into_response(),
+        "pcm" => {
+            // Raw headerless samples for DSP consumers (L8 is unsigned, L16 little-endian
+            // unless big-endian was requested)
+            let bytes = encode_raw(samples, spec.bits_per_sample, byte_order);
+            let bits = spec.bits_per_sample;
+            let rate = spec.sample_rate;
+            let content_type = format!("audio/L{bits}; rate={rate}; channels=1");
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, content_type)],
+                Body::from(bytes),
+            )
+                .into_response()
+        }
         _ => (
             StatusCode::BAD_REQUEST,
-            "Unsupported format; only 'wav' is implemented",
+            "Unsupported format; expected 'wav' or 'pcm'",
         )
             .into_response(),
     }
 }
 
-fn generate_sine_wav(freq_hz: f32, duration_secs: f32) -> Vec<u8> {
-    let sample_rate = 44100u32;
+/// `gain` scales the peak amplitude; it is clamped to 0.0–1.0 so samples never clip.
+fn synthesize_sine(freq_hz: f32, duration_secs: f32, gain: f32, sample_rate: u32) -> Vec<i16> {
+    let mut samples = Vec::new();
+    synthesize_sine_into(&mut samples, freq_hz, duration_secs, gain, sample_rate);
+    samples
+}
+
+/// [`synthesize_sine`], appending to `out`.
+fn synthesize_sine_into(
+    out: &mut Vec<i16>,
+    freq_hz: f32,
+    duration_secs: f32,
+    gain: f32,
+    sample_rate: u32,
+) {
     let num_samples = (sample_rate as f32 * duration_secs) as u32;
-    let amplitude = i16::MAX as f32;
+    let amplitude = i16::MAX as f32 * gain.clamp(0.0, 1.0);
 
-    let mut data = Vec::with_capacity((num_samples * 2) as usize);
-    for n in 0..num_samples {
+    out.extend((0..num_samples).map(|n| {
         let t = n as f32 / sample_rate as f32;
         let sample = (2.0 * std::f32::consts::PI * freq_hz * t).sin();
-        let v = (sample * amplitude) as i16;
-        data.extend_from_slice(&v.to_le_bytes());
-    }
-
-    // Build simple PCM WAV header (mono, 16-bit)
-    let mut wav = Vec::new();
-    let byte_rate = sample_rate * 2;
-    let block_align = 2u16;
-    let bits_per_sample = 16u16;
-    let subchunk2_size = num_samples * 2;
-    let chunk_size = 36 + subchunk2_size;
+        (sample * amplitude) as i16
+    }));
+}
 
-    // RIFF header
-    wav.extend_from_slice(b"RIFF");
-    wav.extend_from_slice(&chunk_size.to_le_bytes());
-    wav.extend_from_slice(b"WAVE");
+/// Pad interleaved `samples` with `millis` of zeros across every channel.
+fn append_silence(samples: &mut Vec<i16>, millis: u32, spec: &WavSpec) {
+    let frames = u64::from(spec.sample_rate) * u64::from(millis) / 1000;
+    let len = samples.len() + frames as usize * usize::from(spec.channels);
+    samples.resize(len, 0);
+}
 
-    // fmt subchunk
-    wav.extend_from_slice(b"fmt ");
-    wav.extend_from_slice(&16u32.to_le_bytes()); // Subchunk1Size for PCM
-    wav.extend_from_slice(&1u16.to_le_bytes()); // AudioFormat = PCM
-    wav.extend_from_slice(&1u16.to_le_bytes()); // NumChannels = 1
-    wav.extend_from_slice(&sample_rate.to_le_bytes());
-    wav.extend_from_slice(&byte_rate.to_le_bytes());
-    wav.extend_from_slice(&block_align.to_le_bytes());
-    wav.extend_from_slice(&bits_per_sample.to_le_bytes());
+/// Run one throwaway synthesis so the first real request doesn't pay for cold caches
+/// and allocations, returning how long it took.
+fn warmup(config: &Config) -> anyhow::Result<Duration> {
+    let started = Instant::now();
+    let req = TtsRequest {
+        input: "Warmup.".into(),
+        ..Default::default()
+    };
+    let options = RenderOptions {
+        trailing_silence_ms: config.trailing_silence_ms,
+        dither: true,
+        seed: dither::SEED,
+        report_loudness: false,
+        byte_order: ByteOrder::Le,
+        resampler: config.resampler,
+        pool: None,
+    };
+    let resp = render_audio(
+        "wav",
+        Tone::for_request(&req, config),
+        &options,
+        &WavSpec::mono(SAMPLE_RATE),
+        &req.metadata,
+    );
+    if resp.status() != StatusCode::OK {
+        anyhow::bail!("warmup synthesis failed with {}", resp.status());
+    }
+    Ok(started.elapsed())
+}
 
-    // data subchunk
-    wav.extend_from_slice(b"data");
-    wav.extend_from_slice(&subchunk2_size.to_le_bytes());
-    wav.extend_from_slice(&data);
+async fn version_handler() -> Json<common::version::VersionInfo> {
+    Json(common::version_info!())
+}
 
-    wav
+fn app(config: Config) -> Router {
+    let router = Router::new()
+        .route("/v1/audio/speech", post(tts_handler))
+        .route("/version", get(version_handler))
+        .layer(axum::middleware::from_fn(common::gzip::decompress_request));
+    #[cfg(feature = "otel")]
+    let router = router.layer(axum::middleware::from_fn(common::otel::trace_request));
+    let drain = common::drain::routes(config.admin_token.clone());
+    router
+        .with_state(Arc::new(AppState::new(config)))
+        .merge(drain)
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
+    let subscriber = tracing_subscriber::fmt()
         .with_max_level(Level::INFO)
         .with_env_filter("tts_node=info,axum=info")
-        .init();
+        .finish();
+    #[cfg(feature = "otel")]
+    let subscriber = tracing_subscriber::layer::SubscriberExt::with(
+        subscriber,
+        common::otel::layer_from_env("tts-node"),
+    );
+    subscriber.init();
 
-    let app = Router::new().route("/v1/audio/speech", post(tts_handler));
+    let config = Config::from_env()?;
+    if config.warmup {
+        let took = warmup(&config)?;
+        info!("Warmup synthesis finished in {took:?}");
+    }
+    let app = app(config);
 
     let listener = TcpListener::bind("0.0.0.0:9001").await?;
     info!("tts-node listening on {}", listener.local_addr()?);
@@ -114,9 +562,17 @@ async fn main() -> anyhow::Result<()> {
 mod tests {
     use super::*;
 
+    fn generate_sine_wav(spec: &WavSpec) -> Vec<u8> {
+        encode_wav(
+            &synthesize_sine(440.0, 1.0, 1.0, spec.sample_rate),
+            spec,
+            &HashMap::new(),
+        )
+    }
+
     #[test]
     fn test_wav_header_valid() {
-        let wav = generate_sine_wav(440.0, 1.0);
+        let wav = generate_sine_wav(&WavSpec::mono(SAMPLE_RATE));
         assert_eq!(&wav[0..4], b"RIFF");
         assert_eq!(&wav[8..12], b"WAVE");
         assert_eq!(&wav[12..16], b"fmt ");
@@ -124,9 +580,483 @@ mod tests {
 
     #[test]
     fn test_wav_correct_size() {
-        let wav = generate_sine_wav(440.0, 1.0);
+        let wav = generate_sine_wav(&WavSpec::mono(SAMPLE_RATE));
         // 44100 samples * 2 bytes + 44 byte header
         let expected_size = 44100 * 2 + 44;
         assert_eq!(wav.len(), expected_size);
     }
+
+    #[tokio::test]
+    async fn test_pcm_format_is_headerless() {
+        let req = TtsRequest {
+            input: "hello".into(),
+            format: Some("pcm".into()),
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(Config::default()));
+        let resp = tts_handler(State(state), Json(req)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "audio/L16; rate=44100; channels=1"
+        );
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let num_samples = SAMPLE_RATE as usize;
+        assert_eq!(body.len(), num_samples * 2);
+        assert_ne!(&body[0..4], b"RIFF");
+    }
+
+    #[tokio::test]
+    async fn test_trailing_silence_appends_zero_samples() {
+        let req = TtsRequest {
+            input: "hello".into(),
+            format: Some("pcm".into()),
+            trailing_silence_ms: Some(250),
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(Config::default()));
+        let resp = tts_handler(State(state), Json(req)).await;
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let tone_bytes = SAMPLE_RATE as usize * 2;
+        let silence_bytes = SAMPLE_RATE as usize / 4 * 2;
+        assert_eq!(body.len(), tone_bytes + silence_bytes);
+        assert!(body[tone_bytes..].iter().all(|&b| b == 0));
+        assert!(body[..tone_bytes].iter().any(|&b| b != 0));
+    }
+
+    #[tokio::test]
+    async fn test_trailing_silence_over_cap_rejected() {
+        let req = TtsRequest {
+            input: "hello".into(),
+            format: Some("pcm".into()),
+            sample_rate: Some(MAX_SAMPLE_RATE),
+            trailing_silence_ms: Some(4_000_000_000),
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(Config::default()));
+        let resp = tts_handler(State(state), Json(req)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_eight_bit_pcm_is_unsigned_and_dithered() {
+        let render = |dither| {
+            let req = TtsRequest {
+                input: "hello".into(),
+                format: Some("pcm".into()),
+                bit_depth: Some(BitDepth::Bits(8)),
+                dither: Some(dither),
+                ..Default::default()
+            };
+            tts_handler(State(Arc::new(AppState::new(Config::default()))), Json(req))
+        };
+        let plain = render(false).await;
+        assert_eq!(
+            plain.headers()[header::CONTENT_TYPE],
+            "audio/L8; rate=44100; channels=1"
+        );
+        let plain = axum::body::to_bytes(plain.into_body(), usize::MAX).await;
+        let dithered = axum::body::to_bytes(render(true).await.into_body(), usize::MAX).await;
+        let (plain, dithered) = (plain.unwrap(), dithered.unwrap());
+        assert_eq!(plain.len(), SAMPLE_RATE as usize);
+        assert_eq!(plain[0], 128); // silence sits at the 128 offset
+        assert_eq!(dithered.len(), plain.len());
+        assert_ne!(dithered, plain);
+    }
+
+    #[tokio::test]
+    async fn test_louder_speech_reports_higher_lufs() {
+        let lufs = |gain| async move {
+            let req = TtsRequest {
+                input: "hello".into(),
+                gain: Some(gain),
+                ..Default::default()
+            };
+            let state = Arc::new(AppState::new(Config::default()));
+            let resp = tts_handler(State(state), Json(req)).await;
+            let value = resp.headers()[LUFS_HEADER].to_str().unwrap();
+            value.parse::<f64>().unwrap()
+        };
+        let (loud, quiet) = (lufs(1.0).await, lufs(0.25).await);
+        assert!(loud > quiet + 10.0, "{loud} vs {quiet}");
+    }
+
+    #[tokio::test]
+    async fn test_raw_pcm_honors_byte_order() {
+        let render = |format: &str, byte_order| {
+            let req = TtsRequest {
+                input: "hello".into(),
+                format: Some(format.into()),
+                byte_order,
+                ..Default::default()
+            };
+            tts_handler(State(Arc::new(AppState::new(Config::default()))), Json(req))
+        };
+        let body = |resp: Response| async move {
+            axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        };
+        let le = body(render("pcm", ByteOrder::Le).await).await;
+        let be = body(render("pcm", ByteOrder::Be).await).await;
+        assert_eq!(le.len(), be.len());
+        assert_ne!(le, be);
+        for (le, be) in le.chunks_exact(2).zip(be.chunks_exact(2)) {
+            assert_eq!(
+                i16::from_le_bytes([le[0], le[1]]),
+                i16::from_be_bytes([be[0], be[1]])
+            );
+        }
+
+        let wav = render("wav", ByteOrder::Be).await;
+        assert_eq!(wav.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_seed_makes_dithered_audio_reproducible() {
+        let render = |seed| async move {
+            let req = TtsRequest {
+                input: "hello".into(),
+                format: Some("pcm".into()),
+                bit_depth: Some(BitDepth::Bits(8)),
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let state = Arc::new(AppState::new(Config::default()));
+            let resp = tts_handler(State(state), Json(req)).await;
+            axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        };
+        let first = render(42).await;
+        assert_eq!(first, render(42).await);
+        assert_ne!(first, render(43).await);
+    }
+
+    #[test]
+    fn test_half_gain_halves_peak() {
+        let peak = |samples: Vec<i16>| samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        let full = peak(synthesize_sine(440.0, 0.1, 1.0, SAMPLE_RATE));
+        let half = peak(synthesize_sine(440.0, 0.1, 0.5, SAMPLE_RATE));
+        assert!(full.abs_diff(half * 2) <= 2, "full={full} half={half}");
+
+        let loud = peak(synthesize_sine(440.0, 0.1, 4.0, SAMPLE_RATE));
+        assert_eq!(loud, full);
+    }
+
+    #[test]
+    fn test_octave_pitch_doubles_tone_frequency() {
+        let config = Config {
+            voice_pitch: "bass=-12".parse().unwrap(),
+            ..Config::default()
+        };
+        let up = TtsRequest {
+            pitch: Some(12.0),
+            ..TtsRequest::default()
+        };
+        assert_eq!(Tone::for_request(&up, &config).freq_hz, BASE_FREQ_HZ * 2.0);
+
+        // The voice default applies unless the request sets its own pitch
+        let bass = TtsRequest {
+            voice: Some("bass".into()),
+            ..TtsRequest::default()
+        };
+        assert_eq!(
+            Tone::for_request(&bass, &config).freq_hz,
+            BASE_FREQ_HZ / 2.0
+        );
+        let bass_unshifted = TtsRequest {
+            pitch: Some(0.0),
+            ..bass
+        };
+        assert_eq!(
+            Tone::for_request(&bass_unshifted, &config).freq_hz,
+            BASE_FREQ_HZ
+        );
+    }
+
+    #[tokio::test]
+    async fn test_beep_preamble_leads_the_speech() {
+        let render = |preamble| {
+            let req = TtsRequest {
+                input: "hello".into(),
+                format: Some("pcm".into()),
+                preamble,
+                ..Default::default()
+            };
+            tts_handler(State(Arc::new(AppState::new(Config::default()))), Json(req))
+        };
+        let plain = axum::body::to_bytes(render(Preamble::None).await.into_body(), usize::MAX);
+        let beeped = axum::body::to_bytes(render(Preamble::Beep).await.into_body(), usize::MAX);
+        let (plain, beeped) = (plain.await.unwrap(), beeped.await.unwrap());
+
+        let samples: Vec<i16> = beeped
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        let beep_len = (SAMPLE_RATE as f32 * preamble::BEEP_SECS) as usize;
+        let gap_len = (SAMPLE_RATE as f32 * preamble::GAP_SECS) as usize;
+        assert_eq!(samples.len(), beep_len + gap_len + plain.len() / 2);
+        // The speech follows the beep and its gap unchanged
+        assert_eq!(&beeped[(beep_len + gap_len) * 2..], &plain[..]);
+        assert!(
+            samples[beep_len..beep_len + gap_len]
+                .iter()
+                .all(|&s| s == 0)
+        );
+
+        // Two sign changes per cycle identify the beep's frequency
+        let beep = &samples[..beep_len];
+        let crossings = beep.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+        let freq_hz = crossings as f32 / 2.0 / preamble::BEEP_SECS;
+        assert!(
+            (freq_hz - preamble::BEEP_FREQ_HZ).abs() < 20.0,
+            "freq={freq_hz}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pooled_buffers_produce_identical_audio() {
+        let render = |state: Arc<AppState>, sample_rate, trailing_silence_ms| {
+            let req = TtsRequest {
+                input: "hello".into(),
+                format: Some("pcm".into()),
+                sample_rate: Some(sample_rate),
+                trailing_silence_ms: Some(trailing_silence_ms),
+                ..Default::default()
+            };
+            async move {
+                let resp = tts_handler(State(state), Json(req)).await;
+                axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap()
+            }
+        };
+        let unpooled = Arc::new(AppState::new(Config::default()));
+        let pooled = Arc::new(AppState::new(Config {
+            buffer_pool: 2,
+            ..Config::default()
+        }));
+
+        // Later requests reuse buffers left longer by earlier ones
+        for (rate, silence) in [
+            (SAMPLE_RATE, 500),
+            (22050, 0),
+            (SAMPLE_RATE, 0),
+            (16000, 100),
+        ] {
+            let expected = render(unpooled.clone(), rate, silence).await;
+            let actual = render(pooled.clone(), rate, silence).await;
+            assert_eq!(actual, expected, "rate={rate} silence={silence}");
+        }
+        let pool = pooled.pool.as_ref().unwrap();
+        let reused = pool.take();
+        assert!(reused.is_empty() && reused.capacity() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_request_beyond_concurrency_limit_rejected() {
+        let state = Arc::new(AppState::new(Config {
+            max_concurrency: 2,
+            overload_policy: OverloadPolicy::Reject,
+            ..Config::default()
+        }));
+        let request = || TtsRequest {
+            input: "hello".into(),
+            ..Default::default()
+        };
+
+        // Two in-flight syntheses hold both slots
+        let busy = state.synth_slots.acquire_many(2).await.unwrap();
+        let resp = tts_handler(State(state.clone()), Json(request())).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(busy);
+        let resp = tts_handler(State(state), Json(request())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_slow_synthesis_times_out_with_504() {
+        let slow = || {
+            std::thread::sleep(Duration::from_millis(300));
+            StatusCode::OK.into_response()
+        };
+        let resp = run_synthesis(Duration::from_millis(20), slow).await;
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["error"].as_str().unwrap().contains("20ms"), "{body}");
+
+        let fast = || StatusCode::OK.into_response();
+        let resp = run_synthesis(Duration::from_secs(5), fast).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_padded_input_synthesizes_like_trimmed() {
+        assert_eq!(prepare_input("\n  Hello there.  \n", true), "Hello there.");
+        assert_eq!(prepare_input("  Hello  ", false), "  Hello  ");
+
+        let state = Arc::new(AppState::new(Config::default()));
+        let synthesize = |input: &str| {
+            let req = TtsRequest {
+                input: input.into(),
+                ..Default::default()
+            };
+            tts_handler(State(state.clone()), Json(req))
+        };
+        let padded = synthesize("\n\n  Hello there.  \n").await;
+        let trimmed = synthesize("Hello there.").await;
+
+        let padded = axum::body::to_bytes(padded.into_body(), usize::MAX).await;
+        let trimmed = axum::body::to_bytes(trimmed.into_body(), usize::MAX).await;
+        assert_eq!(padded.unwrap(), trimmed.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_phoneme_input_skips_text_preparation() {
+        let state = Arc::new(AppState::new(Config::default()));
+        let synthesize = |input: &str, input_type: InputType| {
+            let req = TtsRequest {
+                input: input.into(),
+                input_type,
+                format: Some("pcm".into()),
+                ..Default::default()
+            };
+            tts_handler(State(state.clone()), Json(req))
+        };
+        let samples = |resp: Response| async {
+            let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await;
+            body.unwrap().len() / 2
+        };
+
+        // Four phonemes give four tone segments instead of the fixed text length
+        let phonemes = samples(synthesize("HH AH0 L OW1", InputType::Phonemes).await).await;
+        let expected = (SAMPLE_RATE as f32 * phonemes::duration_secs(4)) as usize;
+        assert_eq!(phonemes, expected);
+        let text = samples(synthesize("HH AH0 L OW1", InputType::Text).await).await;
+        assert_eq!(text, (SAMPLE_RATE as f32 * TEXT_SECS) as usize);
+
+        let req: TtsRequest =
+            serde_json::from_str(r#"{"input": " h ə l oʊ \n", "input_type": "phonemes"}"#).unwrap();
+        assert_eq!(synthesis_input(&req, true), " h ə l oʊ \n");
+        assert_eq!(
+            Tone::for_request(&req, &state.config).duration_secs,
+            phonemes::duration_secs(4)
+        );
+        let text = TtsRequest {
+            input: req.input.clone(),
+            ..Default::default()
+        };
+        assert_eq!(synthesis_input(&text, true), "h ə l oʊ");
+
+        let streamed = tts_handler(
+            State(state.clone()),
+            Json(TtsRequest {
+                input: "HH AH0".into(),
+                input_type: InputType::Phonemes,
+                stream: Some(true),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert_eq!(streamed.status(), StatusCode::BAD_REQUEST);
+
+        let at_cap = vec!["AH0"; MAX_PHONEMES].join(" ");
+        let resp = synthesize(&at_cap, InputType::Phonemes).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let over_cap = format!("{at_cap} AH0");
+        let resp = synthesize(&over_cap, InputType::Phonemes).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        // Text input's length doesn't affect its duration, so it isn't capped here
+        let resp = synthesize(&over_cap, InputType::Text).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_warmup_synthesizes_once() {
+        let config = Config {
+            warmup: true,
+            ..Config::default()
+        };
+        assert!(warmup(&config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_version_reports_crate_version() {
+        let Json(info) = version_handler().await;
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.name, "tts-node");
+        assert!(!info.git_sha.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stream_emits_audio_per_sentence() {
+        let req = TtsRequest {
+            input: "First sentence. Second one! Third?".into(),
+            stream: Some(true),
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(Config::default()));
+        let resp = tts_handler(State(state.clone()), Json(req)).await;
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "audio/L16; rate=44100; channels=1"
+        );
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let per_sentence = SAMPLE_RATE as usize / 2 * 2;
+        assert_eq!(body.len(), 3 * per_sentence);
+        // The slot is released once the stream has been consumed
+        assert_eq!(
+            state.synth_slots.available_permits(),
+            state.config.max_concurrency
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_rejects_buffered_only_options() {
+        let state = Arc::new(AppState::new(Config::default()));
+        let stream = |req: TtsRequest| TtsRequest {
+            input: "One. Two.".into(),
+            stream: Some(true),
+            ..req
+        };
+        for req in [
+            TtsRequest {
+                sample_rate: Some(22050),
+                ..Default::default()
+            },
+            TtsRequest {
+                bit_depth: Some(BitDepth::Bits(8)),
+                ..Default::default()
+            },
+            TtsRequest {
+                trailing_silence_ms: Some(200),
+                ..Default::default()
+            },
+            TtsRequest {
+                dither: Some(false),
+                ..Default::default()
+            },
+        ] {
+            let resp = tts_handler(State(state.clone()), Json(stream(req))).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(
+            state.synth_slots.available_permits(),
+            state.config.max_concurrency
+        );
+    }
 }
@@ -0,0 +1,31 @@
+//! Phoneme input (`input_type: "phonemes"`) for callers that control pronunciation
+//! themselves.
+//!
+//! `input` is then a whitespace-separated phoneme string, ARPAbet (`HH AH0 L OW1`) or
+//! IPA (`h ə l oʊ`), handed to synthesis without the text preparation applied to
+//! ordinary input. The stub voices one tone segment per phoneme.
+
+use serde::Deserialize;
+
+/// Stub audio length per phoneme.
+pub const PHONEME_SECS: f32 = 0.08;
+/// Most phonemes one request may carry (80 s of stub audio).
+pub const MAX_PHONEMES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputType {
+    #[default]
+    Text,
+    Phonemes,
+}
+
+/// The phoneme symbols in `input`.
+pub fn split(input: &str) -> Vec<&str> {
+    input.split_whitespace().collect()
+}
+
+/// Seconds of stub audio for `count` phonemes.
+pub fn duration_secs(count: usize) -> f32 {
+    count as f32 * PHONEME_SECS
+}
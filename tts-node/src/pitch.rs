@@ -0,0 +1,71 @@
+//! Pitch shifting in semitones, per request (`pitch`) or per voice
+//! (`TTS_NODE_VOICE_PITCH`).
+//!
+//! The stub transposes its tone; a real engine would shift the synthesized audio.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{Context, bail};
+
+/// Shifts are clamped to two octaves either way.
+pub const MAX_SEMITONES: f32 = 24.0;
+
+/// `freq_hz` moved by `semitones` (clamped to ±[`MAX_SEMITONES`]).
+pub fn shift(freq_hz: f32, semitones: f32) -> f32 {
+    let semitones = semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES);
+    freq_hz * 2f32.powf(semitones / 12.0)
+}
+
+/// Default shift per voice name, parsed from `voice=semitones` pairs separated by commas.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VoicePitches(HashMap<String, f32>);
+
+impl VoicePitches {
+    /// The configured shift for `voice`, or none.
+    pub fn get(&self, voice: &str) -> f32 {
+        self.0.get(voice).copied().unwrap_or(0.0)
+    }
+}
+
+impl FromStr for VoicePitches {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut pitches = HashMap::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((voice, semitones)) = entry.split_once('=') else {
+                bail!("expected voice=semitones, got {entry:?}");
+            };
+            let semitones: f32 = semitones
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid semitones for voice {voice:?}"))?;
+            pitches.insert(voice.trim().to_string(), semitones);
+        }
+        Ok(Self(pitches))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_octave_up_doubles_frequency() {
+        assert_eq!(shift(440.0, 12.0), 880.0);
+        assert_eq!(shift(440.0, -12.0), 220.0);
+        assert_eq!(shift(440.0, 0.0), 440.0);
+        // Clamped to two octaves
+        assert_eq!(shift(440.0, 100.0), 1760.0);
+    }
+
+    #[test]
+    fn test_voice_pitches_parse() {
+        let pitches: VoicePitches = "alto=3, bass=-5.5".parse().unwrap();
+        assert_eq!(pitches.get("alto"), 3.0);
+        assert_eq!(pitches.get("bass"), -5.5);
+        assert_eq!(pitches.get("default"), 0.0);
+        assert!("alto".parse::<VoicePitches>().is_err());
+    }
+}
@@ -0,0 +1,36 @@
+//! Reusable sample buffers for buffered synthesis (`TTS_NODE_BUFFER_POOL`).
+//!
+//! Each request otherwise allocates its sample `Vec`s afresh; under concurrent load
+//! that churn shows up in profiles. Buffers taken from the pool keep the capacity of
+//! earlier requests and are always handed out empty, so output is unaffected.
+
+use std::sync::Mutex;
+
+pub struct SamplePool {
+    buffers: Mutex<Vec<Vec<i16>>>,
+    capacity: usize,
+}
+
+impl SamplePool {
+    /// A pool holding at most `capacity` idle buffers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// An empty buffer, reusing a returned one when available.
+    pub fn take(&self) -> Vec<i16> {
+        self.buffers.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Return `buffer` for reuse; it is dropped if the pool is full.
+    pub fn give(&self, mut buffer: Vec<i16>) {
+        buffer.clear();
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.capacity {
+            buffers.push(buffer);
+        }
+    }
+}
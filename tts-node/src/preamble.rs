@@ -0,0 +1,36 @@
+//! Optional alert tone played before the speech (`preamble: "beep"`), for
+//! notification-style TTS.
+
+use serde::Deserialize;
+
+use crate::synthesize_sine;
+
+/// Higher and shorter than the stub voice's 440Hz so it is heard as a separate cue.
+pub const BEEP_FREQ_HZ: f32 = 1000.0;
+pub const BEEP_SECS: f32 = 0.15;
+/// Silence between the beep and the speech.
+pub const GAP_SECS: f32 = 0.05;
+const BEEP_GAIN: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preamble {
+    #[default]
+    None,
+    Beep,
+}
+
+impl Preamble {
+    /// Samples at `sample_rate` to play before the speech; empty for `None`.
+    pub fn samples(self, sample_rate: u32) -> Vec<i16> {
+        match self {
+            Self::None => Vec::new(),
+            Self::Beep => {
+                let mut samples = synthesize_sine(BEEP_FREQ_HZ, BEEP_SECS, BEEP_GAIN, sample_rate);
+                let gap = (sample_rate as f32 * GAP_SECS) as usize;
+                samples.resize(samples.len() + gap, 0);
+                samples
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Sample-rate conversion from the synthesis rate to a requested `sample_rate`.
+//!
+//! Voices synthesize at a fixed native rate; converting afterwards lets any rate be
+//! served without re-synthesizing. Windowed-sinc interpolation (the default) low-passes
+//! at the lower of the two Nyquist frequencies so downsampling doesn't alias; linear
+//! interpolation is cheaper and good enough for speech previews.
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use anyhow::bail;
+
+/// Input samples on each side of the interpolation point at unity cutoff.
+const SINC_HALF_WIDTH: usize = 16;
+
+/// Interpolation used by [`resample`], from `TTS_NODE_RESAMPLER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resampler {
+    Linear,
+    Sinc,
+}
+
+impl FromStr for Resampler {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "sinc" => Ok(Self::Sinc),
+            other => bail!("unknown resampler {other:?}; expected 'linear' or 'sinc'"),
+        }
+    }
+}
+
+/// Convert mono `samples` from `from_rate` to `to_rate`, keeping the duration.
+pub fn resample(samples: &[i16], from_rate: u32, to_rate: u32, method: Resampler) -> Vec<i16> {
+    if from_rate == to_rate || samples.is_empty() || from_rate == 0 || to_rate == 0 {
+        return samples.to_vec();
+    }
+    let ratio = f64::from(from_rate) / f64::from(to_rate);
+    let out_len = (samples.len() as f64 / ratio).round() as usize;
+    (0..out_len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let value = match method {
+                Resampler::Linear => linear_at(samples, position),
+                Resampler::Sinc => sinc_at(samples, position, (1.0 / ratio).min(1.0)),
+            };
+            value
+                .round()
+                .clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+        })
+        .collect()
+}
+
+fn linear_at(samples: &[i16], position: f64) -> f64 {
+    let index = position.floor() as usize;
+    let frac = position - position.floor();
+    let a = f64::from(samples[index.min(samples.len() - 1)]);
+    let b = f64::from(samples[(index + 1).min(samples.len() - 1)]);
+    a + (b - a) * frac
+}
+
+/// Hann-windowed sinc with its cutoff at `cutoff` times the input Nyquist frequency.
+fn sinc_at(samples: &[i16], position: f64, cutoff: f64) -> f64 {
+    // A lower cutoff widens the kernel so it spans the same number of zero crossings
+    let half_width = (SINC_HALF_WIDTH as f64 / cutoff).ceil();
+    let first = (position - half_width).ceil().max(0.0) as usize;
+    let last = ((position + half_width).floor() as usize).min(samples.len() - 1);
+    (first..=last)
+        .map(|k| {
+            let offset = position - k as f64;
+            let x = cutoff * offset;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
+            let window = 0.5 * (1.0 + (PI * offset / half_width).cos());
+            f64::from(samples[k]) * cutoff * sinc * window
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthesize_sine;
+
+    #[test]
+    fn test_halving_the_rate_halves_samples_and_keeps_duration() {
+        let input = synthesize_sine(440.0, 1.0, 0.5, 44100);
+        for method in [Resampler::Linear, Resampler::Sinc] {
+            let output = resample(&input, 44100, 22050, method);
+            assert_eq!(output.len(), 22050);
+            let duration = output.len() as f64 / 22050.0;
+            assert!((duration - 1.0).abs() < 1e-3);
+
+            // Still a 440 Hz tone at roughly the same level: compare against a
+            // tone synthesized directly at the target rate, away from the edges
+            let direct = synthesize_sine(440.0, 1.0, 0.5, 22050);
+            let worst = output[100..22000]
+                .iter()
+                .zip(&direct[100..22000])
+                .map(|(a, b)| a.abs_diff(*b))
+                .max()
+                .unwrap();
+            assert!(worst < 400, "{method:?} deviates by {worst}");
+        }
+    }
+
+    #[test]
+    fn test_matching_rates_pass_through() {
+        let input = [1, -2, 3];
+        assert_eq!(resample(&input, 22050, 22050, Resampler::Sinc), input);
+    }
+}
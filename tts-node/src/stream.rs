@@ -0,0 +1,194 @@
+//! Sentence-at-a-time streaming synthesis for `stream: true` requests.
+//!
+//! Input is split at sentence ends and each sentence is synthesized only when the
+//! client is ready for more audio, so playback can begin after the first sentence.
+//! A client that disconnects mid-stream drops the response body, and with it the
+//! remaining sentences and the concurrency slot: nothing more is synthesized.
+
+use std::convert::Infallible;
+
+use axum::{
+    body::Body,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::{Stream, StreamExt};
+use tokio::sync::OwnedSemaphorePermit;
+
+use crate::wav::{ByteOrder, encode_raw};
+use crate::{SAMPLE_RATE, Tone, synthesize_sine};
+
+/// Stub audio length per sentence.
+const SENTENCE_SECS: f32 = 0.5;
+
+/// Split text after `.`, `!` or `?` runs that end a word, dropping blank pieces.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        current.push(c);
+        let ends_sentence =
+            matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|next| next.is_whitespace());
+        if ends_sentence {
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+    sentences.push(current);
+    sentences
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Lazily synthesize one audio chunk per sentence as the stream is polled.
+pub fn sentence_chunks<F>(
+    sentences: Vec<String>,
+    mut synthesize: F,
+) -> impl Stream<Item = Result<Vec<u8>, Infallible>>
+where
+    F: FnMut(&str) -> Vec<u8>,
+{
+    futures_util::stream::iter(sentences).map(move |sentence| Ok(synthesize(&sentence)))
+}
+
+/// Sentences synthesized for one stream, logged if the client leaves early.
+struct Progress {
+    synthesized: usize,
+    total: usize,
+}
+
+impl Progress {
+    fn advance(&mut self) {
+        self.synthesized += 1;
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.synthesized < self.total {
+            tracing::info!(
+                "Client disconnected after {} of {} sentences; synthesis stopped",
+                self.synthesized,
+                self.total
+            );
+        }
+    }
+}
+
+/// Chunked raw PCM response; `slot` is held until the last sentence is sent or the
+/// client disconnects.
+pub fn sentence_response(
+    input: &str,
+    tone: Tone,
+    byte_order: ByteOrder,
+    slot: OwnedSemaphorePermit,
+) -> Response {
+    let sentences = split_sentences(input);
+    tracing::info!("Streaming TTS: {} sentences", sentences.len());
+
+    let mut progress = Progress {
+        synthesized: 0,
+        total: sentences.len(),
+    };
+    let chunks = sentence_chunks(sentences, move |_sentence| {
+        let _slot = &slot;
+        progress.advance();
+        // Stub: a fixed-length tone per sentence
+        let samples = synthesize_sine(tone.freq_hz, SENTENCE_SECS, tone.gain, SAMPLE_RATE);
+        encode_raw(&samples, 16, byte_order)
+    });
+    // The preamble goes out as its own chunk ahead of the first sentence
+    let preamble = tone.preamble.samples(SAMPLE_RATE);
+    let preamble = (!preamble.is_empty()).then(|| Ok(encode_raw(&preamble, 16, byte_order)));
+    let chunks = futures_util::stream::iter(preamble).chain(chunks);
+    let content_type = format!("audio/L16; rate={SAMPLE_RATE}; channels=1");
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_split_sentences() {
+        assert_eq!(
+            split_sentences("Hello there. How are you?  Fine! v1.2 works"),
+            ["Hello there.", "How are you?", "Fine!", "v1.2 works"]
+        );
+        assert!(split_sentences("   ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_first_sentence_flushed_before_last_synthesized() {
+        let synthesized = Arc::new(AtomicUsize::new(0));
+        let counter = synthesized.clone();
+        let sentences = split_sentences("One. Two. Three.");
+        let stream = sentence_chunks(sentences, move |s| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            s.as_bytes().to_vec()
+        });
+        futures_util::pin_mut!(stream);
+
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first, b"One.");
+        assert_eq!(synthesized.load(Ordering::SeqCst), 1);
+
+        let rest: Vec<_> = stream.collect().await;
+        assert_eq!(rest.len(), 2);
+        assert_eq!(synthesized.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_stops_synthesis() {
+        const SENTENCES: usize = 10_000;
+        let synthesized = Arc::new(AtomicUsize::new(0));
+        let slots = Arc::new(tokio::sync::Semaphore::new(1));
+        let app = {
+            let (synthesized, slots) = (synthesized.clone(), slots.clone());
+            axum::Router::new().route(
+                "/",
+                axum::routing::get(move || async move {
+                    let slot = slots.try_acquire_owned().unwrap();
+                    let chunks = sentence_chunks(vec![String::new(); SENTENCES], move |_| {
+                        let _slot = &slot;
+                        synthesized.fetch_add(1, Ordering::SeqCst);
+                        vec![0; SAMPLE_RATE as usize]
+                    });
+                    Body::from_stream(chunks)
+                }),
+            )
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        // Read the start of the audio, then hang up
+        let client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client.writable().await.unwrap();
+        client
+            .try_write(b"GET / HTTP/1.1\r\nHost: tts\r\n\r\n")
+            .unwrap();
+        client.readable().await.unwrap();
+        assert!(client.try_read(&mut [0; 1024]).unwrap() > 0);
+        drop(client);
+
+        let released = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while slots.available_permits() == 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        assert!(released.is_ok(), "slot still held after disconnect");
+        let done = synthesized.load(Ordering::SeqCst);
+        assert!(done < SENTENCES, "synthesized all {done} sentences");
+    }
+}
@@ -0,0 +1,364 @@
+//! 16-bit and 8-bit PCM, and 32-bit IEEE float, WAV encoding.
+//!
+//! Mono and stereo use the plain `fmt ` chunk (format tag 1, or 3 for float). More than two channels (or an
+//! explicit request) switch to `WAVE_FORMAT_EXTENSIBLE`, which carries the speaker
+//! layout as a channel mask so multichannel consumers map channels correctly.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// `KSDATAFORMAT_SUBTYPE_PCM` (00000001-0000-0010-8000-00aa00389b71) in file byte order.
+const SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// `KSDATAFORMAT_SUBTYPE_IEEE_FLOAT` (00000003-0000-0010-8000-00aa00389b71) in file byte order.
+const SUBTYPE_IEEE_FLOAT: [u8; 16] = [
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// Request metadata keys and the RIFF INFO tags they are written as.
+const INFO_TAGS: &[(&str, &[u8; 4])] = &[
+    ("title", b"INAM"),
+    ("artist", b"IART"),
+    ("album", b"IPRD"),
+    ("genre", b"IGNR"),
+    ("date", b"ICRD"),
+    ("comment", b"ICMT"),
+    ("copyright", b"ICOP"),
+    ("software", b"ISFT"),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct WavSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 16, 8 for unsigned 8-bit samples, or 32 for IEEE float samples.
+    pub bits_per_sample: u16,
+    /// Use `WAVE_FORMAT_EXTENSIBLE` even for mono/stereo.
+    pub extensible: bool,
+}
+
+impl WavSpec {
+    pub fn mono(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            channels: 1,
+            bits_per_sample: 16,
+            extensible: false,
+        }
+    }
+
+    fn uses_extensible(&self) -> bool {
+        self.extensible || self.channels > 2
+    }
+
+    pub fn is_float(&self) -> bool {
+        self.bits_per_sample == 32
+    }
+}
+
+/// Standard speaker masks for common layouts (mono, stereo, quad, 5.1, 7.1);
+/// other counts take the first `channels` speaker positions.
+pub fn channel_mask(channels: u16) -> u32 {
+    match channels {
+        1 => 0x4,
+        2 => 0x3,
+        4 => 0x33,
+        6 => 0x3F,
+        8 => 0x63F,
+        n => (1u32 << n.min(18)) - 1,
+    }
+}
+
+/// Fold interleaved `channels`-channel audio to mono by averaging each frame, so no
+/// channel's content is lost. Mono input is returned unchanged.
+pub fn downmix(samples: Vec<i16>, channels: u16) -> Vec<i16> {
+    if channels <= 1 {
+        return samples;
+    }
+    samples
+        .chunks(usize::from(channels))
+        .map(|frame| {
+            let sum: i32 = frame.iter().map(|&s| i32::from(s)).sum();
+            (sum / frame.len() as i32) as i16
+        })
+        .collect()
+}
+
+pub fn encode_pcm(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// Sample byte order of raw PCM output; WAV data is always little-endian.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteOrder {
+    #[default]
+    Le,
+    Be,
+}
+
+/// Headerless samples as [`encode_samples`], with 16-bit samples in `order`. 8-bit
+/// samples are single bytes, so only 16-bit output is affected.
+pub fn encode_raw(samples: &[i16], bits_per_sample: u16, order: ByteOrder) -> Vec<u8> {
+    match (bits_per_sample, order) {
+        (16, ByteOrder::Be) => samples.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        _ => encode_samples(samples, bits_per_sample),
+    }
+}
+
+/// Raw sample bytes at `bits_per_sample`: signed little-endian 16-bit, unsigned 8-bit
+/// with a 128 offset (rounded to the nearest step), or little-endian f32 in -1.0..1.0.
+pub fn encode_samples(samples: &[i16], bits_per_sample: u16) -> Vec<u8> {
+    match bits_per_sample {
+        8 => samples
+            .iter()
+            .map(|&s| (((i32::from(s) + 128) >> 8).clamp(-128, 127) + 128) as u8)
+            .collect(),
+        32 => samples
+            .iter()
+            .flat_map(|&s| (f32::from(s) / 32768.0).to_le_bytes())
+            .collect(),
+        _ => encode_pcm(samples),
+    }
+}
+
+/// Encode interleaved `samples` as a WAV file with an optional `LIST/INFO` chunk.
+pub fn encode_wav(samples: &[i16], spec: &WavSpec, metadata: &HashMap<String, String>) -> Vec<u8> {
+    let fmt = encode_fmt_chunk(spec);
+    let fact = encode_fact_chunk(samples.len(), spec);
+    let info = encode_info_chunk(metadata);
+    let data = encode_samples(samples, spec.bits_per_sample);
+    // Chunks are word-aligned; odd-length 8-bit data gets a pad byte
+    let pad = data.len() % 2;
+    let riff_size = 4 + fmt.len() + fact.len() + info.len() + 8 + data.len() + pad;
+
+    let mut wav = Vec::with_capacity(8 + riff_size);
+    // RIFF header
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(riff_size as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(&fmt);
+    wav.extend_from_slice(&fact);
+    // Optional LIST/INFO subchunk; readers skip chunks they don't understand
+    wav.extend_from_slice(&info);
+
+    // data subchunk
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&data);
+    wav.resize(wav.len() + pad, 0);
+    wav
+}
+
+/// The complete `fmt ` chunk: 16 bytes of PCM fields, plus the 24-byte extension.
+fn encode_fmt_chunk(spec: &WavSpec) -> Vec<u8> {
+    let extensible = spec.uses_extensible();
+    let block_align = spec.channels * spec.bits_per_sample / 8;
+    let byte_rate = spec.sample_rate * u32::from(block_align);
+    let (subtype, plain_tag) = if spec.is_float() {
+        (SUBTYPE_IEEE_FLOAT, WAVE_FORMAT_IEEE_FLOAT)
+    } else {
+        (SUBTYPE_PCM, WAVE_FORMAT_PCM)
+    };
+    let (format_tag, body_size) = if extensible {
+        (WAVE_FORMAT_EXTENSIBLE, 40u32)
+    } else {
+        (plain_tag, 16u32)
+    };
+
+    let mut chunk = Vec::with_capacity(8 + body_size as usize);
+    chunk.extend_from_slice(b"fmt ");
+    chunk.extend_from_slice(&body_size.to_le_bytes());
+    chunk.extend_from_slice(&format_tag.to_le_bytes());
+    chunk.extend_from_slice(&spec.channels.to_le_bytes());
+    chunk.extend_from_slice(&spec.sample_rate.to_le_bytes());
+    chunk.extend_from_slice(&byte_rate.to_le_bytes());
+    chunk.extend_from_slice(&block_align.to_le_bytes());
+    chunk.extend_from_slice(&spec.bits_per_sample.to_le_bytes());
+    if extensible {
+        chunk.extend_from_slice(&22u16.to_le_bytes()); // cbSize
+        chunk.extend_from_slice(&spec.bits_per_sample.to_le_bytes()); // wValidBitsPerSample
+        chunk.extend_from_slice(&channel_mask(spec.channels).to_le_bytes());
+        chunk.extend_from_slice(&subtype);
+    }
+    chunk
+}
+
+/// Non-PCM formats carry a `fact` chunk with the frame count; empty for PCM.
+fn encode_fact_chunk(sample_count: usize, spec: &WavSpec) -> Vec<u8> {
+    if !spec.is_float() {
+        return Vec::new();
+    }
+    let frames = (sample_count / usize::from(spec.channels)) as u32;
+    let mut chunk = Vec::with_capacity(12);
+    chunk.extend_from_slice(b"fact");
+    chunk.extend_from_slice(&4u32.to_le_bytes());
+    chunk.extend_from_slice(&frames.to_le_bytes());
+    chunk
+}
+
+/// Build a `LIST/INFO` chunk from the recognised metadata keys.
+/// Returns an empty vec when no known keys are present, so no chunk is written.
+fn encode_info_chunk(metadata: &HashMap<String, String>) -> Vec<u8> {
+    let mut entries = Vec::new();
+    for (key, tag) in INFO_TAGS {
+        let Some(value) = metadata.get(*key) else {
+            continue;
+        };
+        // Each entry is a NUL-terminated string, padded to an even length
+        let size = value.len() as u32 + 1;
+        entries.extend_from_slice(*tag);
+        entries.extend_from_slice(&size.to_le_bytes());
+        entries.extend_from_slice(value.as_bytes());
+        entries.push(0);
+        if size % 2 == 1 {
+            entries.push(0);
+        }
+    }
+    if entries.is_empty() {
+        return entries;
+    }
+
+    let mut chunk = Vec::with_capacity(12 + entries.len());
+    chunk.extend_from_slice(b"LIST");
+    chunk.extend_from_slice(&(4 + entries.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(b"INFO");
+    chunk.extend_from_slice(&entries);
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walk the RIFF chunks after the `WAVE` tag, returning (id, body) pairs.
+    fn riff_chunks(wav: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut chunks = Vec::new();
+        let mut pos = 12;
+        while pos + 8 <= wav.len() {
+            let id: [u8; 4] = wav[pos..pos + 4].try_into().unwrap();
+            let size = u32::from_le_bytes(wav[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            chunks.push((id, &wav[pos + 8..pos + 8 + size]));
+            pos += 8 + size + size % 2;
+        }
+        assert_eq!(pos, wav.len(), "chunks must exactly fill the file");
+        chunks
+    }
+
+    fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes(bytes[pos..pos + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_downmix_averages_channels() {
+        // Interleaved left/right frames with distinct content per channel
+        let stereo = vec![1000, -1000, 3000, 1000, i16::MAX, i16::MAX, -7, 0];
+        assert_eq!(downmix(stereo, 2), vec![0, 2000, i16::MAX, -3]);
+        assert_eq!(downmix(vec![5, 6, 7], 1), vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn test_metadata_written_as_list_info_chunk() {
+        let metadata = HashMap::from([
+            ("title".to_string(), "Test Tone".to_string()),
+            ("artist".to_string(), "ai-stack".to_string()),
+            ("unknown".to_string(), "ignored".to_string()),
+        ]);
+        let samples = vec![0i16; 4410];
+        let wav = encode_wav(&samples, &WavSpec::mono(44100), &metadata);
+
+        let riff_size = u32_at(&wav, 4) as usize;
+        assert_eq!(riff_size, wav.len() - 8);
+
+        let chunks = riff_chunks(&wav);
+        let ids: Vec<_> = chunks.iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, [b"fmt ", b"LIST", b"data"]);
+
+        let list = chunks[1].1;
+        assert_eq!(&list[0..4], b"INFO");
+        assert_eq!(&list[4..8], b"INAM");
+        assert_eq!(&list[12..22], b"Test Tone\0");
+        assert!(list.windows(4).any(|w| w == b"IART"));
+        assert!(!list.windows(7).any(|w| w == b"ignored"));
+        assert_eq!(chunks[2].1.len(), samples.len() * 2);
+    }
+
+    #[test]
+    fn test_four_channels_use_extensible_format() {
+        let spec = WavSpec {
+            sample_rate: 48000,
+            channels: 4,
+            bits_per_sample: 16,
+            extensible: false,
+        };
+        let samples = vec![0i16; 4 * 100];
+        let wav = encode_wav(&samples, &spec, &HashMap::new());
+
+        let chunks = riff_chunks(&wav);
+        let (id, fmt) = chunks[0];
+        assert_eq!(&id, b"fmt ");
+        assert_eq!(fmt.len(), 40);
+        assert_eq!(u16_at(fmt, 0), WAVE_FORMAT_EXTENSIBLE);
+        assert_eq!(u16_at(fmt, 2), 4); // channels
+        assert_eq!(u32_at(fmt, 4), 48000);
+        assert_eq!(u32_at(fmt, 8), 48000 * 8); // byte rate
+        assert_eq!(u16_at(fmt, 12), 8); // block align
+        assert_eq!(u16_at(fmt, 16), 22); // cbSize
+        assert_eq!(u16_at(fmt, 18), 16); // valid bits
+        assert_eq!(u32_at(fmt, 20), 0x33); // FL | FR | BL | BR
+        assert_eq!(fmt[24..40], SUBTYPE_PCM);
+        assert_eq!(chunks[1].1.len(), samples.len() * 2);
+    }
+
+    #[test]
+    fn test_float_wav_uses_ieee_format_tag() {
+        let spec = WavSpec {
+            bits_per_sample: 32,
+            ..WavSpec::mono(48000)
+        };
+        let samples = [0i16, i16::MIN, 16384];
+        let wav = encode_wav(&samples, &spec, &HashMap::new());
+
+        let chunks = riff_chunks(&wav);
+        let ids: Vec<_> = chunks.iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, [b"fmt ", b"fact", b"data"]);
+        let fmt = chunks[0].1;
+        assert_eq!(u16_at(fmt, 0), WAVE_FORMAT_IEEE_FLOAT);
+        assert_eq!(u32_at(fmt, 8), 48000 * 4); // byte rate
+        assert_eq!(u16_at(fmt, 12), 4); // block align
+        assert_eq!(u16_at(fmt, 14), 32);
+        assert_eq!(u32_at(chunks[1].1, 0), 3); // frames
+
+        let data = chunks[2].1;
+        assert_eq!(data.len(), samples.len() * 4);
+        let float_at = |i: usize| f32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        assert_eq!([float_at(0), float_at(1), float_at(2)], [0.0, -1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_mono_keeps_plain_pcm_unless_requested() {
+        let plain = encode_wav(&[0; 10], &WavSpec::mono(44100), &HashMap::new());
+        assert_eq!(u32_at(&plain, 16), 16);
+        assert_eq!(u16_at(&plain, 20), WAVE_FORMAT_PCM);
+
+        let spec = WavSpec {
+            extensible: true,
+            ..WavSpec::mono(44100)
+        };
+        let forced = encode_wav(&[0; 10], &spec, &HashMap::new());
+        assert_eq!(u16_at(&forced, 20), WAVE_FORMAT_EXTENSIBLE);
+        assert_eq!(u32_at(&forced, 40), 0x4); // front centre
+    }
+}
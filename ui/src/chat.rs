@@ -0,0 +1,99 @@
+//! Chat completion responses as relayed by the gateway, mirroring llm-node's types.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    pub choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatChoice {
+    pub message: ChatMessage,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// The assistant reply to show for a completion.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssistantReply {
+    pub id: String,
+    pub content: String,
+}
+
+/// Extract `choices[0].message.content`, or `None` when `raw` is not a completion
+/// (e.g. the gateway answered with an `ErrorResponse`).
+pub fn assistant_reply(raw: &str) -> Option<AssistantReply> {
+    let response: ChatCompletionResponse = serde_json::from_str(raw).ok()?;
+    let choice = response.choices.into_iter().next()?;
+    (choice.message.role == "assistant").then(|| AssistantReply {
+        id: response.id,
+        content: choice.message.content,
+    })
+}
+
+/// The `messages` to send: the system prompt when set, then the last `max_turns` turns
+/// of `history` (a turn starts at a user message). Older turns stay displayed but are
+/// not sent, which bounds request size however long the conversation grows.
+pub fn windowed(
+    system_prompt: &str,
+    history: &[ChatMessage],
+    max_turns: usize,
+) -> Vec<ChatMessage> {
+    let turn_starts: Vec<usize> = history
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.role == "user")
+        .map(|(i, _)| i)
+        .collect();
+    let keep_from = turn_starts
+        .len()
+        .checked_sub(max_turns.max(1))
+        .map_or(0, |skipped| turn_starts[skipped]);
+    let system =
+        (!system_prompt.trim().is_empty()).then(|| ChatMessage::new("system", system_prompt));
+    system
+        .into_iter()
+        .chain(history[keep_from..].iter().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_keeps_system_prompt_and_newest_turns() {
+        let history: Vec<ChatMessage> = (1..=4)
+            .flat_map(|turn| {
+                [
+                    ChatMessage::new("user", format!("question {turn}")),
+                    ChatMessage::new("assistant", format!("answer {turn}")),
+                ]
+            })
+            .collect();
+
+        let sent = windowed("Be brief.", &history, 2);
+        assert_eq!(sent[0], ChatMessage::new("system", "Be brief."));
+        assert_eq!(sent[1..], history[4..]);
+
+        // Fewer turns than the window: everything is sent
+        assert_eq!(windowed("Be brief.", &history, 10)[1..], history[..]);
+        // No system prompt, no system message
+        assert_eq!(windowed(" ", &history, 1), history[6..]);
+    }
+}
@@ -0,0 +1,65 @@
+//! Runtime configuration injected by the host page.
+//! The page may define `window.AI_STACK_CONFIG = { ... }` before loading the bundle;
+//! missing keys fall back to defaults.
+
+use wasm_bindgen::JsValue;
+
+const CONFIG_GLOBAL: &str = "AI_STACK_CONFIG";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiConfig {
+    /// Intro message pinned as the first assistant bubble. Never sent to the backend.
+    pub greeting: String,
+    /// Send triggers within this many milliseconds of the previous send are ignored.
+    pub send_debounce_ms: f64,
+    /// System prompt sent first with every request, whatever the history window.
+    pub system_prompt: String,
+    /// Most recent conversation turns sent with each request; adjustable in the page.
+    pub history_turns: usize,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            greeting: String::new(),
+            send_debounce_ms: 300.0,
+            system_prompt: String::new(),
+            history_turns: 10,
+        }
+    }
+}
+
+impl UiConfig {
+    pub fn from_window() -> Self {
+        let Some(window) = web_sys::window() else {
+            return Self::default();
+        };
+        let config = js_sys::Reflect::get(&window, &JsValue::from_str(CONFIG_GLOBAL))
+            .unwrap_or(JsValue::UNDEFINED);
+        if config.is_undefined() || config.is_null() {
+            return Self::default();
+        }
+
+        let defaults = Self::default();
+        Self {
+            greeting: read_string(&config, "greeting").unwrap_or(defaults.greeting),
+            send_debounce_ms: read_number(&config, "send_debounce_ms")
+                .unwrap_or(defaults.send_debounce_ms),
+            system_prompt: read_string(&config, "system_prompt").unwrap_or(defaults.system_prompt),
+            history_turns: read_number(&config, "history_turns")
+                .map_or(defaults.history_turns, |turns| turns.max(1.0) as usize),
+        }
+    }
+}
+
+fn read_string(config: &JsValue, key: &str) -> Option<String> {
+    js_sys::Reflect::get(config, &JsValue::from_str(key))
+        .ok()?
+        .as_string()
+}
+
+fn read_number(config: &JsValue, key: &str) -> Option<f64> {
+    js_sys::Reflect::get(config, &JsValue::from_str(key))
+        .ok()?
+        .as_f64()
+}
@@ -1,11 +1,25 @@
+mod chat;
+mod config;
+mod speech;
+
 use gloo_net::http::Request;
 use wasm_bindgen::prelude::*;
 use yew::prelude::*;
 
+use crate::chat::{AssistantReply, ChatMessage};
+use crate::config::UiConfig;
+
 #[function_component(App)]
 pub fn app() -> Html {
+    let config = use_memo((), |_| UiConfig::from_window());
     let input = use_state(String::new);
     let output = use_state(String::new);
+    // Only a parsed completion can be spoken; errors and raw fallbacks leave it empty.
+    let reply = use_state(AssistantReply::default);
+    let speech_error = use_state(String::new);
+    // The whole conversation is shown; only the last `window_turns` turns are sent.
+    let history = use_state(Vec::<ChatMessage>::new);
+    let window_turns = use_state(|| config.history_turns);
 
     let on_input_change = {
         let input = input.clone();
@@ -16,18 +30,49 @@ pub fn app() -> Html {
         })
     };
 
+    let on_window_change = {
+        let window_turns = window_turns.clone();
+        Callback::from(move |e: Event| {
+            let turns = e
+                .target_dyn_into::<web_sys::HtmlInputElement>()
+                .and_then(|target| target.value().parse::<usize>().ok());
+            if let Some(turns) = turns {
+                window_turns.set(turns.max(1));
+            }
+        })
+    };
+
+    // A ref rather than state: it must update synchronously to catch rapid triggers.
+    let last_send = use_mut_ref(|| None::<f64>);
+
     let on_send = {
         let input = input.clone();
         let output = output.clone();
+        let reply = reply.clone();
+        let history = history.clone();
+        let window_turns = *window_turns;
+        let system_prompt = config.system_prompt.clone();
+        let debounce_ms = config.send_debounce_ms;
         Callback::from(move |_| {
+            let now = js_sys::Date::now();
+            if !debounce_elapsed(*last_send.borrow(), now, debounce_ms) {
+                return;
+            }
+            *last_send.borrow_mut() = Some(now);
+
             let input = input.clone();
             let output = output.clone();
+            let reply = reply.clone();
+            let history = history.clone();
+            reply.set(AssistantReply::default());
+            let mut conversation = (*history).clone();
+            conversation.push(ChatMessage::new("user", (*input).clone()));
+            history.set(conversation.clone());
+            let messages = chat::windowed(&system_prompt, &conversation, window_turns);
             wasm_bindgen_futures::spawn_local(async move {
                 let body = serde_json::json!({
                     "model": "qwen3-8b-instruct",
-                    "messages": [
-                        { "role": "user", "content": (*input).clone() }
-                    ]
+                    "messages": messages,
                 });
 
                 match Request::post("http://localhost:8080/v1/chat/completions")
@@ -37,7 +82,19 @@ pub fn app() -> Html {
                     Ok(req) => match req.send().await {
                         Ok(resp) => {
                             if let Ok(text) = resp.text().await {
-                                output.set(text);
+                                match chat::assistant_reply(&text) {
+                                    Some(parsed) => {
+                                        output.set(parsed.content.clone());
+                                        conversation.push(ChatMessage::new(
+                                            "assistant",
+                                            parsed.content.clone(),
+                                        ));
+                                        history.set(conversation);
+                                        reply.set(parsed);
+                                    }
+                                    // Not a completion (e.g. an ErrorResponse): show it as is
+                                    None => output.set(text),
+                                }
                             } else {
                                 output.set("Failed to read response text".into());
                             }
@@ -54,10 +111,49 @@ pub fn app() -> Html {
         })
     };
 
+    let on_speak = {
+        let reply = reply.clone();
+        let speech_error = speech_error.clone();
+        Callback::from(move |_| {
+            let text = reply.content.clone();
+            let speech_error = speech_error.clone();
+            speech_error.set(String::new());
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(e) = speech::speak(&text).await {
+                    speech_error.set(e);
+                }
+            });
+        })
+    };
+
     html! {
         <div style="max-width: 800px; margin: 1rem auto; font-family: sans-serif;">
             <h1>{ "Rust AI Stack Demo UI" }</h1>
             <p>{ "This Yew/WASM UI talks to the Rust gateway at http://localhost:8080." }</p>
+            if !config.greeting.is_empty() {
+                <div class="message assistant" style="background:#e8f0fe; padding:0.5rem; border-radius:4px; margin-bottom:0.5rem;">
+                    { config.greeting.clone() }
+                </div>
+            }
+            { for history.iter().map(|message| html! {
+                <div
+                    class={classes!("message", message.role.clone())}
+                    style="background:#f7f7f7; padding:0.5rem; border-radius:4px; margin-bottom:0.5rem; white-space:pre-wrap;"
+                >
+                    <strong>{ format!("{}: ", message.role) }</strong>
+                    { message.content.clone() }
+                </div>
+            }) }
+            <label for="history-turns">{ "Turns sent per request: " }</label>
+            <input
+                id="history-turns"
+                type="number"
+                min="1"
+                value={window_turns.to_string()}
+                onchange={on_window_change}
+                style="width: 4rem; margin-bottom: 0.5rem;"
+            />
+            <br />
             <label for="prompt">{ "Prompt:" }</label>
             <textarea
                 id="prompt"
@@ -67,16 +163,42 @@ pub fn app() -> Html {
                 oninput={on_input_change}
             />
             <button onclick={on_send} style="margin-top: 0.5rem;">{ "Send to LLM" }</button>
-            <h2>{ "Raw response:" }</h2>
-            <pre style="background:#f0f0f0; padding:0.5rem; white-space:pre-wrap;">
+            <h2>{ "Response:" }</h2>
+            <pre
+                style="background:#f0f0f0; padding:0.5rem; white-space:pre-wrap;"
+                title={reply.id.clone()}
+            >
                 { (*output).clone() }
             </pre>
-            <p>{ "TTS endpoint (/v1/audio/speech) is wired but not used in this minimal UI yet." }</p>
+            <button onclick={on_speak} disabled={reply.content.trim().is_empty()}>
+                { "Speak" }
+            </button>
+            if !speech_error.is_empty() {
+                <p style="color:#b00020;">{ (*speech_error).clone() }</p>
+            }
         </div>
     }
 }
 
+/// True when no send happened yet or the previous one is older than the window.
+fn debounce_elapsed(last_send_ms: Option<f64>, now_ms: f64, window_ms: f64) -> bool {
+    last_send_ms.is_none_or(|last| now_ms - last >= window_ms)
+}
+
 #[wasm_bindgen(start)]
 pub fn run() {
     yew::Renderer::<App>::new().render();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce_window() {
+        assert!(debounce_elapsed(None, 1_000.0, 500.0));
+        assert!(!debounce_elapsed(Some(1_000.0), 1_499.0, 500.0));
+        assert!(debounce_elapsed(Some(1_000.0), 1_500.0, 500.0));
+        assert!(debounce_elapsed(Some(1_000.0), 2_000.0, 500.0));
+    }
+}
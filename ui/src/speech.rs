@@ -0,0 +1,66 @@
+//! Speaking assistant replies through the gateway's TTS endpoint.
+
+use gloo_net::http::Request;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobPropertyBag, HtmlAudioElement, Url};
+
+const TTS_URL: &str = "http://localhost:8080/v1/audio/speech";
+
+fn js_error(context: &str, err: JsValue) -> String {
+    format!(
+        "{context}: {}",
+        err.as_string().unwrap_or_else(|| format!("{err:?}"))
+    )
+}
+
+/// Synthesize `text` as WAV and play it. The object URL holding the audio is revoked
+/// once playback ends, or straight away if it cannot start.
+pub async fn speak(text: &str) -> Result<(), String> {
+    let body = serde_json::json!({ "input": text, "format": "wav" });
+    let resp = Request::post(TTS_URL)
+        .header("Content-Type", "application/json")
+        .json(&body)
+        .map_err(|e| format!("Failed to build TTS request: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("TTS request error: {e}"))?;
+    if !resp.ok() {
+        return Err(format!("TTS request failed with status {}", resp.status()));
+    }
+    let bytes = resp
+        .binary()
+        .await
+        .map_err(|e| format!("Failed to read TTS audio: {e}"))?;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes.as_slice()));
+    let options = BlobPropertyBag::new();
+    options.set_type("audio/wav");
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(|e| js_error("Failed to create audio blob", e))?;
+    let url = Url::create_object_url_with_blob(&blob)
+        .map_err(|e| js_error("Failed to create audio URL", e))?;
+
+    let played = play(&url).await;
+    if played.is_err() {
+        let _ = Url::revoke_object_url(&url);
+    }
+    played
+}
+
+async fn play(url: &str) -> Result<(), String> {
+    let audio =
+        HtmlAudioElement::new_with_src(url).map_err(|e| js_error("Failed to create audio", e))?;
+    let ended_url = url.to_string();
+    let revoke = Closure::once_into_js(move || {
+        let _ = Url::revoke_object_url(&ended_url);
+    });
+    audio.set_onended(Some(revoke.unchecked_ref()));
+    let playing = audio
+        .play()
+        .map_err(|e| js_error("Failed to play audio", e))?;
+    JsFuture::from(playing)
+        .await
+        .map(|_| ())
+        .map_err(|e| js_error("Failed to play audio", e))
+}